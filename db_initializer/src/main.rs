@@ -1,766 +1,1224 @@
 // use std::vec;
 
-use openssl::ssl::{SslConnector, SslMethod};
+use dmadms_core::fulfillment::{Inventory, Strategy};
+use dmadms_core::geocoding::GeocodeSummary;
+use dmadms_core::roles::Role;
+use dmadms_core::tenancy::Tenant;
+use dmadms_core::{
+    anonymize, archive, attributes, barcodes, categories, demo, documents, drift, error, export,
+    fulfillment, geocoding, history, import, lots, offerings, pricing, purchasing, roles, sales,
+    search, seed, tenancy, units, validation, Config,
+};
 use postgres::Client;
-use postgres_openssl::MakeTlsConnector;
 
 fn connect() -> Client {
-    let postgres_host = std::env::var("POSTGRES_HOST").unwrap();
-    let postgres_user = std::env::var("POSTGRES_USER").unwrap();
-    let postgres_password = std::env::var("POSTGRES_PASSWORD").unwrap();
-    let postgres_dbname = std::env::var("POSTGRES_DBNAME").unwrap();
+    let config = Config::from_env().expect("can't read connection parameters");
 
-    let ssl_connector = SslConnector::builder(SslMethod::tls()).unwrap().build();
-
-    let connector = MakeTlsConnector::new(ssl_connector);
-
-    let params = format!("host={postgres_host} user={postgres_user} password={postgres_password} dbname={postgres_dbname}");
-
-    Client::connect(&params, connector).expect("can't connect to postgresql database")
+    dmadms_core::connect(&config).expect("can't connect to postgresql database")
 }
 
-fn drop_tables(client: &mut Client) {
-    for table_name in [
-        "countries",
-        "suppliers",
-        "product_categories",
-        "product_subcategories",
-        "products",
-        "clients",
-        "client_addresses",
-        "product_requirements",
-        "warehouses",
-        "product_locations",
-        "user_roles",
-        "users",
-    ] {
-        _ = client.batch_execute(&format!("DROP TABLE {table_name} CASCADE"));
-    }
-}
+fn demo_queries(client: &mut Client) -> Result<(), postgres::Error> {
+    println!(
+        "
+Countries
+"
+    );
 
-fn create_tables(client: &mut Client) -> Result<(), postgres::Error> {
-    client.batch_execute("BEGIN TRANSACTION")?;
+    for country in demo::countries(client)? {
+        println!("{country}")
+    }
 
-    client.batch_execute(
+    println!(
         "
-        CREATE TABLE countries (
-            country_id  SERIAL PRIMARY KEY,
-            name        TEXT UNIQUE NOT NULL
-        )
-    ",
-    )?;
+Subcategories per each category
+Categories                     Subcategories
+"
+    );
 
-    client.batch_execute(
-        "
-        CREATE TABLE suppliers (
-            supplier_id SERIAL PRIMARY KEY,
-            country_id  INTEGER REFERENCES countries NOT NULL,
-            name        TEXT NOT NULL,
-            email       TEXT NOT NULL
-        )
-    ",
-    )?;
+    for (category, subcategory) in demo::subcategories(client, &[])? {
+        println!("{category:30} {subcategory}")
+    }
 
-    client.batch_execute(
+    println!(
         "
-        CREATE TABLE product_categories (
-            category_id SERIAL PRIMARY KEY,
-            name        TEXT UNIQUE NOT NULL
-        )
-    ",
-    )?;
+First 10 suppliers with name, email and country
+{:50} {:30} Country
+",
+        "Supplier", "Email"
+    );
 
-    client.batch_execute(
-        "
-        CREATE TABLE product_subcategories (
-            subcategory_id  SERIAL PRIMARY KEY,
-            category_id     INTEGER REFERENCES product_categories NOT NULL,
-            name            TEXT UNIQUE NOT NULL
-        )
-    ",
-    )?;
+    for (supplier, email, country) in demo::suppliers(client, 10)? {
+        println!("{supplier:50} {email:30} {country}")
+    }
 
-    client.batch_execute(
+    println!(
         "
-        CREATE TABLE products (
-            product_id      SERIAL PRIMARY KEY,
-            supplier_id     INTEGER REFERENCES suppliers NOT NULL,
-            subcategory_id  INTEGER REFERENCES product_subcategories NOT NULL,
-            name            TEXT UNIQUE NOT NULL
-        )
-    ",
-    )?;
+Groceries in subcategories that starts with M
+{:50} Product
+",
+        "Subcategory"
+    );
 
-    client.batch_execute(
-        "
-        CREATE TABLE clients (
-            client_id   SERIAL PRIMARY KEY,
-            name        TEXT UNIQUE NOT NULL,
-            email       TEXT NOT NULL
-        )
-    ",
-    )?;
+    for (subcategory, product) in demo::groceries(client, "M")? {
+        println!("{subcategory:50} {product}")
+    }
 
-    client.batch_execute(
-        "
-        CREATE TABLE client_addresses (
-            client_address_id   SERIAL PRIMARY KEY,
-            client_id           INTEGER REFERENCES clients NOT NULL,
-            address             TEXT NOT NULL
-        )
-    ",
-    )?;
+    println!();
+    println!("count of users: {}", demo::count_users(client)?);
 
-    client.batch_execute(
-        "
-        CREATE TABLE product_requirements (
-            product_requirement_id  SERIAL PRIMARY KEY,
-            product_id              INTEGER REFERENCES products NOT NULL,
-            client_address_id       INTEGER REFERENCES client_addresses NOT NULL,
-            count                   INTEGER NOT NULL,
-            CHECK (count >= 0)
-        )
-    ",
-    )?;
+    let deleted = demo::delete_supplier_users(client, "password")?;
 
-    client.batch_execute(
-        "
-        CREATE TABLE warehouses (
-            warehouse_id    SERIAL PRIMARY KEY,
-            address         TEXT UNIQUE NOT NULL
-        )
-        ",
-    )?;
+    println!("Deleted all supplier users with password 'password' (total {deleted})");
 
-    client.batch_execute(
-        "
-        CREATE TABLE product_locations (
-            product_location_id SERIAL PRIMARY KEY,
-            warehouse_id        INTEGER REFERENCES warehouses NOT NULL,
-            product_id          INTEGER REFERENCES products NOT NULL,
-            count               INTEGER NOT NULL,
-            CHECK (count >= 0)
-        )
-    ",
-    )?;
+    println!("count of users: {}", demo::count_users(client)?);
 
-    client.batch_execute(
-        "
-        CREATE TABLE user_roles (
-            user_role_id    SERIAL PRIMARY KEY,
-            name            TEXT UNIQUE NOT NULL
-        )
-    ",
-    )?;
+    println!();
+    println!("change product categories to lowercase");
 
-    client.batch_execute("CREATE EXTENSION IF NOT EXISTS pgcrypto")?;
+    demo::lowercase_categories(client)?;
 
-    client.batch_execute(
+    println!(
         "
-        CREATE TABLE users (
-            user_id             SERIAL PRIMARY KEY,
-            supplier_id         INTEGER REFERENCES suppliers UNIQUE, -- NULLABLE
-            client_id           INTEGER REFERENCES clients UNIQUE, -- NULLABLE
-            user_role_id        INTEGER REFERENCES user_roles NOT NULL,
-            name                TEXT UNIQUE NOT NULL,
-            password            TEXT NOT NULL, -- use encryption
-            CHECK ((supplier_id IS NULL) OR (client_id IS NULL))
-        )
-    ",
-    )?;
+Subcategories per each category except 'grocery' and 'healthy eating'
+Categories                     Subcategories
+"
+    );
 
-    client.batch_execute("COMMIT TRANSACTION")?;
+    for (category, subcategory) in demo::subcategories(client, &["grocery", "healthy eating"])? {
+        println!("{category:30} {subcategory}")
+    }
 
     Ok(())
 }
 
-fn create_indexes(client: &mut Client) -> Result<(), postgres::Error> {
-    client.batch_execute(
-        "
-        CREATE INDEX user_index
-        ON users(supplier_id, client_id)
-    ",
-    )?;
+fn plan(client: &mut Client, args: &[&str]) {
+    let strategy = match args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(name) => name.parse().unwrap_or_else(|error| usage(error)),
+        None => Strategy::Greedy,
+    };
 
-    client.batch_execute(
-        "
-        CREATE INDEX user_role_index
-        ON user_roles(name)
-    ",
-    )?;
+    let inventory = Inventory::load(client).expect("can't load inventory");
+    let plan = fulfillment::plan(&inventory, strategy);
 
-    Ok(())
-}
+    fulfillment::print_plan(&plan);
 
-fn init_tables(client: &mut Client) -> Result<(), postgres::Error> {
-    /* TODO LIST:
-     * client_addresses
-     * clients
-     * countries
-     * product_categories
-     * product_locations
-     * product_requirements
-     * product_subcategories
-     * products
-     * suppliers
-     * user
-     * user_roles
-     * warehouses
-     */
-
-    client.batch_execute("BEGIN TRANSACTION")?;
-
-    let countries = include!("init_data/countries");
-
-    for country in countries {
-        client.execute(
-            "INSERT INTO countries (name) VALUES ($1)",
-            &[&country.to_owned()],
-        )?;
-    }
-
-    let supplier_names = include!("init_data/suppliers").iter();
-    let emails = include!("init_data/emails").iter();
-
-    let mut suppliers = supplier_names.zip(emails);
-
-    let country_ids: Vec<i32> = client
-        .query("SELECT country_id FROM countries", &[])?
-        .iter()
-        .map(|row| row.get("country_id"))
-        .collect();
-
-    for country_id in country_ids.iter().cycle() {
-        if let Some(supplier) = suppliers.next() {
-            client.execute(
-                "
-                INSERT INTO suppliers (country_id, name, email)
-                VALUES ($1, $2, $3)
-                ",
-                &[&country_id, supplier.0, supplier.1],
-            )?;
-        } else {
-            break;
-        }
-    }
-
-    let client_names = include!("init_data/clients").iter();
-    let emails = include!("init_data/emails").iter();
-    let clients = client_names.zip(emails);
-
-    for my_client in clients {
-        client.execute(
-            "
-            INSERT INTO clients (name, email)
-            VALUES ($1, $2)
-            ",
-            &[my_client.0, my_client.1],
-        )?;
-    }
-
-    let product_categories = include!("init_data/product_categories");
-
-    for (category, subcategories) in product_categories {
-        if let &[ref row] = &client.query(
-            "
-            INSERT INTO product_categories (name)
-            VALUES ($1)
-            RETURNING (category_id)
-            ",
-            &[&category],
-        )?[..]
-        {
-            let category_id: i32 = row.get("category_id");
-
-            for subcategory in subcategories {
-                client.execute(
-                    "
-                    INSERT INTO product_subcategories (category_id, name)
-                    VALUES ($1, $2)
-                    ",
-                    &[&category_id, &subcategory],
-                )?;
+    if args.contains(&"--commit") {
+        match fulfillment::commit_plan(client, &plan) {
+            Ok(plan_id) => println!("committed as fulfillment plan {plan_id}"),
+            Err(error) => {
+                eprintln!("{error}");
+                std::process::exit(1);
             }
         }
     }
+}
 
-    let subcategory_ids: Vec<i32> = client
-        .query("SELECT subcategory_id FROM product_subcategories", &[])?
-        .iter()
-        .map(|row| row.get("subcategory_id"))
-        .collect();
+fn geocode(client: &mut Client, args: &[&str]) {
+    let places = match args {
+        [] => geocoding::read_gazetteer(seed::GAZETTEER.as_bytes()),
+        [path] => {
+            let file = std::fs::File::open(path).expect("can't open gazetteer");
+            geocoding::read_gazetteer(file)
+        }
+        _ => usage("geocode expects at most one gazetteer file".to_owned()),
+    };
+
+    let result = places.and_then(|places| {
+        let summary = geocoding::import_gazetteer(client, &places)?;
+        print_geocode_summary(&summary, places.len());
+        Ok(())
+    });
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
 
-    let supplier_ids: Vec<i32> = client
-        .query("SELECT supplier_id FROM suppliers", &[])?
-        .iter()
-        .map(|row| row.get("supplier_id"))
-        .collect();
+fn print_geocode_summary(summary: &GeocodeSummary, places: usize) {
+    println!(
+        "geocoded {} warehouses and {} client addresses from {places} gazetteer entries",
+        summary.warehouses, summary.client_addresses
+    );
 
-    let product_names = include!("init_data/products");
+    for address in &summary.unmatched {
+        println!("not found in gazetteer: {address}");
+    }
+}
+
+fn nearest(client: &mut Client, args: &[&str]) {
+    let (client_address_id, limit) = match args {
+        [id] => (id.parse().ok(), Some(5)),
+        [id, limit] => (id.parse().ok(), limit.parse().ok()),
+        _ => (None, None),
+    };
+
+    let (Some(client_address_id), Some(limit)) = (client_address_id, limit) else {
+        usage("nearest expects a client address id and an optional limit".to_owned())
+    };
 
-    let products = product_names.iter().zip(
-        subcategory_ids
-            .iter()
-            .cycle()
-            .zip(supplier_ids.iter().cycle()),
+    let warehouses = geocoding::nearest_warehouses(client, client_address_id, limit)
+        .expect("can't find nearest warehouses");
+
+    println!(
+        "
+Nearest warehouses to client address {client_address_id}
+{:10} {:40} Distance, km
+",
+        "Warehouse", "Address"
     );
 
-    for (product_name, (subcategory_id, supplier_id)) in products {
-        client.execute(
-            "
-            INSERT INTO products (supplier_id, subcategory_id, name)
-            VALUES ($1, $2, $3)
-            ",
-            &[&supplier_id, &subcategory_id, &product_name],
-        )?;
-    }
-
-    let addresses = include!("init_data/addresses");
-
-    for address in &addresses {
-        client.execute(
-            "
-        INSERT INTO warehouses (address)
-        VALUES ($1)
-        ",
-            &[address],
-        )?;
-    }
-
-    let client_ids: Vec<i32> = client
-        .query("SELECT client_id FROM clients", &[])?
-        .iter()
-        .map(|row| row.get("client_id"))
-        .collect();
-
-    let client_addresses = addresses.iter().zip(client_ids.iter().cycle());
-
-    for (address, client_id) in client_addresses {
-        client.execute(
-            "
-            INSERT INTO client_addresses (client_id, address)
-            VALUES ($1, $2)
-            ",
-            &[&client_id, address],
-        )?;
-    }
-
-    let client_address_ids: Vec<i32> = client
-        .query("SELECT client_address_id from client_addresses", &[])?
-        .iter()
-        .map(|row| row.get("client_address_id"))
-        .collect();
-
-    let product_ids: Vec<i32> = client
-        .query("SELECT product_id from products", &[])?
-        .iter()
-        .map(|row| row.get("product_id"))
-        .take(10)
-        .collect();
-
-    for client_address_id in client_address_ids {
-        for product_id in &product_ids {
-            let count = client_address_id
-                .wrapping_mul(73)
-                .wrapping_add(product_id.wrapping_add(42))
-                % 300;
-
-            if count != 0 {
-                client.execute(
-                    "
-                    INSERT INTO product_requirements (client_address_id, product_id, count)
-                    VALUES ($1, $2, $3)
-                    ",
-                    &[&client_address_id, &product_id, &count],
-                )?;
+    for warehouse in warehouses {
+        println!(
+            "{:<10} {:40} {:.1}",
+            warehouse.warehouse_id, warehouse.address, warehouse.distance_km
+        );
+    }
+}
+
+fn purchase_orders(client: &mut Client, args: &[&str]) {
+    let id = |arg: &str| {
+        arg.parse()
+            .unwrap_or_else(|_| usage(format!("'{arg}' is not an id")))
+    };
+
+    let result = match *args {
+        ["generate", ref rest @ ..] => {
+            let strategy = match rest {
+                [] => Strategy::Greedy,
+                [name] => name.parse().unwrap_or_else(|error| usage(error)),
+                _ => usage("po generate expects at most one strategy".to_owned()),
+            };
+
+            let inventory = Inventory::load(client).expect("can't load inventory");
+            let plan = fulfillment::plan(&inventory, strategy);
+
+            purchasing::generate(client, &plan.shortfalls).map(|ids| {
+                println!("drafted {} purchase orders", ids.len());
+            })
+        }
+        ["list"] => purchasing::list(client).map(|orders| {
+            purchasing::print_purchase_orders(&orders);
+        }),
+        ["send", order] => purchasing::send(client, id(order)),
+        ["confirm", order] => purchasing::confirm(client, id(order)),
+        ["receive", order, warehouse] => purchasing::receive(client, id(order), id(warehouse)),
+        _ => usage(format!("unknown command 'po {}'", args.join(" "))),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
+
+fn sales_orders(client: &mut Client, args: &[&str]) {
+    let id = |arg: &str| {
+        arg.parse()
+            .unwrap_or_else(|_| usage(format!("'{arg}' is not an id")))
+    };
+
+    let result = match *args {
+        ["create", address] => sales::create_from_requirements(client, id(address))
+            .map(|order| println!("created sales order {order}")),
+        ["list"] => sales::list(client).map(|orders| sales::print_sales_orders(&orders)),
+        ["confirm", order] => sales::confirm(client, id(order)),
+        ["ship", order, warehouse] => sales::ship(client, id(order), id(warehouse))
+            .map(|shipment| println!("shipped as shipment {shipment}")),
+        ["deliver", order] => sales::deliver(client, id(order)),
+        ["cancel", order] => sales::cancel(client, id(order)),
+        ["history", order] => sales::history(client, id(order)).map(|changes| {
+            for change in changes {
+                println!("{} {}", change.changed_at, change.status);
             }
+        }),
+        _ => usage(format!("unknown command 'orders {}'", args.join(" "))),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
+
+fn rates(client: &mut Client, args: &[&str]) {
+    let rates = match args {
+        [] => pricing::read_exchange_rates(seed::EXCHANGE_RATES.as_bytes()),
+        [path] => {
+            let file = std::fs::File::open(path).expect("can't open exchange rates");
+            pricing::read_exchange_rates(file)
         }
+        _ => usage("rates expects at most one exchange rates file".to_owned()),
+    };
+
+    let result = rates.and_then(|rates| {
+        pricing::import_exchange_rates(client, &rates)?;
+        println!("loaded {} exchange rates", rates.len());
+        Ok(())
+    });
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
     }
+}
 
-    let warehouse_ids: Vec<i32> = client
-        .query("SELECT warehouse_id from warehouses", &[])?
-        .iter()
-        .map(|row| row.get("warehouse_id"))
-        .collect();
+fn value(client: &mut Client, args: &[&str]) {
+    let (report, base) = match *args {
+        [report] => (report, pricing::DEFAULT_BASE_CURRENCY),
+        [report, base] => (report, base),
+        _ => usage("value expects a report and an optional currency".to_owned()),
+    };
 
-    for warehouse_id in warehouse_ids {
-        for product_id in &product_ids {
-            let count = warehouse_id
-                .wrapping_mul(73)
-                .wrapping_add(product_id.wrapping_add(42))
-                % 300;
+    let result = match report {
+        "stock" => pricing::stock_value(client, base).map(|values| {
+            println!(
+                "
+Stock value per warehouse, {base}
+{:10} {:40} {:>16} Unpriced
+",
+                "Warehouse", "Address", "Value"
+            );
+
+            for value in values {
+                println!(
+                    "{:<10} {:40} {:>16} {}",
+                    value.warehouse_id, value.address, value.value, value.unpriced
+                );
+            }
+        }),
+        "requirements" => pricing::requirements_value(client, base).map(|values| {
+            println!(
+                "
+Outstanding requirements value per client, {base}
+{:40} {:>16} Unpriced
+",
+                "Client", "Value"
+            );
 
-            if count != 0 {
-                client.execute(
-                    "
-                    INSERT INTO product_locations (warehouse_id, product_id, count)
-                    VALUES ($1, $2, $3)
-                    ",
-                    &[&warehouse_id, &product_id, &count],
-                )?;
+            for value in values {
+                println!("{:40} {:>16} {}", value.client, value.value, value.unpriced);
             }
+        }),
+        _ => usage(format!(
+            "unknown report '{report}', expected stock or requirements"
+        )),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
+
+fn lots(client: &mut Client, args: &[&str]) {
+    let number = |arg: &str| {
+        arg.parse()
+            .unwrap_or_else(|_| usage(format!("'{arg}' is not a number")))
+    };
+    let date = |arg: &str| {
+        arg.parse()
+            .unwrap_or_else(|_| usage(format!("'{arg}' is not a date like 2022-12-31")))
+    };
+
+    let result = match *args {
+        ["expiring"] | ["expiring", _] => {
+            let days = args.get(1).map(|days| number(days)).unwrap_or(30);
+
+            lots::expiring(client, days).map(|lots| {
+                println!(
+                    "
+Lots expiring within {days} days
+{:10} {:30} {:40} {:12} {:12} Count
+",
+                    "Warehouse", "Address", "Product", "Lot", "Expires"
+                );
+
+                for lot in lots {
+                    println!(
+                        "{:<10} {:30} {:40} {:12} {:12} {}",
+                        lot.warehouse_id,
+                        lot.warehouse,
+                        lot.product,
+                        lot.supplier_lot_number,
+                        lot.expiry_date.to_string(),
+                        lot.count
+                    );
+                }
+            })
         }
+        ["receive", warehouse, product, lot_number, production, expiry, count] => {
+            let lot = lots::Lot {
+                supplier_lot_number: lot_number.to_owned(),
+                production_date: date(production),
+                expiry_date: date(expiry),
+            };
+
+            lots::receive(
+                client,
+                number(warehouse),
+                number(product),
+                &lot,
+                number(count),
+            )
+            .map(|lot| println!("received into lot {lot}"))
+        }
+        _ => usage(format!("unknown command 'lots {}'", args.join(" "))),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
     }
+}
 
-    let user_roles = ["admin", "manager", "client", "supplier"];
+// Value of a `--name value` option
+fn option<'a>(args: &[&'a str], name: &str) -> Option<&'a str> {
+    args.iter().position(|arg| *arg == name).map(|index| {
+        args.get(index + 1)
+            .copied()
+            .unwrap_or_else(|| usage(format!("{name} expects a value")))
+    })
+}
+
+fn documents(client: &mut Client, args: &[&str]) {
+    let number = |arg: &str| {
+        arg.parse()
+            .unwrap_or_else(|_| usage(format!("'{arg}' is not a number")))
+    };
+    let date = |arg: &str| {
+        arg.parse()
+            .unwrap_or_else(|_| usage(format!("'{arg}' is not a date like 2022-12-31")))
+    };
+
+    let result = match *args {
+        ["add", document_type, document_number, valid_from, ref rest @ ..] => {
+            let document = documents::NewDocument {
+                document_type: document_type.to_owned(),
+                number: document_number.to_owned(),
+                valid_from: date(valid_from),
+                valid_to: option(rest, "--valid-to").map(date),
+                supplier_id: option(rest, "--supplier").map(number),
+                product_id: option(rest, "--product").map(number),
+                purchase_order_id: option(rest, "--po").map(number),
+            };
+            let file = option(rest, "--file").map(std::path::Path::new);
+
+            documents::add(client, &document, file, &documents::storage_dir())
+                .map(|document| println!("added document {document}"))
+        }
+        ["require", country, document_type] => documents::require(client, country, document_type),
+        ["missing"] | ["missing", _] => {
+            documents::missing(client, args.get(1).copied()).map(|missing| {
+                println!(
+                    "
+Products without a valid required document
+{:25} {:40} {:40} Document
+",
+                    "Country", "Supplier", "Product"
+                );
+
+                for document in missing {
+                    println!(
+                        "{:25} {:40} {:40} {}",
+                        document.country,
+                        document.supplier,
+                        document.product,
+                        document.document_type
+                    );
+                }
+            })
+        }
+        _ => usage(format!("unknown command 'documents {}'", args.join(" "))),
+    };
 
-    for user_role in user_roles {
-        client.execute(
-            "
-            INSERT INTO user_roles (name)
-            VALUES ($1)
-        ",
-            &[&user_role],
-        )?;
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
     }
+}
 
-    client.batch_execute(
-        "
-        INSERT INTO users (name, user_role_id, password) values (
-            'Gigachad', 
-            (
-                SELECT user_role_id
-                FROM user_roles
-                WHERE name = 'admin'
-            ),
-            crypt('adminadmin', gen_salt('md5'))
-    )
-    ",
-    )?;
-
-    let managers = [
-        ("Helmer", "array"),
-        ("Macey", "capacitor"),
-        ("Melvina", "interface"),
-        ("Priscilla", "driver"),
-        ("Mollie", "capacitor"),
-        ("Jaren", "driver"),
-        ("Addison", "port"),
-        ("Jerrold", "firewall"),
-    ];
-
-    let manager_role_id: i32 = client
-        .query_one(
-            "
-                SELECT user_role_id
-                FROM user_roles
-                WHERE name = 'manager'
-            ",
-            &[],
-        )?
-        .get("user_role_id");
-
-    for manager in managers {
-        client.execute(
-            "
-        INSERT INTO users (name, password, user_role_id)
-        VALUES ($1, 
-            crypt($2, gen_salt('md5')), $3)",
-            &[&manager.0, &manager.1, &manager_role_id],
-        )?;
-    }
-
-    let supplier_role_id: i32 = client
-        .query_one(
-            "
-                SELECT user_role_id
-                FROM user_roles
-                WHERE name = 'supplier'
-            ",
-            &[],
-        )?
-        .get("user_role_id");
-
-    let supplier_ids: Vec<i32> = client
-        .query("SELECT supplier_id FROM suppliers", &[])?
-        .iter()
-        .map(|row| row.get("supplier_id"))
-        .collect();
-
-    for supplier_id in supplier_ids {
-        client.execute(
-            "
-            INSERT INTO users (name, password, user_role_id, supplier_id)
-            VALUES (
-                (
-                    SELECT name
-                    FROM suppliers
-                    WHERE supplier_id = $1
-                ),
-                crypt('password', gen_salt('md5')),
-                $2,
-                $1
-            )
-            ",
-            &[&supplier_id, &supplier_role_id],
-        )?;
-    }
-
-    let client_role_id: i32 = client
-        .query_one(
-            "
-                SELECT user_role_id
-                FROM user_roles
-                WHERE name = 'client'
-            ",
-            &[],
-        )?
-        .get("user_role_id");
-
-    let client_ids: Vec<i32> = client
-        .query("SELECT client_id FROM clients", &[])?
-        .iter()
-        .map(|row| row.get("client_id"))
-        .collect();
-
-    for client_id in client_ids {
-        client.execute(
-            "
-            INSERT INTO users (name, password, user_role_id, client_id)
-            VALUES (
-                (
-                    SELECT name
-                    FROM clients
-                    WHERE client_id = $1
-                ),
-                crypt('password', gen_salt('md5')),
-                $2,
-                $1
-            )
-            ",
-            &[&client_id, &client_role_id],
-        )?;
+fn units(client: &mut Client, args: &[&str]) {
+    let number = |arg: &str| {
+        arg.parse()
+            .unwrap_or_else(|_| usage(format!("'{arg}' is not a number")))
+    };
+
+    let result = match *args {
+        ["list", product] => units::packaging(client, number(product)).map(|packaging| {
+            for level in packaging {
+                println!("{:10} {}", level.unit, level.factor);
+            }
+        }),
+        ["convert", product, count, from, to] => {
+            units::convert(client, number(product), number(count), from, to)
+                .map(|converted| println!("{count} {from} = {converted} {to}"))
+        }
+        ["require", address, product, count, unit] => units::require(
+            client,
+            number(address),
+            number(product),
+            number(count),
+            unit,
+        )
+        .map(|requirement| println!("added product requirement {requirement}")),
+        ["stock", warehouse] | ["stock", warehouse, _] => {
+            units::stock(client, number(warehouse), args.get(2).copied()).map(|stock| {
+                println!(
+                    "
+Stock of warehouse {warehouse}
+{:8} {:40} Count
+",
+                    "Id", "Product"
+                );
+
+                for line in stock {
+                    match (line.unit, line.factor) {
+                        (Some(unit), Some(factor)) => println!(
+                            "{:<8} {:40} {} {unit}",
+                            line.product_id,
+                            line.product,
+                            units::render(line.count as i64, factor)
+                        ),
+                        _ => println!(
+                            "{:<8} {:40} {} (base units)",
+                            line.product_id, line.product, line.count
+                        ),
+                    }
+                }
+            })
+        }
+        _ => usage(format!("unknown command 'units {}'", args.join(" "))),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
+
+fn products(client: &mut Client, args: &[&str]) {
+    let list = |arg: Option<&str>| -> Vec<String> {
+        arg.map(|arg| arg.split(',').map(|name| name.trim().to_owned()).collect())
+            .unwrap_or_default()
+    };
+
+    let result = match *args {
+        ["show", product] => {
+            let product = product
+                .parse()
+                .unwrap_or_else(|_| usage(format!("'{product}' is not a number")));
+
+            attributes::attributes(client, product).map(|attributes| {
+                println!("{}", attributes.product);
+
+                println!("\nAllergens");
+                for (allergen, presence) in attributes.allergens {
+                    println!("    {allergen:20} {presence}");
+                }
+
+                println!("\nDietary flags");
+                for flag in attributes.dietary_flags {
+                    println!("    {flag}");
+                }
+
+                if let Some(nutrition) = attributes.nutrition {
+                    println!("\nNutrition per 100g");
+                    println!("    {:20} {}", "energy, kcal", nutrition.energy_kcal);
+                    println!("    {:20} {}", "fat, g", nutrition.fat);
+                    println!("    {:20} {}", "  saturated, g", nutrition.saturated_fat);
+                    println!("    {:20} {}", "carbohydrates, g", nutrition.carbohydrates);
+                    println!("    {:20} {}", "  sugars, g", nutrition.sugars);
+                    println!("    {:20} {}", "protein, g", nutrition.protein);
+                    println!("    {:20} {}", "salt, g", nutrition.salt);
+                }
+
+                println!("\nIngredients\n    {}", attributes.ingredients.join(", "));
+            })
+        }
+        ["find", ref rest @ ..] => {
+            let filter = attributes::ProductFilter {
+                category: option(rest, "--category").map(str::to_owned),
+                without_allergens: list(option(rest, "--without")),
+                without_traces: rest.contains(&"--traces"),
+                dietary_flags: list(option(rest, "--with")),
+            };
+
+            attributes::find(client, &filter).map(|products| {
+                println!(
+                    "
+{:8} {:40} Category
+",
+                    "Id", "Product"
+                );
+
+                for product in products {
+                    println!(
+                        "{:<8} {:40} {}",
+                        product.product_id, product.name, product.category
+                    );
+                }
+            })
+        }
+        _ => usage(format!("unknown command 'products {}'", args.join(" "))),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
     }
+}
 
-    client.batch_execute("COMMIT TRANSACTION")?;
+fn categories(client: &mut Client, args: &[&str]) {
+    let result = match *args {
+        ["tree"] => categories::tree(client).map(|tree| {
+            for category in tree {
+                println!(
+                    "{:<6} {:indent$}{} ({})",
+                    category.category_id,
+                    "",
+                    category.name,
+                    category.products,
+                    indent = category.depth as usize * 4
+                );
+            }
+        }),
+        ["products", category] => categories::products(client, category).map(|products| {
+            println!(
+                "
+{:8} {:40} Category
+",
+                "Id", "Product"
+            );
+
+            for product in products {
+                println!(
+                    "{:<8} {:40} {}",
+                    product.product_id, product.name, product.path
+                );
+            }
+        }),
+        ["add", name] | ["add", name, _] => categories::add(client, args.get(2).copied(), name)
+            .map(|category| println!("added category {category}")),
+        ["rename", category, name] => categories::rename(client, category, name),
+        ["move", category] | ["move", category, _] => {
+            categories::move_to(client, category, args.get(2).copied())
+        }
+        ["merge", category, into] => categories::merge(client, category, into),
+        ["migrate"] => {
+            categories::migrate(client).map(|count| println!("migrated {count} categories"))
+        }
+        _ => usage(format!("unknown command 'categories {}'", args.join(" "))),
+    };
 
-    Ok(())
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
 }
 
-/*
-"
-    SELECT product_categories.name as category, product_subcategories.name as subcategory
-    FROM product_categories
-    CROSS JOIN product_subcategories
-"
-*/
+fn archive(client: &mut Client, command: &str, args: &[&str]) {
+    let entity = |arg: &str| arg.parse().unwrap_or_else(|error| usage(error));
+    let number = |arg: &str| {
+        arg.parse()
+            .unwrap_or_else(|_| usage(format!("'{arg}' is not a number")))
+    };
+
+    let result = match (command, args) {
+        ("delete", [name, id]) => {
+            let entity: archive::Entity = entity(name);
+            archive::delete(client, entity, number(id)).map(|_| println!("deleted {entity} {id}"))
+        }
+        ("restore", [name, id]) => {
+            let entity: archive::Entity = entity(name);
+            archive::restore(client, entity, number(id)).map(|_| println!("restored {entity} {id}"))
+        }
+        ("deleted", [name]) => archive::deleted(client, entity(name)).map(|rows| {
+            println!(
+                "
+{:8} {:50} Deleted at
+",
+                "Id", "Name"
+            );
 
-fn demo_queries(client: &mut Client) -> Result<(), postgres::Error> {
-    /* TODO LIST:
-     * client_addresses
-     * clients
-     * countries +
-     * product_categories +
-     * product_locations
-     * product_requirements
-     * product_subcategories +
-     * products
-     * suppliers
-     * user
-     * user_roles
-     * warehouses
-     */
-
-    // List of suppliers with name, email and country. +
-    // List of products for category or subcategory. +
-    // List of product requierments for each client address.
-    // List of awailable products per warehouse.
-    // List of countries, categories and subcategories.
-    // List of users.
-    // List of all addresses for client.
-
-    let countries: Vec<String> = client
-        .query(
-            "
-                SELECT name FROM countries
-            ",
-            &[],
-        )?
-        .iter()
-        .map(|row| row.get("name"))
-        .collect();
+            for row in rows {
+                println!("{:<8} {:50} {}", row.id, row.label, row.deleted_at);
+            }
+        }),
+        ("purge", []) | ("purge", [_]) => {
+            let retention_days = args
+                .first()
+                .map(|days| number(days))
+                .unwrap_or(archive::DEFAULT_RETENTION_DAYS);
+
+            archive::purge(client, retention_days).map(|summary| {
+                for (entity, archived, kept) in summary.tables {
+                    println!("{entity:10} archived {archived}, kept {kept} still referenced");
+                }
+            })
+        }
+        _ => usage(format!("unknown command '{command} {}'", args.join(" "))),
+    };
 
-    println!(
-        "
-Countries
-"
-    );
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
 
-    for country in countries {
-        println!("{country}")
+fn history(client: &mut Client, args: &[&str]) {
+    let table = |arg: &str| -> history::Table { arg.parse().unwrap_or_else(|error| usage(error)) };
+    let number = |arg: &str| {
+        arg.parse()
+            .unwrap_or_else(|_| usage(format!("'{arg}' is not a number")))
+    };
+    let timestamp = |arg: &str| {
+        history::parse_timestamp(arg)
+            .unwrap_or_else(|| usage(format!("'{arg}' is not a date or a timestamp")))
+    };
+
+    let result = match *args {
+        [name, id] => history::versions(client, table(name), number(id)).map(|versions| {
+            for version in versions {
+                history::print_version(&version);
+                println!();
+            }
+        }),
+        [name, id, "--as-of", at] if table(name) == history::Table::Product => {
+            history::product_as_of(client, number(id), timestamp(at)).map(|snapshot| {
+                history::print_version(&snapshot.product);
+                println!(
+                    "    {:25} {}",
+                    "category",
+                    snapshot.category.as_deref().unwrap_or("-")
+                );
+                println!("    {:25} {}", "suppliers", snapshot.suppliers.join(", "));
+            })
+        }
+        [name, id, "--as-of", at] => history::as_of(client, table(name), number(id), timestamp(at))
+            .map(|version| history::print_version(&version)),
+        _ => usage(format!("unknown command 'history {}'", args.join(" "))),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
     }
+}
 
-    let caterories: Vec<(String, String)> = client
-        .query(
-            "
-                SELECT product_categories.name as category, product_subcategories.name as subcategory
-                FROM product_categories
-                INNER JOIN product_subcategories 
-                ON product_categories.category_id = product_subcategories.category_id;
-            ",
-            &[],
-        )?
-        .iter()
-        .map(|row| (row.get("category"), row.get("subcategory")))
-        .collect();
+fn schema(client: &mut Client, args: &[&str]) {
+    let result = match *args {
+        ["diff"] | ["diff", "--sql"] => drift::diff(client).map(|drifts| {
+            if drifts.is_empty() {
+                println!("no drift, the database matches the expected schema");
+                return;
+            }
 
-    println!(
-        "
-Subcategories per each category
-Categories                     Subcategories
-"
-    );
+            for drift in &drifts {
+                println!("{drift}");
+            }
 
-    for (category, subcategory) in caterories {
-        println!("{category:30} {subcategory}")
+            if args.contains(&"--sql") {
+                println!();
+                for statement in drift::converge_sql(&drifts) {
+                    println!("{statement}");
+                }
+            }
+
+            std::process::exit(1);
+        }),
+        _ => usage(format!("unknown command 'schema {}'", args.join(" "))),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
     }
+}
 
-    let suppliers: Vec<(String, String, String)> = client
-        .query(
-            "
-                SELECT suppliers.name as supplier, email, countries.name as country
-                FROM suppliers
-                INNER JOIN countries
-                ON suppliers.country_id = countries.country_id
-                LIMIT 10
-            ",
-            &[],
-        )?
-        .iter()
-        .map(|row| (row.get("supplier"), row.get("email"), row.get("country")))
-        .collect();
+fn grant_tenant(client: &mut Client, role: &str, user: &str) {
+    let result = tenancy::tenant(client, user).and_then(|tenant| match tenant {
+        Some(tenant) => tenancy::grant(client, role, tenant).map(|_| tenant),
+        None => Err(error::Error::Invalid(format!(
+            "user '{user}' is staff, not of a client or supplier"
+        ))),
+    });
+
+    match result {
+        Ok(Tenant::Client(client_id)) => println!("{role} sees the rows of client {client_id}"),
+        Ok(Tenant::Supplier(supplier_id)) => {
+            println!("{role} sees the rows of supplier {supplier_id}")
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
 
-    println!(
-        "
-First 10 suppliers with name, email and country
-{:50} {:30} {}
-",
-        "Supplier", "Email", "Country"
-    );
+fn validation(client: &mut Client, args: &[&str]) {
+    let result = match *args {
+        ["report"] => validation::violations(client).map(|violations| {
+            if violations.is_empty() {
+                println!("no violations, names and emails of suppliers and clients are valid");
+                return;
+            }
 
-    for (supplier, email, country) in suppliers {
-        println!("{supplier:50} {email:30} {country}")
+            for violation in &violations {
+                println!("{violation}");
+            }
+            println!("{} violations", violations.len());
+
+            std::process::exit(1);
+        }),
+        ["migrate"] => validation::migrate(client).map(|migration| {
+            for violation in &migration.violations {
+                println!("{violation}");
+            }
+
+            if !migration.violations.is_empty() {
+                println!(
+                    "{} violations, nothing migrated, fix the rows and run it again",
+                    migration.violations.len()
+                );
+                std::process::exit(1);
+            } else if migration.migrated {
+                println!(
+                    "normalized {} emails and added the constraints",
+                    migration.normalized
+                );
+            } else {
+                println!("the constraints are there already");
+            }
+        }),
+        _ => usage(format!("unknown command 'validation {}'", args.join(" "))),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
     }
+}
 
-    let groceries: Vec<(String, String)> = client
-        .query(
-            "
-            SELECT subcategory, products.name as product
-            FROM 
-            (   
-                SELECT product_subcategories.name as subcategory, subcategory_id 
-                FROM product_subcategories
-                WHERE category_id = (
-                    SELECT category_id from product_categories
-                    WHERE name = 'Grocery'
-                )
-            ) as grocery_subcategories
-            INNER JOIN products
-            ON grocery_subcategories.subcategory_id = products.subcategory_id
-            WHERE subcategory LIKE 'M%'
-            ORDER by subcategory
-        ",
-            &[],
-        )?
-        .iter()
-        .map(|row| (row.get("subcategory"), row.get("product")))
-        .collect();
+fn search(client: &mut Client, args: &[&str]) {
+    let result = match *args {
+        ["refresh-index"] => search::refresh(client),
+        [text, ref rest @ ..] if !text.starts_with("--") => {
+            let query = search::Query {
+                text: text.to_owned(),
+                category: option(rest, "--category").map(str::to_owned),
+                country: option(rest, "--country").map(str::to_owned),
+                in_stock: rest.contains(&"--in-stock") || option(rest, "--warehouse").is_some(),
+                warehouse_id: option(rest, "--warehouse").map(|warehouse| {
+                    warehouse
+                        .parse()
+                        .unwrap_or_else(|_| usage(format!("'{warehouse}' is not a number")))
+                }),
+                limit: option(rest, "--limit")
+                    .map(|limit| {
+                        limit
+                            .parse()
+                            .unwrap_or_else(|_| usage(format!("'{limit}' is not a number")))
+                    })
+                    .unwrap_or(search::DEFAULT_LIMIT),
+            };
+
+            search::search(client, &query).map(|results| search::print_results(&results))
+        }
+        _ => usage(format!("unknown command 'search {}'", args.join(" "))),
+    };
 
-    println!(
-        "
-Groceries in subcategories that starts with M
-{:50} {}
-",
-        "Subcategory", "Product"
-    );
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
 
-    for (subcategory, product) in groceries {
-        println!("{subcategory:50} {product}")
+fn offerings(client: &mut Client, args: &[&str]) {
+    let number = |arg: &str| {
+        arg.parse()
+            .unwrap_or_else(|_| usage(format!("'{arg}' is not a number")))
+    };
+
+    let result = match *args {
+        ["product", product] => offerings::suppliers_of(client, number(product))
+            .map(|offerings| offerings::print_offerings(&offerings)),
+        ["supplier", supplier] => offerings::products_of(client, number(supplier))
+            .map(|offerings| offerings::print_offerings(&offerings)),
+        ["add", supplier, product, sku, lead_time]
+        | ["add", supplier, product, sku, lead_time, _] => {
+            let offering = offerings::NewOffering {
+                supplier_id: number(supplier),
+                product_id: number(product),
+                sku: sku.to_owned(),
+                lead_time_days: number(lead_time),
+                min_order_quantity: args.get(5).copied().map(number).unwrap_or(1),
+            };
+
+            offerings::add(client, &offering)
+                .map(|offering| println!("added supplier offering {offering}"))
+        }
+        ["activate", supplier, product] => {
+            offerings::set_active(client, number(supplier), number(product), true)
+        }
+        ["deactivate", supplier, product] => {
+            offerings::set_active(client, number(supplier), number(product), false)
+        }
+        _ => usage(format!("unknown command 'offerings {}'", args.join(" "))),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
     }
+}
 
-    let count: i64 = client.query(
-        "
-            SELECT COUNT(1)
-            FROM users
-        ",
-        &[],
-    )?[0]
-        .get(0);
+fn barcodes(client: &mut Client, args: &[&str]) {
+    let result = match *args {
+        ["add", product, unit, gtin] => {
+            let product = product
+                .parse()
+                .unwrap_or_else(|_| usage(format!("'{product}' is not a number")));
 
-    println!();
-    println!("count of users: {count}");
+            barcodes::add(client, product, unit, gtin).map(|_| println!("added GTIN {gtin}"))
+        }
+        ["lookup", code] => barcodes::lookup(client, code).map(|scanned| {
+            println!(
+                "{} {} ({} of {} each) GTIN {}",
+                scanned.product_id, scanned.product, scanned.unit, scanned.factor, scanned.gtin
+            )
+        }),
+        ["import"] | ["import", _] => {
+            let barcodes = match args.get(1) {
+                None => barcodes::read_barcodes(seed::BARCODES.as_bytes()),
+                Some(path) => std::fs::File::open(path)
+                    .map_err(error::Error::from)
+                    .and_then(barcodes::read_barcodes),
+            };
+
+            barcodes.and_then(|barcodes| {
+                barcodes::import(client, &barcodes)?;
+                println!("imported {} barcodes", barcodes.len());
+                Ok(())
+            })
+        }
+        _ => usage(format!("unknown command 'barcodes {}'", args.join(" "))),
+    };
 
-    let deleted = client.execute(
-        "
-        DELETE FROM users
-        WHERE password = crypt('password', password) AND supplier_id IS NOT NULL
-    ",
-        &[],
-    )?;
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
 
-    println!("Deleted all supplier users with password 'password' (total {deleted})");
+// Least privileged role a command needs. Commands creating or migrating the
+// schema keep the role of the connection, they create the roles too.
+fn role(args: &[&str]) -> Option<Role> {
+    match args {
+        []
+        | ["init"]
+        | ["schema", ..]
+        | ["categories", "migrate"]
+        | ["anonymize"]
+        | ["validation", "migrate"]
+        | ["tenants", "grant", ..] => None,
+        ["history", ..] | ["deleted", ..] | ["orders", "history", ..] => Some(Role::Auditor),
+        ["plan", rest @ ..] if !rest.contains(&"--commit") => Some(Role::Reporting),
+        ["nearest", ..]
+        | ["value", ..]
+        | ["po", "list"]
+        | ["orders", "list"]
+        | ["lots", "expiring", ..]
+        | ["documents", "missing", ..]
+        | ["units", "list" | "convert" | "stock", ..]
+        | ["products", ..]
+        | ["categories", "tree" | "products", ..]
+        | ["offerings", "product" | "supplier", ..]
+        | ["barcodes", "lookup", ..]
+        | ["export", ..]
+        | ["validation", "report"] => Some(Role::Reporting),
+        ["search", text, ..] if *text != "refresh-index" => Some(Role::Reporting),
+        _ => Some(Role::App),
+    }
+}
 
-    let count: i64 = client.query(
-        "
-            SELECT COUNT(1)
-            FROM users
-        ",
-        &[],
-    )?[0]
-        .get(0);
+fn export(client: &mut Client, args: &[&str]) {
+    let (dir, rest) = match args {
+        [dir, rest @ ..] if !dir.starts_with("--") => (std::path::Path::new(dir), rest),
+        _ => usage("export expects a directory".to_owned()),
+    };
+
+    let options = export::Options {
+        format: option(rest, "--format")
+            .map(|format| format.parse().unwrap_or_else(|error| usage(error)))
+            .unwrap_or(export::Format::Csv),
+        filter: export::Filter {
+            country: option(rest, "--country").map(str::to_owned),
+            client_id: option(rest, "--client").map(|client| {
+                client
+                    .parse()
+                    .unwrap_or_else(|_| usage(format!("'{client}' is not a number")))
+            }),
+        },
+        anonymize: option(rest, "--anonymize")
+            .map(|list| {
+                list.split(',')
+                    .map(|name| name.trim().parse().unwrap_or_else(|error| usage(error)))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+
+    match export::export(client, dir, &options) {
+        Ok(manifest) => {
+            for table in &manifest.tables {
+                println!("{:30} {:>8} rows", table.file, table.rows);
+            }
+            println!(
+                "exported {} tables of schema version {} into {}",
+                manifest.tables.len(),
+                manifest.schema_version,
+                dir.display()
+            );
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
 
-    println!("count of users: {count}");
+fn anonymize(client: &mut Client) {
+    match anonymize::anonymize(client) {
+        Ok(summary) => {
+            for (table, column, rows) in &summary.columns {
+                println!("{:34} {rows:>8} rows", format!("{table}.{column}"));
+            }
+            println!("replaced {} values with fake ones", summary.values);
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
 
-    println!();
-    println!("change product categories to lowercase");
+fn import(client: &mut Client, args: &[&str]) {
+    let (entity, path) = match args {
+        [entity, path] | [entity, path, "--dry-run"] => (
+            entity
+                .parse::<import::Entity>()
+                .unwrap_or_else(|error| usage(error)),
+            std::path::Path::new(path),
+        ),
+        _ => usage("import expects an entity and a file".to_owned()),
+    };
+    let dry_run = args.contains(&"--dry-run");
+
+    let report =
+        import::read(path).and_then(|records| import::import(client, entity, &records, dry_run));
+
+    match report {
+        Ok(report) => {
+            for error in &report.errors {
+                println!("{error}");
+            }
 
-    client.batch_execute(
-        "
-        UPDATE product_categories
-        SET name = lower(name);
-        UPDATE product_subcategories
-        SET name = lower(name);
-    ",
-    )?;
-
-    let caterories: Vec<(String, String)> = client
-        .query(
-            "
-                SELECT product_categories.name as category, product_subcategories.name as subcategory
-                FROM product_categories
-                INNER JOIN product_subcategories 
-                ON product_categories.category_id = product_subcategories.category_id
-                WHERE product_categories.name NOT IN ('grocery', 'healthy eating')
-            ",
-            &[],
-        )?
-        .iter()
-        .map(|row| (row.get("category"), row.get("subcategory")))
-        .collect();
+            let counts = format!(
+                "{} new and {} updated {entity}",
+                report.inserted, report.updated
+            );
 
-    println!(
+            match (report.errors.len(), report.committed) {
+                (0, true) => println!("imported {counts}"),
+                (0, false) => println!("dry run, would import {counts}"),
+                (failed, _) => println!("{failed} rows failed, nothing imported of {counts}"),
+            }
+
+            if !report.errors.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn usage(error: String) -> ! {
+    eprintln!("{error}");
+    eprintln!(
         "
-Subcategories per each category except 'grocery' and 'healthy eating'
-Categories                     Subcategories
+usage: db_initializer [--as <user>] [command]
+
+options:
+    --as <user>                                         run the command as a client or supplier user, seeing only its rows
+
+Commands run as the least privileged database role they need: reading ones and
+export as dmadms_reporting, history and deleted rows as dmadms_auditor, others as dmadms_app.
+init, schema, categories migrate, anonymize, validation migrate and tenants grant keep the role of
+the connection.
+
+commands:
+    init (default)                                      recreate, fill and query the database
+    plan [greedy|fair-share|min-warehouses|nearest] [--commit]
+                                                        propose allocations of stock to requirements
+    geocode [gazetteer.csv]                             fill coordinates of warehouses and client addresses
+    nearest <client_address_id> [limit]                 list warehouses nearest to a client address
+    po generate [strategy]                              draft purchase orders for shortfalls of a plan
+    po list                                             list purchase orders
+    po send|confirm <purchase_order_id>                 move a purchase order through its lifecycle
+    po receive <purchase_order_id> <warehouse_id>       put received products into a warehouse
+    orders create <client_address_id>                   order outstanding requirements of an address
+    orders list                                         list sales orders
+    orders confirm|deliver|cancel <sales_order_id>      move a sales order through its lifecycle
+    orders ship <sales_order_id> <warehouse_id>         ship a sales order from a warehouse
+    orders history <sales_order_id>                     show status history of a sales order
+    rates [exchange_rates.csv]                          load currencies and exchange rates
+    value stock|requirements [currency]                 value stock or outstanding requirements
+    lots expiring [days]                                list lots expiring within days, 30 by default
+    lots receive <warehouse_id> <product_id> <lot_number> <production_date> <expiry_date> <count>
+                                                        put a lot of product into a warehouse
+    documents add <type> <number> <valid_from> [--valid-to date] [--supplier id] [--product id] [--po id] [--file path]
+                                                        register a document, its file is copied to DOCUMENTS_DIR
+    documents require <country> <type>                  require a document type for products from a country
+    documents missing [country]                         list products without a valid required document
+    units list <product_id>                             list packaging levels of a product
+    units convert <product_id> <count> <from> <to>      convert a count between units of a product
+    units require <client_address_id> <product_id> <count> <unit>
+                                                        add a requirement stated in a unit
+    units stock <warehouse_id> [unit]                   show stock of a warehouse in a unit
+    products show <product_id>                          show allergens, dietary flags, nutrition and ingredients
+    products find [--category id|path] [--without allergen,..] [--traces] [--with flag,..]
+                                                        find products by category, allergens and diet
+    categories tree                                     show the category tree with product counts
+    categories products <category>                      list products of a category and everything below it
+    categories add <name> [parent]                      add a category, a root one without parent
+    categories rename <category> <name>                 rename a category
+    categories move <category> [parent]                 move a category with its subtree, to the roots without parent
+    categories merge <category> <into>                  move products and subcategories into another category and remove it
+    categories migrate                                  convert former product_categories and product_subcategories into the tree
+    delete|restore user|supplier|client|product|warehouse <id>
+                                                        soft delete a row or bring it back, users go with their supplier or client
+    deleted user|supplier|client|product|warehouse      list soft deleted rows
+    purge [retention_days]                              archive rows deleted before the retention period, 365 days by default
+    history product|supplier|offering|client|address|category <id>
+                                                        list every version of a row with its validity
+    history product|supplier|offering|client|address|category <id> --as-of <date|timestamp>
+                                                        show a row as it was at a moment, products with their category and suppliers
+    schema diff [--sql]                                 report drift of tables, columns, constraints and indexes from the expected schema,
+                                                        with --sql also statements to converge, exits with 1 on drift
+    search <words> [--category id|path] [--country name] [--in-stock] [--warehouse id] [--limit n]
+                                                        rank products by names of product, category and suppliers
+    search refresh-index                                rebuild the search index after products, categories or suppliers change
+    offerings product <product_id>                      list suppliers offering a product, the preferred one first
+    offerings supplier <supplier_id>                    list products available from a supplier
+    offerings add <supplier_id> <product_id> <sku> <lead_time_days> [min_order_quantity]
+                                                        let a supplier offer a product
+    offerings activate|deactivate <supplier_id> <product_id>
+                                                        start or stop buying a product from a supplier
+    barcodes add <product_id> <unit> <gtin>             assign a GTIN to a packaging level of a product
+    barcodes lookup <gtin>                              find the product and packaging level of a scanned code
+    barcodes import [barcodes.csv]                      import GTINs (gtin,product,unit) by product name, bundled ones by default
+    export <dir> [--format csv|jsonl|parquet] [--country name] [--client id] [--anonymize emails,names,addresses]
+                                                        write every table and a manifest with row counts and checksums into a directory
+    import suppliers|products|addresses|requirements|stock <file.csv|file.xlsx> [--dry-run]
+                                                        upsert rows of a file by names, all of them or none, listing every failed row
+    anonymize                                           replace emails, user names and addresses, history and archive included,
+                                                        with consistent fake ones, e.g. in a copy of production data
+    validation report                                   list suppliers and clients with invalid, unnormalized or shared emails
+                                                        or names too long, exits with 1 on violations
+    validation migrate                                  normalize emails and add their constraints to an existing database,
+                                                        nothing when rows would still violate them
+    tenants grant <role> <user>                         let a database role, e.g. a login for direct SQL access, see only
+                                                        the rows of the client or supplier of a user
 "
     );
-    for (category, subcategory) in caterories {
-        println!("{category:30} {subcategory}")
-    }
+    std::process::exit(2)
+}
 
-    Ok(())
+fn assume(client: &mut Client, args: &[&str]) {
+    if let Some(role) = role(args) {
+        if let Err(error) = roles::assume(client, role) {
+            eprintln!(
+                "can't switch to role {}, run init first: {error}",
+                role.name()
+            );
+            std::process::exit(1);
+        }
+    }
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
     let mut client = connect();
 
-    drop_tables(&mut client);
+    let args = match args[..] {
+        ["--as", user, ref rest @ ..] => {
+            let tenant = tenancy::tenant(&mut client, user).unwrap_or_else(|error| {
+                eprintln!("{error}");
+                std::process::exit(1)
+            });
+
+            match tenant {
+                Some(tenant) => {
+                    tenancy::enter(&mut client, tenant).expect("can't switch to the tenant role")
+                }
+                None => assume(&mut client, rest),
+            }
+
+            rest.to_vec()
+        }
+        ["--as"] => usage("--as needs a user name".to_owned()),
+        _ => {
+            assume(&mut client, &args);
+            args
+        }
+    };
+
+    match args[..] {
+        [] | ["init"] => {
+            seed::reset(&mut client).expect("can't create tables");
 
-    create_tables(&mut client).expect("can't create tables");
+            let summary = seed::seed(&mut client).expect("can't init tables");
 
-    create_indexes(&mut client).expect("can't create indexes");
+            print_geocode_summary(&summary.geocoded, summary.gazetteer_places);
+            println!("loaded {} exchange rates", summary.exchange_rates);
+            println!("imported {} barcodes", summary.barcodes);
 
-    init_tables(&mut client).expect("can't init tables");
+            demo_queries(&mut client).expect("can't show demo queries");
 
-    demo_queries(&mut client).expect("can't show demo queries");
+            search::refresh(&mut client).expect("can't build search index");
+        }
+        ["plan", ref rest @ ..] => plan(&mut client, rest),
+        ["geocode", ref rest @ ..] => geocode(&mut client, rest),
+        ["nearest", ref rest @ ..] => nearest(&mut client, rest),
+        ["po", ref rest @ ..] => purchase_orders(&mut client, rest),
+        ["orders", ref rest @ ..] => sales_orders(&mut client, rest),
+        ["rates", ref rest @ ..] => rates(&mut client, rest),
+        ["value", ref rest @ ..] => value(&mut client, rest),
+        ["lots", ref rest @ ..] => lots(&mut client, rest),
+        ["documents", ref rest @ ..] => documents(&mut client, rest),
+        ["units", ref rest @ ..] => units(&mut client, rest),
+        ["products", ref rest @ ..] => products(&mut client, rest),
+        ["barcodes", ref rest @ ..] => barcodes(&mut client, rest),
+        ["categories", ref rest @ ..] => categories(&mut client, rest),
+        ["offerings", ref rest @ ..] => offerings(&mut client, rest),
+        ["schema", ref rest @ ..] => schema(&mut client, rest),
+        ["search", ref rest @ ..] => search(&mut client, rest),
+        ["delete", ref rest @ ..] => archive(&mut client, "delete", rest),
+        ["restore", ref rest @ ..] => archive(&mut client, "restore", rest),
+        ["deleted", ref rest @ ..] => archive(&mut client, "deleted", rest),
+        ["purge", ref rest @ ..] => archive(&mut client, "purge", rest),
+        ["history", ref rest @ ..] => history(&mut client, rest),
+        ["export", ref rest @ ..] => export(&mut client, rest),
+        ["import", ref rest @ ..] => import(&mut client, rest),
+        ["anonymize"] => anonymize(&mut client),
+        ["validation", ref rest @ ..] => validation(&mut client, rest),
+        ["tenants", "grant", role, user] => grant_tenant(&mut client, role, user),
+        _ => usage(format!("unknown command '{}'", args.join(" "))),
+    }
 }
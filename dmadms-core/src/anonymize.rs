@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use postgres::{Client, Transaction};
+
+use crate::archive;
+use crate::error::Error;
+use crate::geocoding;
+use crate::history;
+use crate::roles::Role;
+use crate::seed;
+use crate::validation;
+
+// Pools the fake values are drawn from, the ones the demo data is seeded with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pool {
+    Emails,
+    Names,
+    Addresses,
+}
+
+impl Pool {
+    const ALL: [Pool; 3] = [Pool::Emails, Pool::Names, Pool::Addresses];
+
+    fn name(self) -> &'static str {
+        match self {
+            Pool::Emails => "emails",
+            Pool::Names => "names",
+            Pool::Addresses => "addresses",
+        }
+    }
+}
+
+// Columns rewritten, together with their copies in history and archive tables
+const COLUMNS: [(Pool, &str, &str); 5] = [
+    (Pool::Emails, "suppliers", "email"),
+    (Pool::Emails, "clients", "email"),
+    (Pool::Names, "users", "name"),
+    (Pool::Addresses, "client_addresses", "address"),
+    (Pool::Addresses, "warehouses", "address"),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct AnonymizeSummary {
+    // (table, column, rewritten rows)
+    pub columns: Vec<(String, &'static str, u64)>,
+    // distinct values replaced by fake ones
+    pub values: usize,
+}
+
+fn copies(table: &str) -> Vec<String> {
+    let mut tables = vec![table.to_owned()];
+
+    if history::Table::ALL.iter().any(|copy| copy.table() == table) {
+        tables.push(format!("{table}_history"));
+    }
+    if archive::tables().contains(&table) {
+        tables.push(format!("{table}_archive"));
+    }
+
+    tables
+}
+
+// The n-th fake value of a pool. Once the pool runs out its values come again
+// with the round, so that distinct values stay distinct.
+fn fake(pool: Pool, values: &[&str], n: usize) -> String {
+    let value = values[n % values.len()];
+    let round = n / values.len();
+
+    match pool {
+        Pool::Emails => match value.split_once('@') {
+            Some((local, domain)) if round > 0 => {
+                validation::normalize_email(&format!("{local}+{round}@{domain}"))
+            }
+            _ => validation::normalize_email(value),
+        },
+        // user names are the local parts of the emails
+        Pool::Names => {
+            let local = value.split('@').next().unwrap_or(value);
+
+            match round {
+                0 => local.to_owned(),
+                _ => format!("{local}-{round}"),
+            }
+        }
+        Pool::Addresses => match round {
+            0 => value.to_owned(),
+            _ => format!("{value}, unit {round}"),
+        },
+    }
+}
+
+// Maps every distinct value of the columns of a pool, in all their tables, to a
+// fake one. Values are taken in random order, so the mapping can't be told from
+// the order of the originals.
+fn map_values(
+    transaction: &mut Transaction,
+    pool: Pool,
+    values: &[&str],
+    gazetteer: &HashMap<String, geocoding::Place>,
+    token: &str,
+) -> Result<usize, Error> {
+    let selects: Vec<String> = COLUMNS
+        .iter()
+        .filter(|(column_pool, _, _)| *column_pool == pool)
+        .flat_map(|(_, table, column)| {
+            copies(table)
+                .into_iter()
+                .map(move |table| format!("SELECT {column} as value FROM {table}"))
+        })
+        .collect();
+
+    let originals: Vec<String> = transaction
+        .query(
+            &format!(
+                "
+                SELECT value
+                FROM ({}) as originals
+                WHERE value IS NOT NULL
+                GROUP BY value
+                ORDER BY random()
+                ",
+                selects.join(" UNION ALL ")
+            ),
+            &[],
+        )?
+        .iter()
+        .map(|row| row.get("value"))
+        .collect();
+
+    let fakes: Vec<String> = (0..originals.len())
+        .map(|n| fake(pool, values, n))
+        .collect();
+    // valid emails too, in a domain reserved for invalid ones
+    let placeholders: Vec<String> = (0..originals.len())
+        .map(|n| format!("{n}.{token}@anonymizing.invalid"))
+        .collect();
+
+    // fake addresses are placed where the gazetteer has the address of the pool
+    let places: Vec<Option<&geocoding::Place>> = (0..originals.len())
+        .map(|n| match pool {
+            Pool::Addresses => gazetteer.get(&geocoding::normalize(values[n % values.len()])),
+            _ => None,
+        })
+        .collect();
+    let latitudes: Vec<Option<f64>> = places
+        .iter()
+        .map(|place| place.map(|p| p.latitude))
+        .collect();
+    let longitudes: Vec<Option<f64>> = places
+        .iter()
+        .map(|place| place.map(|p| p.longitude))
+        .collect();
+
+    transaction.execute(
+        "
+        INSERT INTO anonymized (pool, original, placeholder, fake, latitude, longitude)
+        SELECT $1, original, placeholder, fake, latitude, longitude
+        FROM unnest($2::TEXT[], $3::TEXT[], $4::TEXT[], $5::FLOAT8[], $6::FLOAT8[])
+            as values (original, placeholder, fake, latitude, longitude)
+        ",
+        &[
+            &pool.name(),
+            &originals,
+            &placeholders,
+            &fakes,
+            &latitudes,
+            &longitudes,
+        ],
+    )?;
+
+    Ok(originals.len())
+}
+
+// Unique indexes are checked row by row, so a fake value may still be the
+// original of a row not rewritten yet. Rows of the table itself get a
+// placeholder first, replaced once all of them are rewritten.
+fn rewrite(
+    transaction: &mut Transaction,
+    pool: Pool,
+    table: &str,
+    column: &str,
+    placeholder: bool,
+) -> Result<u64, Error> {
+    let value = if placeholder { "placeholder" } else { "fake" };
+    let coordinates = match pool {
+        // rows without coordinates stay without them
+        Pool::Addresses => format!(
+            ",
+            latitude = CASE WHEN {table}.latitude IS NULL THEN NULL ELSE anonymized.latitude END,
+            longitude = CASE WHEN {table}.latitude IS NULL THEN NULL ELSE anonymized.longitude END
+            "
+        ),
+        _ => String::new(),
+    };
+
+    Ok(transaction.execute(
+        &format!(
+            "
+            UPDATE {table}
+            SET {column} = anonymized.{value}{coordinates}
+            FROM anonymized
+            WHERE anonymized.pool = $1 AND {table}.{column} = anonymized.original
+            "
+        ),
+        &[&pool.name()],
+    )?)
+}
+
+// Rewrites emails of suppliers and clients, names of users and addresses of
+// clients and warehouses, in their history and archive rows as well, with fake
+// values from the seeding pools. The same value gets the same fake one
+// everywhere and distinct values distinct ones, so references, unique indexes
+// and how often values repeat are kept. History triggers are off meanwhile, the
+// rewrite isn't a new version of the rows.
+pub fn anonymize(client: &mut Client) -> Result<AnonymizeSummary, Error> {
+    let emails = include!("init_data/emails");
+    let addresses = include!("init_data/addresses");
+    let gazetteer: HashMap<String, geocoding::Place> =
+        geocoding::read_gazetteer(seed::GAZETTEER.as_bytes())?
+            .into_iter()
+            .map(|place| (geocoding::normalize(&place.address), place))
+            .collect();
+
+    let mut summary = AnonymizeSummary::default();
+    let mut transaction = client.transaction()?;
+
+    // the owner can switch the triggers off and isn't subject to row level security
+    transaction.batch_execute(&format!(
+        "
+        SET LOCAL ROLE {};
+
+        CREATE TEMPORARY TABLE anonymized (
+            pool        TEXT NOT NULL,
+            original    TEXT NOT NULL,
+            placeholder TEXT NOT NULL,
+            fake        TEXT NOT NULL,
+            latitude    DOUBLE PRECISION, -- NULLABLE
+            longitude   DOUBLE PRECISION, -- NULLABLE
+            PRIMARY KEY (pool, original)
+        ) ON COMMIT DROP;
+        ",
+        Role::Owner.name()
+    ))?;
+
+    let triggers: Vec<&str> = history::Table::ALL
+        .iter()
+        .map(|table| table.table())
+        .filter(|table| COLUMNS.iter().any(|(_, name, _)| name == table))
+        .collect();
+
+    for table in &triggers {
+        transaction.batch_execute(&format!(
+            "ALTER TABLE {table} DISABLE TRIGGER {table}_history_trigger"
+        ))?;
+    }
+
+    let token: String = transaction
+        .query_one("SELECT md5(random()::TEXT)", &[])?
+        .get(0);
+
+    for pool in Pool::ALL {
+        let values: &[&str] = match pool {
+            Pool::Emails | Pool::Names => &emails,
+            Pool::Addresses => &addresses,
+        };
+        summary.values += map_values(&mut transaction, pool, values, &gazetteer, &token)?;
+    }
+
+    for (pool, table, column) in COLUMNS {
+        for copy in copies(table) {
+            let placeholder = copy == table;
+            let rows = rewrite(&mut transaction, pool, &copy, column, placeholder)?;
+
+            summary.columns.push((copy, column, rows));
+        }
+    }
+
+    for (pool, table, column) in COLUMNS {
+        transaction.execute(
+            &format!(
+                "
+                UPDATE {table}
+                SET {column} = anonymized.fake
+                FROM anonymized
+                WHERE anonymized.pool = $1 AND {table}.{column} = anonymized.placeholder
+                "
+            ),
+            &[&pool.name()],
+        )?;
+    }
+
+    for table in &triggers {
+        transaction.batch_execute(&format!(
+            "ALTER TABLE {table} ENABLE TRIGGER {table}_history_trigger"
+        ))?;
+    }
+
+    transaction.commit()?;
+
+    Ok(summary)
+}
@@ -0,0 +1,407 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use postgres::{Client, Transaction};
+
+use crate::error::Error;
+use crate::search;
+
+pub const DEFAULT_RETENTION_DAYS: i32 = 365;
+
+// Master data that is soft deleted: rows get `deleted_at` instead of being
+// removed, and are moved into `<table>_archive` only by a purge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entity {
+    User,
+    Supplier,
+    Client,
+    Product,
+    Warehouse,
+}
+
+impl Entity {
+    // in the order purge goes through them, rows referring to others first
+    pub const ALL: [Entity; 5] = [
+        Entity::User,
+        Entity::Supplier,
+        Entity::Client,
+        Entity::Product,
+        Entity::Warehouse,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Entity::User => "user",
+            Entity::Supplier => "supplier",
+            Entity::Client => "client",
+            Entity::Product => "product",
+            Entity::Warehouse => "warehouse",
+        }
+    }
+
+    pub fn table(self) -> &'static str {
+        match self {
+            Entity::User => "users",
+            Entity::Supplier => "suppliers",
+            Entity::Client => "clients",
+            Entity::Product => "products",
+            Entity::Warehouse => "warehouses",
+        }
+    }
+
+    fn id_column(self) -> &'static str {
+        match self {
+            Entity::User => "user_id",
+            Entity::Supplier => "supplier_id",
+            Entity::Client => "client_id",
+            Entity::Product => "product_id",
+            Entity::Warehouse => "warehouse_id",
+        }
+    }
+
+    fn label_column(self) -> &'static str {
+        match self {
+            Entity::Warehouse => "address",
+            _ => "name",
+        }
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for Entity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Entity::ALL
+            .into_iter()
+            .find(|entity| entity.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown entity '{s}', expected user, supplier, client, product or warehouse"
+                )
+            })
+    }
+}
+
+// Rows belonging to a row of an entity, `$1` being its id, archived together
+// with it in `<table>_archive` before it, rows referring to others first. Rows
+// recording business, like orders, lots, stock or allocated requirements,
+// keep it from being archived instead.
+const DETAILS: [(Entity, &str, &str); 17] = [
+    (Entity::Supplier, "supplier_prices", "supplier_id = $1"),
+    (Entity::Supplier, "supplier_products", "supplier_id = $1"),
+    (Entity::Client, "client_prices", "client_id = $1"),
+    (
+        Entity::Client,
+        "product_requirements",
+        "client_address_id IN (SELECT client_address_id FROM client_addresses WHERE client_id = $1)",
+    ),
+    (Entity::Client, "client_addresses", "client_id = $1"),
+    (Entity::Product, "supplier_prices", "product_id = $1"),
+    (Entity::Product, "supplier_products", "product_id = $1"),
+    (Entity::Product, "client_prices", "product_id = $1"),
+    (
+        Entity::Product,
+        "product_barcodes",
+        "product_unit_id IN (SELECT product_unit_id FROM product_units WHERE product_id = $1)",
+    ),
+    (Entity::Product, "product_units", "product_id = $1"),
+    (Entity::Product, "product_nutrition", "product_id = $1"),
+    (Entity::Product, "product_allergens", "product_id = $1"),
+    (Entity::Product, "product_dietary_flags", "product_id = $1"),
+    (Entity::Product, "product_ingredients", "product_id = $1"),
+    (Entity::Product, "product_locations", "product_id = $1 AND count = 0"),
+    (Entity::Warehouse, "lot_locations", "warehouse_id = $1 AND count = 0"),
+    (Entity::Warehouse, "product_locations", "warehouse_id = $1 AND count = 0"),
+];
+
+#[derive(Debug, Clone)]
+pub struct DeletedRow {
+    pub id: i32,
+    pub label: String,
+    pub deleted_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PurgeSummary {
+    // (entity, archived rows, rows kept because other records still refer to them)
+    pub tables: Vec<(Entity, usize, usize)>,
+}
+
+// Tables with an archive, those of the entities and of their details
+pub fn tables() -> Vec<&'static str> {
+    let mut tables: Vec<&'static str> = Entity::ALL.iter().map(|entity| entity.table()).collect();
+
+    for (_, table, _) in DETAILS {
+        if !tables.contains(&table) {
+            tables.push(table);
+        }
+    }
+
+    tables
+}
+
+// Archive tables copy the columns of their tables and add the time of archival
+pub fn table_statements() -> Vec<String> {
+    tables()
+        .iter()
+        .map(|table| {
+            format!(
+                "
+                CREATE TABLE {table}_archive (
+                    LIKE {table},
+                    archived_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+                )
+                "
+            )
+        })
+        .chain([ARCHIVE_USER.to_owned()])
+        .collect()
+}
+
+// The app can't read password hashes, so it archives users through this
+// function running as the owner
+const ARCHIVE_USER: &str = "
+    CREATE OR REPLACE FUNCTION archive_user(id INTEGER) RETURNS VOID
+    LANGUAGE sql SECURITY DEFINER SET search_path FROM CURRENT AS $$
+        WITH deleted AS (
+            DELETE FROM users
+            WHERE user_id = id
+            RETURNING *
+        )
+        INSERT INTO users_archive
+        SELECT deleted.*, CURRENT_TIMESTAMP
+        FROM deleted
+    $$;
+
+    REVOKE EXECUTE ON FUNCTION archive_user(INTEGER) FROM PUBLIC;
+    GRANT EXECUTE ON FUNCTION archive_user(INTEGER) TO dmadms_app;
+    ";
+
+// Deleting a supplier or a client deletes its users too
+pub fn delete(client: &mut Client, entity: Entity, id: i32) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    let updated = transaction.execute(
+        &format!(
+            "
+            UPDATE {table}
+            SET deleted_at = CURRENT_TIMESTAMP
+            WHERE {id_column} = $1 AND deleted_at IS NULL
+            ",
+            table = entity.table(),
+            id_column = entity.id_column()
+        ),
+        &[&id],
+    )?;
+
+    if updated == 0 {
+        return Err(Error::NotFound(format!("{entity} {id}")));
+    }
+
+    if matches!(entity, Entity::Supplier | Entity::Client) {
+        transaction.execute(
+            &format!(
+                "
+                UPDATE users
+                SET deleted_at = CURRENT_TIMESTAMP
+                WHERE {id_column} = $1 AND deleted_at IS NULL
+                ",
+                id_column = entity.id_column()
+            ),
+            &[&id],
+        )?;
+    }
+
+    transaction.commit()?;
+
+    refresh_search(client, entity)
+}
+
+fn refresh_search(client: &mut Client, entity: Entity) -> Result<(), Error> {
+    match entity {
+        Entity::Supplier | Entity::Product => search::refresh(client),
+        _ => Ok(()),
+    }
+}
+
+// Restoring a supplier or a client restores the users deleted with it
+pub fn restore(client: &mut Client, entity: Entity, id: i32) -> Result<(), Error> {
+    let conflict = |error: postgres::Error| match error.code() {
+        Some(&postgres::error::SqlState::UNIQUE_VIOLATION) => Error::Conflict(format!(
+            "{entity} {id} can't be restored, another {entity} or user took its place"
+        )),
+        _ => error.into(),
+    };
+
+    let mut transaction = client.transaction()?;
+
+    let deleted_at: NaiveDateTime = transaction
+        .query_opt(
+            &format!(
+                "
+                UPDATE {table}
+                SET deleted_at = NULL
+                FROM (
+                    SELECT deleted_at
+                    FROM {table}
+                    WHERE {id_column} = $1 AND deleted_at IS NOT NULL
+                    FOR UPDATE
+                ) as deleted
+                WHERE {table}.{id_column} = $1
+                RETURNING deleted.deleted_at
+                ",
+                table = entity.table(),
+                id_column = entity.id_column()
+            ),
+            &[&id],
+        )
+        .map_err(conflict)?
+        .ok_or_else(|| Error::NotFound(format!("deleted {entity} {id}")))?
+        .get("deleted_at");
+
+    if matches!(entity, Entity::Supplier | Entity::Client) {
+        transaction
+            .execute(
+                &format!(
+                    "
+                    UPDATE users
+                    SET deleted_at = NULL
+                    WHERE {id_column} = $1 AND deleted_at = $2
+                    ",
+                    id_column = entity.id_column()
+                ),
+                &[&id, &deleted_at],
+            )
+            .map_err(conflict)?;
+    }
+
+    transaction.commit()?;
+
+    refresh_search(client, entity)
+}
+
+pub fn deleted(client: &mut Client, entity: Entity) -> Result<Vec<DeletedRow>, Error> {
+    Ok(client
+        .query(
+            &format!(
+                "
+                SELECT {id_column} as id, {label_column} as label, deleted_at
+                FROM {table}
+                WHERE deleted_at IS NOT NULL
+                ORDER BY deleted_at, {id_column}
+                ",
+                table = entity.table(),
+                id_column = entity.id_column(),
+                label_column = entity.label_column()
+            ),
+            &[],
+        )?
+        .iter()
+        .map(|row| DeletedRow {
+            id: row.get("id"),
+            label: row.get("label"),
+            deleted_at: row.get("deleted_at"),
+        })
+        .collect())
+}
+
+fn archive_row(
+    transaction: &mut Transaction,
+    entity: Entity,
+    id: i32,
+) -> Result<(), postgres::Error> {
+    if entity == Entity::User {
+        transaction.execute("SELECT archive_user($1)", &[&id])?;
+        return Ok(());
+    }
+
+    let row = format!("{} = $1", entity.id_column());
+    let conditions = DETAILS
+        .iter()
+        .filter(|(detail_of, _, _)| *detail_of == entity)
+        .map(|(_, table, condition)| (*table, *condition))
+        .chain([(entity.table(), row.as_str())]);
+
+    for (table, condition) in conditions {
+        transaction.execute(
+            &format!(
+                "
+                WITH deleted AS (
+                    DELETE FROM {table}
+                    WHERE {condition}
+                    RETURNING *
+                )
+                INSERT INTO {table}_archive
+                SELECT deleted.*, CURRENT_TIMESTAMP
+                FROM deleted
+                "
+            ),
+            &[&id],
+        )?;
+    }
+
+    Ok(())
+}
+
+// Moves rows deleted more than `retention_days` ago into the archive tables,
+// together with their details. Rows other records still refer to, like
+// products of past orders, are kept.
+pub fn purge(client: &mut Client, retention_days: i32) -> Result<PurgeSummary, Error> {
+    let mut transaction = client.transaction()?;
+    let mut summary = PurgeSummary::default();
+
+    for entity in Entity::ALL {
+        let ids: Vec<i32> = transaction
+            .query(
+                &format!(
+                    "
+                    SELECT {id_column}
+                    FROM {table}
+                    WHERE deleted_at < CURRENT_TIMESTAMP - make_interval(days => $1)
+                    ORDER BY {id_column}
+                    ",
+                    table = entity.table(),
+                    id_column = entity.id_column()
+                ),
+                &[&retention_days],
+            )?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        let (mut archived, mut kept) = (0, 0);
+
+        for id in ids {
+            let mut savepoint = transaction.transaction()?;
+
+            let moved = archive_row(&mut savepoint, entity, id);
+
+            match moved {
+                Ok(_) => {
+                    savepoint.commit()?;
+                    archived += 1;
+                }
+                Err(error)
+                    if error.code() == Some(&postgres::error::SqlState::FOREIGN_KEY_VIOLATION) =>
+                {
+                    savepoint.rollback()?;
+                    kept += 1;
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        summary.tables.push((entity, archived, kept));
+    }
+
+    transaction.commit()?;
+
+    Ok(summary)
+}
@@ -0,0 +1,307 @@
+use postgres::Client;
+use rust_decimal::Decimal;
+
+use crate::categories;
+use crate::error::Error;
+
+// The 14 major allergens of Regulation (EU) No 1169/2011, Annex II
+pub const ALLERGENS: [&str; 14] = [
+    "gluten",
+    "crustaceans",
+    "eggs",
+    "fish",
+    "peanuts",
+    "soybeans",
+    "milk",
+    "nuts",
+    "celery",
+    "mustard",
+    "sesame",
+    "sulphites",
+    "lupin",
+    "molluscs",
+];
+
+pub const DIETARY_FLAGS: [&str; 4] = ["vegan", "halal", "kosher", "gluten_free"];
+
+#[derive(Debug, Clone)]
+pub struct Nutrition {
+    pub energy_kcal: Decimal,
+    pub fat: Decimal,
+    pub saturated_fat: Decimal,
+    pub carbohydrates: Decimal,
+    pub sugars: Decimal,
+    pub protein: Decimal,
+    pub salt: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProductAttributes {
+    pub product: String,
+    // (allergen, presence) where presence is `contains` or `may_contain`
+    pub allergens: Vec<(String, String)>,
+    pub dietary_flags: Vec<String>,
+    pub nutrition: Option<Nutrition>,
+    pub ingredients: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProductFilter {
+    // id or path of a category, products anywhere below it match
+    pub category: Option<String>,
+    pub without_allergens: Vec<String>,
+    // exclude products that may contain traces of the allergens too
+    pub without_traces: bool,
+    pub dietary_flags: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FoundProduct {
+    pub product_id: i32,
+    pub name: String,
+    pub category: String,
+}
+
+pub(crate) const INSERT_ALLERGEN: &str = "INSERT INTO allergens (name) VALUES ($1)";
+
+pub(crate) const INSERT_DIETARY_FLAG: &str = "INSERT INTO dietary_flags (name) VALUES ($1)";
+
+pub(crate) const SEED: &str = "
+    INSERT INTO product_allergens (product_id, allergen_id, presence)
+    SELECT products.product_id, allergens.allergen_id, 'contains'
+    FROM products
+    INNER JOIN allergens
+    ON allergens.allergen_id = products.product_id % 14 + 1
+    WHERE products.product_id % 3 <> 0;
+
+    INSERT INTO product_allergens (product_id, allergen_id, presence)
+    SELECT products.product_id, allergens.allergen_id, 'may_contain'
+    FROM products
+    INNER JOIN allergens
+    ON allergens.allergen_id = products.product_id * 5 % 14 + 1
+    WHERE products.product_id % 4 = 0
+    ON CONFLICT DO NOTHING;
+
+    INSERT INTO product_dietary_flags (product_id, dietary_flag_id)
+    SELECT products.product_id, dietary_flags.dietary_flag_id
+    FROM products
+    CROSS JOIN dietary_flags
+    WHERE (dietary_flags.name = 'vegan' AND products.product_id % 5 = 0)
+    OR (dietary_flags.name = 'halal' AND products.product_id % 3 = 0)
+    OR (dietary_flags.name = 'kosher' AND products.product_id % 4 = 0)
+    OR (
+        dietary_flags.name = 'gluten_free'
+        AND products.product_id % 2 = 0
+        AND NOT EXISTS (
+            SELECT 1
+            FROM product_allergens
+            INNER JOIN allergens
+            ON product_allergens.allergen_id = allergens.allergen_id
+            WHERE product_allergens.product_id = products.product_id
+            AND allergens.name = 'gluten'
+        )
+    );
+
+    INSERT INTO product_nutrition (
+        product_id, energy_kcal, fat, saturated_fat, carbohydrates, sugars, protein, salt
+    )
+    SELECT
+        product_id,
+        (product_id * 37) % 500 + 20,
+        (product_id * 7) % 30 + 0.5,
+        ((product_id * 7) % 30 + 0.5) / 4,
+        (product_id * 11) % 40 + 1.5,
+        ((product_id * 11) % 40 + 1.5) / 3,
+        (product_id * 13) % 20 + 0.2,
+        (product_id % 20) / 10.0
+    FROM products;
+
+    INSERT INTO product_ingredients (product_id, position, name)
+    SELECT products.product_id, ingredient.position, ingredient.name
+    FROM products
+    INNER JOIN categories
+    ON products.category_id = categories.category_id
+    CROSS JOIN LATERAL (
+        VALUES (1, lower(categories.name)), (2, 'water'), (3, 'salt')
+    ) as ingredient(position, name)
+    WHERE ingredient.position < 3 OR products.product_id % 2 = 0;
+    ";
+
+pub fn seed(client: &mut Client) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    for allergen in ALLERGENS {
+        transaction.execute(INSERT_ALLERGEN, &[&allergen])?;
+    }
+
+    for flag in DIETARY_FLAGS {
+        transaction.execute(INSERT_DIETARY_FLAG, &[&flag])?;
+    }
+
+    transaction.batch_execute(SEED)?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+pub fn attributes(client: &mut Client, product_id: i32) -> Result<ProductAttributes, Error> {
+    let product: String = client
+        .query_opt(
+            "SELECT name FROM products WHERE product_id = $1",
+            &[&product_id],
+        )?
+        .ok_or_else(|| Error::NotFound(format!("product {product_id}")))?
+        .get("name");
+
+    let allergens = client
+        .query(
+            "
+            SELECT allergens.name, product_allergens.presence
+            FROM product_allergens
+            INNER JOIN allergens
+            ON product_allergens.allergen_id = allergens.allergen_id
+            WHERE product_allergens.product_id = $1
+            ORDER BY product_allergens.presence, allergens.name
+            ",
+            &[&product_id],
+        )?
+        .iter()
+        .map(|row| (row.get("name"), row.get("presence")))
+        .collect();
+
+    let dietary_flags = client
+        .query(
+            "
+            SELECT dietary_flags.name
+            FROM product_dietary_flags
+            INNER JOIN dietary_flags
+            ON product_dietary_flags.dietary_flag_id = dietary_flags.dietary_flag_id
+            WHERE product_dietary_flags.product_id = $1
+            ORDER BY dietary_flags.name
+            ",
+            &[&product_id],
+        )?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect();
+
+    let nutrition = client
+        .query_opt(
+            "SELECT * FROM product_nutrition WHERE product_id = $1",
+            &[&product_id],
+        )?
+        .map(|row| Nutrition {
+            energy_kcal: row.get("energy_kcal"),
+            fat: row.get("fat"),
+            saturated_fat: row.get("saturated_fat"),
+            carbohydrates: row.get("carbohydrates"),
+            sugars: row.get("sugars"),
+            protein: row.get("protein"),
+            salt: row.get("salt"),
+        });
+
+    let ingredients = client
+        .query(
+            "
+            SELECT name
+            FROM product_ingredients
+            WHERE product_id = $1
+            ORDER BY position
+            ",
+            &[&product_id],
+        )?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect();
+
+    Ok(ProductAttributes {
+        product,
+        allergens,
+        dietary_flags,
+        nutrition,
+        ingredients,
+    })
+}
+
+fn check_names(client: &mut Client, table: &str, names: &[String]) -> Result<(), Error> {
+    let known: Vec<String> = client
+        .query(
+            &format!("SELECT name FROM {table} WHERE name = ANY($1)"),
+            &[&names],
+        )?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect();
+
+    match names.iter().find(|name| !known.contains(name)) {
+        Some(name) => Err(Error::NotFound(format!("'{name}' in {table}"))),
+        None => Ok(()),
+    }
+}
+
+pub fn find(client: &mut Client, filter: &ProductFilter) -> Result<Vec<FoundProduct>, Error> {
+    let mut dietary_flags = filter.dietary_flags.clone();
+    dietary_flags.sort();
+    dietary_flags.dedup();
+
+    check_names(client, "allergens", &filter.without_allergens)?;
+    check_names(client, "dietary_flags", &dietary_flags)?;
+
+    let category_id = filter
+        .category
+        .as_deref()
+        .map(|category| categories::resolve(client, category))
+        .transpose()?;
+
+    Ok(client
+        .query(
+            "
+            SELECT
+                products.product_id,
+                products.name,
+                category_paths.path as category
+            FROM products
+            INNER JOIN category_paths
+            ON products.category_id = category_paths.category_id
+            WHERE products.deleted_at IS NULL
+            AND (
+                $1::INTEGER IS NULL
+                OR products.category_id IN (
+                    SELECT descendant_id FROM category_closure WHERE ancestor_id = $1
+                )
+            )
+            AND NOT EXISTS (
+                SELECT 1
+                FROM product_allergens
+                INNER JOIN allergens
+                ON product_allergens.allergen_id = allergens.allergen_id
+                WHERE product_allergens.product_id = products.product_id
+                AND allergens.name = ANY($2)
+                AND (product_allergens.presence = 'contains' OR $3)
+            )
+            AND (
+                SELECT COUNT(*)
+                FROM product_dietary_flags
+                INNER JOIN dietary_flags
+                ON product_dietary_flags.dietary_flag_id = dietary_flags.dietary_flag_id
+                WHERE product_dietary_flags.product_id = products.product_id
+                AND dietary_flags.name = ANY($4)
+            ) = cardinality($4::TEXT[])
+            ORDER BY category_paths.path, products.name
+            ",
+            &[
+                &category_id,
+                &filter.without_allergens,
+                &filter.without_traces,
+                &dietary_flags,
+            ],
+        )?
+        .iter()
+        .map(|row| FoundProduct {
+            product_id: row.get("product_id"),
+            name: row.get("name"),
+            category: row.get("category"),
+        })
+        .collect())
+}
@@ -0,0 +1,222 @@
+use std::io::Read;
+
+use postgres::{Client, GenericClient, Row};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone)]
+pub struct Barcode {
+    pub gtin: String,
+    pub product: String,
+    pub unit: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScannedProduct {
+    pub product_id: i32,
+    pub product: String,
+    pub unit: String,
+    pub factor: i32,
+    pub gtin: String,
+}
+
+// GS1 check digit of the digits preceding it: weights 3 and 1 alternate from
+// the right
+pub fn check_digit(digits: &str) -> u32 {
+    let sum: u32 = digits
+        .chars()
+        .rev()
+        .filter_map(|c| c.to_digit(10))
+        .enumerate()
+        .map(|(i, digit)| if i % 2 == 0 { digit * 3 } else { digit })
+        .sum();
+
+    (10 - sum % 10) % 10
+}
+
+// Trimmed GTIN-8, GTIN-12, GTIN-13 or GTIN-14 with a correct check digit
+pub fn validate(gtin: &str) -> Result<String, Error> {
+    let gtin = gtin.trim();
+
+    if !matches!(gtin.len(), 8 | 12 | 13 | 14) || !gtin.chars().all(|c| c.is_ascii_digit()) {
+        return Err(Error::Invalid(format!(
+            "'{gtin}' is not a GTIN of 8, 12, 13 or 14 digits"
+        )));
+    }
+
+    let (digits, check) = gtin.split_at(gtin.len() - 1);
+    let expected = check_digit(digits);
+
+    if check != expected.to_string() {
+        return Err(Error::Invalid(format!(
+            "GTIN {gtin} has check digit {check}, {expected} expected"
+        )));
+    }
+
+    Ok(gtin.to_owned())
+}
+
+pub(crate) const PRODUCT_UNIT_ID: &str = "
+    SELECT product_units.product_unit_id
+    FROM product_units
+    INNER JOIN units
+    ON product_units.unit_id = units.unit_id
+    WHERE product_units.product_id = $1 AND units.code = $2
+    ";
+
+// Assigning a GTIN again to the same packaging level changes nothing and
+// returns the existing barcode, no row is returned when another one has it
+pub(crate) const INSERT_BARCODE: &str = "
+    WITH inserted AS (
+        INSERT INTO product_barcodes (product_unit_id, gtin)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        RETURNING product_barcode_id
+    )
+    SELECT product_barcode_id
+    FROM inserted
+    UNION ALL
+    SELECT product_barcode_id
+    FROM product_barcodes
+    WHERE product_unit_id = $1 AND lpad(gtin, 14, '0') = lpad($2, 14, '0')
+    ";
+
+pub(crate) const PRODUCT_ID: &str =
+    "SELECT product_id FROM products WHERE name = $1 AND deleted_at IS NULL";
+
+// What the rows of the statements above mean, shared with the concurrent seeder
+pub(crate) fn product_id(row: Option<Row>, product: &str) -> Result<i32, Error> {
+    row.map(|row| row.get("product_id"))
+        .ok_or_else(|| Error::NotFound(format!("product '{product}'")))
+}
+
+pub(crate) fn product_unit_id(row: Option<Row>, product_id: i32, unit: &str) -> Result<i32, Error> {
+    row.map(|row| row.get("product_unit_id"))
+        .ok_or_else(|| Error::NotFound(format!("unit '{unit}' of product {product_id}")))
+}
+
+pub(crate) fn product_barcode_id(row: Option<Row>, gtin: &str) -> Result<i32, Error> {
+    row.map(|row| row.get("product_barcode_id")).ok_or_else(|| {
+        Error::Conflict(format!(
+            "GTIN {gtin} is already assigned to another product or unit"
+        ))
+    })
+}
+
+// Errors of a row of a barcode file name its line
+pub(crate) fn on_line(line: usize) -> impl Fn(Error) -> Error {
+    move |error| Error::Invalid(format!("{error} on line {}", line + 2))
+}
+
+fn insert<C: GenericClient>(
+    client: &mut C,
+    product_id: i32,
+    unit: &str,
+    gtin: &str,
+) -> Result<i32, Error> {
+    let product_unit_id = product_unit_id(
+        client.query_opt(PRODUCT_UNIT_ID, &[&product_id, &unit])?,
+        product_id,
+        unit,
+    )?;
+
+    product_barcode_id(
+        client.query_opt(INSERT_BARCODE, &[&product_unit_id, &gtin])?,
+        gtin,
+    )
+}
+
+pub fn add(client: &mut Client, product_id: i32, unit: &str, gtin: &str) -> Result<i32, Error> {
+    let gtin = validate(gtin)?;
+
+    insert(client, product_id, unit, &gtin)
+}
+
+// Finds the product and packaging level of a scanned code, leading zeros of
+// shorter GTINs don't matter
+pub fn lookup(client: &mut Client, code: &str) -> Result<ScannedProduct, Error> {
+    let code = validate(code)?;
+
+    let row = client
+        .query_opt(
+            "
+            SELECT
+                products.product_id,
+                products.name as product,
+                units.code as unit,
+                product_units.factor,
+                product_barcodes.gtin
+            FROM product_barcodes
+            INNER JOIN product_units
+            ON product_barcodes.product_unit_id = product_units.product_unit_id
+            INNER JOIN units
+            ON product_units.unit_id = units.unit_id
+            INNER JOIN products
+            ON product_units.product_id = products.product_id
+            WHERE lpad(product_barcodes.gtin, 14, '0') = lpad($1, 14, '0')
+            ",
+            &[&code],
+        )?
+        .ok_or_else(|| Error::NotFound(format!("product with GTIN {code}")))?;
+
+    Ok(ScannedProduct {
+        product_id: row.get("product_id"),
+        product: row.get("product"),
+        unit: row.get("unit"),
+        factor: row.get("factor"),
+        gtin: row.get("gtin"),
+    })
+}
+
+// CSV file with `gtin,product[,unit]` columns where product is a product name
+// and unit defaults to `each`
+pub fn read_barcodes<R: Read>(reader: R) -> Result<Vec<Barcode>, Error> {
+    let mut barcodes = Vec::new();
+
+    for (line, record) in csv::Reader::from_reader(reader).records().enumerate() {
+        let record = record?;
+
+        let (Some(gtin), Some(product)) = (record.get(0), record.get(1)) else {
+            return Err(Error::Invalid(format!(
+                "invalid barcode on line {}",
+                line + 2
+            )));
+        };
+
+        let gtin = validate(gtin)
+            .map_err(|error| Error::Invalid(format!("{error} on line {}", line + 2)))?;
+
+        barcodes.push(Barcode {
+            gtin,
+            product: product.trim().to_owned(),
+            unit: record
+                .get(2)
+                .map(str::trim)
+                .filter(|unit| !unit.is_empty())
+                .unwrap_or("each")
+                .to_owned(),
+        });
+    }
+
+    Ok(barcodes)
+}
+
+// Imports all barcodes or none of them
+pub fn import(client: &mut Client, barcodes: &[Barcode]) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    for (line, barcode) in barcodes.iter().enumerate() {
+        let product_id = product_id(
+            transaction.query_opt(PRODUCT_ID, &[&barcode.product])?,
+            &barcode.product,
+        )
+        .map_err(on_line(line))?;
+
+        insert(&mut transaction, product_id, &barcode.unit, &barcode.gtin)
+            .map_err(on_line(line))?;
+    }
+
+    transaction.commit()?;
+
+    Ok(())
+}
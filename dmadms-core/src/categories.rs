@@ -0,0 +1,479 @@
+use postgres::{Client, GenericClient};
+
+use crate::error::Error;
+use crate::{history, roles, tenancy};
+
+// Separates category names in a path like `Grocery / Ketchup`
+pub const PATH_SEPARATOR: &str = " / ";
+
+// Adjacency list of the tree and its closure table, which holds a row for every
+// ancestor of a category including the category itself at depth 0
+pub const TABLES: &str = "
+    CREATE TABLE categories (
+        category_id SERIAL PRIMARY KEY,
+        parent_id   INTEGER REFERENCES categories, -- NULLABLE, root categories have no parent
+        name        TEXT NOT NULL,
+        CHECK (parent_id <> category_id),
+        UNIQUE (parent_id, name)
+    );
+
+    CREATE UNIQUE INDEX root_category_name_index
+    ON categories(name)
+    WHERE parent_id IS NULL;
+
+    CREATE TABLE category_closure (
+        ancestor_id     INTEGER REFERENCES categories NOT NULL,
+        descendant_id   INTEGER REFERENCES categories NOT NULL,
+        depth           INTEGER NOT NULL,
+        CHECK (depth >= 0),
+        PRIMARY KEY (ancestor_id, descendant_id)
+    );
+
+    CREATE INDEX category_descendant_index
+    ON category_closure(descendant_id);
+
+    CREATE VIEW category_paths AS
+    SELECT
+        category_closure.descendant_id as category_id,
+        string_agg(categories.name, ' / ' ORDER BY category_closure.depth DESC) as path
+    FROM category_closure
+    INNER JOIN categories
+    ON category_closure.ancestor_id = categories.category_id
+    GROUP BY category_closure.descendant_id;
+";
+
+#[derive(Debug, Clone)]
+pub struct Category {
+    pub category_id: i32,
+    pub name: String,
+    pub depth: i32,
+    // products of the category and all categories below it
+    pub products: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryProduct {
+    pub product_id: i32,
+    pub name: String,
+    pub path: String,
+}
+
+pub(crate) const INSERT: &str = "
+    WITH category AS (
+        INSERT INTO categories (parent_id, name)
+        VALUES ($1, $2)
+        RETURNING category_id
+    ), closure AS (
+        INSERT INTO category_closure (ancestor_id, descendant_id, depth)
+        SELECT category_closure.ancestor_id, category.category_id, category_closure.depth + 1
+        FROM category_closure
+        CROSS JOIN category
+        WHERE category_closure.descendant_id = $1
+        UNION ALL
+        SELECT category_id, category_id, 0
+        FROM category
+    )
+    SELECT category_id FROM category
+    ";
+
+pub fn insert<C: GenericClient>(
+    client: &mut C,
+    parent_id: Option<i32>,
+    name: &str,
+) -> Result<i32, postgres::Error> {
+    Ok(client
+        .query_one(INSERT, &[&parent_id, &name])?
+        .get("category_id"))
+}
+
+// Category by its id or by its path from a root category, names are compared
+// ignoring case unless that is ambiguous
+pub fn resolve<C: GenericClient>(client: &mut C, category: &str) -> Result<i32, Error> {
+    if let Ok(category_id) = category.parse::<i32>() {
+        return client
+            .query_opt(CATEGORY_BY_ID, &[&category_id])?
+            .map(|row| row.get("category_id"))
+            .ok_or_else(|| Error::NotFound(format!("category {category_id}")));
+    }
+
+    let path = path_of(category);
+
+    let matches: Vec<(i32, String)> = client
+        .query(CATEGORIES_BY_PATH, &[&path])?
+        .iter()
+        .map(|row| (row.get("category_id"), row.get("path")))
+        .collect();
+
+    pick(category, &path, &matches)
+}
+
+pub(crate) const CATEGORY_BY_ID: &str = "SELECT category_id FROM categories WHERE category_id = $1";
+
+pub(crate) const CATEGORIES_BY_PATH: &str =
+    "SELECT category_id, path FROM category_paths WHERE lower(path) = lower($1)";
+
+pub(crate) fn path_of(category: &str) -> String {
+    category
+        .split('/')
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(PATH_SEPARATOR)
+}
+
+// The only match, or the one matching the case of the path exactly
+pub(crate) fn pick(category: &str, path: &str, matches: &[(i32, String)]) -> Result<i32, Error> {
+    match matches {
+        [] => Err(Error::NotFound(format!("category '{category}'"))),
+        [(category_id, _)] => Ok(*category_id),
+        _ => matches
+            .iter()
+            .find(|(_, candidate)| candidate == path)
+            .map(|(category_id, _)| *category_id)
+            .ok_or_else(|| {
+                Error::Invalid(format!("category '{category}' is ambiguous, use its id"))
+            }),
+    }
+}
+
+fn is_within<C: GenericClient>(
+    client: &mut C,
+    category_id: i32,
+    ancestor_id: i32,
+) -> Result<bool, Error> {
+    Ok(client
+        .query_one(
+            "
+            SELECT EXISTS (
+                SELECT 1
+                FROM category_closure
+                WHERE ancestor_id = $2 AND descendant_id = $1
+            )
+            ",
+            &[&category_id, &ancestor_id],
+        )?
+        .get(0))
+}
+
+fn name_taken(error: postgres::Error, name: &str) -> Error {
+    match error.code() {
+        Some(&postgres::error::SqlState::UNIQUE_VIOLATION) => {
+            Error::Conflict(format!("there is already a category '{name}' there"))
+        }
+        _ => error.into(),
+    }
+}
+
+pub fn add(client: &mut Client, parent: Option<&str>, name: &str) -> Result<i32, Error> {
+    let mut transaction = client.transaction()?;
+
+    let parent_id = parent
+        .map(|parent| resolve(&mut transaction, parent))
+        .transpose()?;
+    let category_id =
+        insert(&mut transaction, parent_id, name).map_err(|error| name_taken(error, name))?;
+
+    transaction.commit()?;
+
+    Ok(category_id)
+}
+
+pub fn rename(client: &mut Client, category: &str, name: &str) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    let category_id = resolve(&mut transaction, category)?;
+
+    transaction
+        .execute(
+            "UPDATE categories SET name = $2 WHERE category_id = $1",
+            &[&category_id, &name],
+        )
+        .map_err(|error| name_taken(error, name))?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+fn move_under<C: GenericClient>(
+    client: &mut C,
+    category_id: i32,
+    parent_id: Option<i32>,
+) -> Result<(), Error> {
+    if let Some(parent_id) = parent_id {
+        if is_within(client, parent_id, category_id)? {
+            return Err(Error::Invalid(format!(
+                "category {category_id} can't be moved into itself"
+            )));
+        }
+    }
+
+    // paths from former ancestors into the subtree
+    client.execute(
+        "
+        DELETE FROM category_closure
+        WHERE descendant_id IN (
+            SELECT descendant_id FROM category_closure WHERE ancestor_id = $1
+        )
+        AND ancestor_id NOT IN (
+            SELECT descendant_id FROM category_closure WHERE ancestor_id = $1
+        )
+        ",
+        &[&category_id],
+    )?;
+
+    client.execute(
+        "
+        INSERT INTO category_closure (ancestor_id, descendant_id, depth)
+        SELECT above.ancestor_id, below.descendant_id, above.depth + below.depth + 1
+        FROM category_closure as above
+        CROSS JOIN category_closure as below
+        WHERE above.descendant_id = $2 AND below.ancestor_id = $1
+        ",
+        &[&category_id, &parent_id],
+    )?;
+
+    let name: String = client
+        .query_one(
+            "SELECT name FROM categories WHERE category_id = $1",
+            &[&category_id],
+        )?
+        .get("name");
+
+    client
+        .execute(
+            "UPDATE categories SET parent_id = $2 WHERE category_id = $1",
+            &[&category_id, &parent_id],
+        )
+        .map_err(|error| name_taken(error, &name))?;
+
+    Ok(())
+}
+
+// Moves a category with everything below it, to the roots without a parent
+pub fn move_to(client: &mut Client, category: &str, parent: Option<&str>) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    let category_id = resolve(&mut transaction, category)?;
+    let parent_id = parent
+        .map(|parent| resolve(&mut transaction, parent))
+        .transpose()?;
+
+    move_under(&mut transaction, category_id, parent_id)?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+fn merge_into<C: GenericClient>(
+    client: &mut C,
+    source_id: i32,
+    target_id: i32,
+) -> Result<(), Error> {
+    client.execute(
+        "UPDATE products SET category_id = $2 WHERE category_id = $1",
+        &[&source_id, &target_id],
+    )?;
+
+    // children with a namesake in the target are merged into it, others moved
+    let children: Vec<(i32, Option<i32>)> = client
+        .query(
+            "
+            SELECT source.category_id, target.category_id as namesake_id
+            FROM categories as source
+            LEFT JOIN categories as target
+            ON target.parent_id = $2 AND target.name = source.name
+            WHERE source.parent_id = $1
+            ",
+            &[&source_id, &target_id],
+        )?
+        .iter()
+        .map(|row| (row.get("category_id"), row.get("namesake_id")))
+        .collect();
+
+    for (child_id, namesake_id) in children {
+        match namesake_id {
+            Some(namesake_id) => merge_into(client, child_id, namesake_id)?,
+            None => move_under(client, child_id, Some(target_id))?,
+        }
+    }
+
+    client.execute(
+        "DELETE FROM category_closure WHERE descendant_id = $1",
+        &[&source_id],
+    )?;
+    client.execute(
+        "DELETE FROM categories WHERE category_id = $1",
+        &[&source_id],
+    )?;
+
+    Ok(())
+}
+
+// Moves products and subcategories of a category into another one and removes it
+pub fn merge(client: &mut Client, source: &str, target: &str) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    let source_id = resolve(&mut transaction, source)?;
+    let target_id = resolve(&mut transaction, target)?;
+
+    if is_within(&mut transaction, target_id, source_id)? {
+        return Err(Error::Invalid(format!(
+            "category {source} can't be merged into itself"
+        )));
+    }
+
+    merge_into(&mut transaction, source_id, target_id)?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+// Whole tree in depth first order
+pub fn tree(client: &mut Client) -> Result<Vec<Category>, Error> {
+    Ok(client
+        .query(
+            "
+            SELECT
+                categories.category_id,
+                categories.name,
+                (SELECT MAX(depth) FROM category_closure WHERE descendant_id = categories.category_id) as depth,
+                (
+                    SELECT COUNT(*)
+                    FROM category_closure
+                    INNER JOIN products
+                    ON category_closure.descendant_id = products.category_id
+                    WHERE category_closure.ancestor_id = categories.category_id
+                    AND products.deleted_at IS NULL
+                ) as products
+            FROM categories
+            INNER JOIN category_paths
+            ON categories.category_id = category_paths.category_id
+            ORDER BY category_paths.path
+            ",
+            &[],
+        )?
+        .iter()
+        .map(|row| Category {
+            category_id: row.get("category_id"),
+            name: row.get("name"),
+            depth: row.get("depth"),
+            products: row.get("products"),
+        })
+        .collect())
+}
+
+// Products of a category and all categories below it
+pub fn products(client: &mut Client, category: &str) -> Result<Vec<CategoryProduct>, Error> {
+    let category_id = resolve(client, category)?;
+
+    Ok(client
+        .query(
+            "
+            SELECT products.product_id, products.name, category_paths.path
+            FROM category_closure
+            INNER JOIN products
+            ON category_closure.descendant_id = products.category_id
+            INNER JOIN category_paths
+            ON products.category_id = category_paths.category_id
+            WHERE category_closure.ancestor_id = $1
+            AND products.deleted_at IS NULL
+            ORDER BY category_paths.path, products.name
+            ",
+            &[&category_id],
+        )?
+        .iter()
+        .map(|row| CategoryProduct {
+            product_id: row.get("product_id"),
+            name: row.get("name"),
+            path: row.get("path"),
+        })
+        .collect())
+}
+
+// Converts a database with the former two fixed levels, `product_categories`
+// and `product_subcategories`, into the tree. The owner takes the database over
+// and the tree gets its history, grants and tenant access as in a new schema.
+pub fn migrate(client: &mut Client) -> Result<usize, Error> {
+    let mut transaction = client.transaction()?;
+
+    let migrated: bool = transaction
+        .query_one("SELECT to_regclass('product_subcategories') IS NULL", &[])?
+        .get(0);
+
+    if migrated {
+        return Ok(0);
+    }
+
+    roles::take_over(&mut transaction)?;
+
+    for statement in [
+        TABLES,
+        history::RECORD_HISTORY,
+        &history::table_statement(history::Table::Category),
+        &roles::history_grant(&[history::Table::Category]),
+        tenancy::CATEGORY_GRANT,
+    ] {
+        transaction.batch_execute(statement)?;
+    }
+
+    let categories: Vec<(i32, String)> = transaction
+        .query(
+            "SELECT category_id, name FROM product_categories ORDER BY category_id",
+            &[],
+        )?
+        .iter()
+        .map(|row| (row.get("category_id"), row.get("name")))
+        .collect();
+
+    transaction.batch_execute(
+        "
+        ALTER TABLE products ADD COLUMN category_id INTEGER REFERENCES categories;
+        ",
+    )?;
+
+    let mut count = 0;
+
+    for (old_category_id, name) in categories {
+        let category_id = insert(&mut transaction, None, &name)?;
+        count += 1;
+
+        let subcategories: Vec<(i32, String)> = transaction
+            .query(
+                "
+                SELECT subcategory_id, name
+                FROM product_subcategories
+                WHERE category_id = $1
+                ORDER BY subcategory_id
+                ",
+                &[&old_category_id],
+            )?
+            .iter()
+            .map(|row| (row.get("subcategory_id"), row.get("name")))
+            .collect();
+
+        for (subcategory_id, name) in subcategories {
+            let subcategory_category_id = insert(&mut transaction, Some(category_id), &name)?;
+            count += 1;
+
+            transaction.execute(
+                "UPDATE products SET category_id = $2 WHERE subcategory_id = $1",
+                &[&subcategory_id, &subcategory_category_id],
+            )?;
+        }
+    }
+
+    transaction.batch_execute(
+        "
+        ALTER TABLE products ALTER COLUMN category_id SET NOT NULL;
+        ALTER TABLE products DROP COLUMN subcategory_id;
+        DROP TABLE product_subcategories;
+        DROP TABLE product_categories;
+        ",
+    )?;
+
+    transaction.commit()?;
+
+    Ok(count)
+}
@@ -0,0 +1,59 @@
+use postgres::Client;
+
+use crate::error::Error;
+
+#[cfg(feature = "tls")]
+pub type Tls = postgres_openssl::MakeTlsConnector;
+#[cfg(not(feature = "tls"))]
+pub type Tls = postgres::NoTls;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub host: String,
+    pub user: String,
+    pub password: String,
+    pub dbname: String,
+}
+
+impl Config {
+    // Reads POSTGRES_HOST, POSTGRES_USER, POSTGRES_PASSWORD and POSTGRES_DBNAME
+    pub fn from_env() -> Result<Config, Error> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| Error::Invalid(format!("{name} is not set")))
+        };
+
+        Ok(Config {
+            host: var("POSTGRES_HOST")?,
+            user: var("POSTGRES_USER")?,
+            password: var("POSTGRES_PASSWORD")?,
+            dbname: var("POSTGRES_DBNAME")?,
+        })
+    }
+
+    pub fn params(&self) -> String {
+        format!(
+            "host={} user={} password={} dbname={}",
+            self.host, self.user, self.password, self.dbname
+        )
+    }
+}
+
+#[cfg(feature = "tls")]
+pub fn tls() -> Result<Tls, Error> {
+    use openssl::ssl::{SslConnector, SslMethod};
+
+    let ssl_connector = SslConnector::builder(SslMethod::tls())
+        .map_err(|error| Error::Invalid(format!("can't set up TLS: {error}")))?
+        .build();
+
+    Ok(postgres_openssl::MakeTlsConnector::new(ssl_connector))
+}
+
+#[cfg(not(feature = "tls"))]
+pub fn tls() -> Result<Tls, Error> {
+    Ok(postgres::NoTls)
+}
+
+pub fn connect(config: &Config) -> Result<Client, Error> {
+    Ok(Client::connect(&config.params(), tls()?)?)
+}
@@ -0,0 +1,119 @@
+use postgres::Client;
+
+// Queries shown after the database is initialized
+
+pub(crate) const COUNTRIES: &str = "
+    SELECT name FROM countries
+    ";
+
+pub fn countries(client: &mut Client) -> Result<Vec<String>, postgres::Error> {
+    Ok(client
+        .query(COUNTRIES, &[])?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect())
+}
+
+pub(crate) const SUBCATEGORIES: &str = "
+    SELECT categories.name as category, subcategories.name as subcategory
+    FROM categories
+    INNER JOIN categories as subcategories
+    ON categories.category_id = subcategories.parent_id
+    WHERE categories.parent_id IS NULL
+    AND categories.name <> ALL($1)
+    ";
+
+// (category, subcategory) of root categories except the excluded ones
+pub fn subcategories(
+    client: &mut Client,
+    except: &[&str],
+) -> Result<Vec<(String, String)>, postgres::Error> {
+    Ok(client
+        .query(SUBCATEGORIES, &[&except])?
+        .iter()
+        .map(|row| (row.get("category"), row.get("subcategory")))
+        .collect())
+}
+
+pub(crate) const SUPPLIERS: &str = "
+    SELECT suppliers.name as supplier, email, countries.name as country
+    FROM suppliers
+    INNER JOIN countries
+    ON suppliers.country_id = countries.country_id
+    WHERE suppliers.deleted_at IS NULL
+    LIMIT $1
+    ";
+
+// (supplier, email, country)
+pub fn suppliers(
+    client: &mut Client,
+    limit: i64,
+) -> Result<Vec<(String, String, String)>, postgres::Error> {
+    Ok(client
+        .query(SUPPLIERS, &[&limit])?
+        .iter()
+        .map(|row| (row.get("supplier"), row.get("email"), row.get("country")))
+        .collect())
+}
+
+pub(crate) const GROCERIES: &str = "
+    SELECT subcategory, products.name as product
+    FROM
+    (
+        SELECT categories.name as subcategory, categories.category_id
+        FROM category_closure
+        INNER JOIN categories
+        ON category_closure.descendant_id = categories.category_id
+        WHERE category_closure.depth > 0 AND category_closure.ancestor_id = (
+            SELECT category_id from categories
+            WHERE name = 'Grocery' AND parent_id IS NULL
+        )
+    ) as grocery_subcategories
+    INNER JOIN products
+    ON grocery_subcategories.category_id = products.category_id
+    WHERE subcategory LIKE $1 || '%' AND products.deleted_at IS NULL
+    ORDER by subcategory
+    ";
+
+// (subcategory, product) of groceries in subcategories that start with a prefix
+pub fn groceries(
+    client: &mut Client,
+    prefix: &str,
+) -> Result<Vec<(String, String)>, postgres::Error> {
+    Ok(client
+        .query(GROCERIES, &[&prefix])?
+        .iter()
+        .map(|row| (row.get("subcategory"), row.get("product")))
+        .collect())
+}
+
+pub(crate) const COUNT_USERS: &str = "
+    SELECT COUNT(1)
+    FROM users
+    WHERE deleted_at IS NULL
+    ";
+
+pub fn count_users(client: &mut Client) -> Result<i64, postgres::Error> {
+    Ok(client.query_one(COUNT_USERS, &[])?.get(0))
+}
+
+pub(crate) const DELETE_SUPPLIER_USERS: &str = "
+    UPDATE users
+    SET deleted_at = CURRENT_TIMESTAMP
+    WHERE password = crypt($1, password) AND supplier_id IS NOT NULL
+    AND deleted_at IS NULL
+    ";
+
+// Soft deletes supplier users with a password, returns how many were deleted
+pub fn delete_supplier_users(client: &mut Client, password: &str) -> Result<u64, postgres::Error> {
+    client.execute(DELETE_SUPPLIER_USERS, &[&password])
+}
+
+pub(crate) const LOWERCASE_CATEGORIES: &str = "
+    UPDATE categories
+    SET name = lower(name);
+    ";
+
+pub fn lowercase_categories(client: &mut Client) -> Result<(), postgres::Error> {
+    client.batch_execute(LOWERCASE_CATEGORIES)
+}
@@ -0,0 +1,263 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use postgres::{Client, Row};
+
+use crate::error::Error;
+
+pub const DOCUMENT_TYPES: [&str; 3] = [
+    "certificate_of_origin",
+    "phytosanitary_certificate",
+    "customs_declaration",
+];
+
+#[derive(Debug, Clone)]
+pub struct NewDocument {
+    pub document_type: String,
+    pub number: String,
+    pub valid_from: NaiveDate,
+    pub valid_to: Option<NaiveDate>,
+    pub supplier_id: Option<i32>,
+    pub product_id: Option<i32>,
+    pub purchase_order_id: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MissingDocument {
+    pub country: String,
+    pub supplier: String,
+    pub product: String,
+    pub document_type: String,
+}
+
+impl MissingDocument {
+    pub(crate) fn from_row(row: &Row) -> Self {
+        MissingDocument {
+            country: row.get("country"),
+            supplier: row.get("supplier"),
+            product: row.get("product"),
+            document_type: row.get("document_type"),
+        }
+    }
+}
+
+// Directory where document files are kept, `DOCUMENTS_DIR` or `documents`
+pub fn storage_dir() -> PathBuf {
+    std::env::var_os("DOCUMENTS_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("documents"))
+}
+
+pub(crate) const INSERT_DOCUMENT_TYPE: &str = "INSERT INTO document_types (name) VALUES ($1)";
+
+pub(crate) const SEED: &str = "
+    INSERT INTO required_documents (country_id, document_type_id)
+    SELECT countries.country_id, document_types.document_type_id
+    FROM countries
+    CROSS JOIN document_types
+    WHERE document_types.name = 'certificate_of_origin'
+    OR (
+        document_types.name = 'phytosanitary_certificate'
+        AND countries.name NOT IN ('Bulgaria', 'France', 'Italy', 'Martinique', 'Netherlands', 'Sweden')
+    );
+
+    INSERT INTO documents (document_type_id, number, valid_from, valid_to, supplier_id)
+    SELECT
+        (SELECT document_type_id FROM document_types WHERE name = 'certificate_of_origin'),
+        'CO-' || lpad(suppliers.supplier_id::TEXT, 6, '0'),
+        DATE '2022-01-01',
+        NULL,
+        suppliers.supplier_id
+    FROM suppliers
+    WHERE suppliers.supplier_id % 2 = 0;
+
+    INSERT INTO documents (document_type_id, number, valid_from, valid_to, product_id)
+    SELECT
+        (SELECT document_type_id FROM document_types WHERE name = 'phytosanitary_certificate'),
+        'PC-' || lpad(products.product_id::TEXT, 6, '0'),
+        CURRENT_DATE - 200,
+        CURRENT_DATE - 200 + (products.product_id * 53) % 365,
+        products.product_id
+    FROM products
+    WHERE products.product_id % 3 = 0;
+    ";
+
+// Every supplier country requires a certificate of origin, countries outside of
+// the EU also a phytosanitary certificate. Even suppliers hold certificates of
+// origin, every third product a phytosanitary certificate, some of them expired.
+pub fn seed(client: &mut Client) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    for document_type in DOCUMENT_TYPES {
+        transaction.execute(INSERT_DOCUMENT_TYPE, &[&document_type])?;
+    }
+
+    transaction.batch_execute(SEED)?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+pub fn require(client: &mut Client, country: &str, document_type: &str) -> Result<(), Error> {
+    let inserted = client.execute(
+        "
+        INSERT INTO required_documents (country_id, document_type_id)
+        SELECT countries.country_id, document_types.document_type_id
+        FROM countries
+        CROSS JOIN document_types
+        WHERE countries.name = $1 AND document_types.name = $2
+        ON CONFLICT DO NOTHING
+        ",
+        &[&country, &document_type],
+    )?;
+
+    if inserted == 0 {
+        let known: bool = client
+            .query_one(
+                "
+                SELECT
+                    EXISTS (SELECT 1 FROM countries WHERE name = $1)
+                    AND EXISTS (SELECT 1 FROM document_types WHERE name = $2)
+                ",
+                &[&country, &document_type],
+            )?
+            .get(0);
+
+        if !known {
+            return Err(Error::NotFound(format!(
+                "country '{country}' or document type '{document_type}'"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+// Registers a document and copies its file, if any, into `storage`
+pub fn add(
+    client: &mut Client,
+    document: &NewDocument,
+    file: Option<&Path>,
+    storage: &Path,
+) -> Result<i32, Error> {
+    let mut transaction = client.transaction()?;
+
+    let document_type_id: i32 = transaction
+        .query_opt(
+            "SELECT document_type_id FROM document_types WHERE name = $1",
+            &[&document.document_type],
+        )?
+        .ok_or_else(|| Error::NotFound(format!("document type '{}'", document.document_type)))?
+        .get("document_type_id");
+
+    let document_id: i32 = transaction
+        .query_one(
+            "
+            INSERT INTO documents (
+                document_type_id, number, valid_from, valid_to, supplier_id, product_id, purchase_order_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING document_id
+            ",
+            &[
+                &document_type_id,
+                &document.number,
+                &document.valid_from,
+                &document.valid_to,
+                &document.supplier_id,
+                &document.product_id,
+                &document.purchase_order_id,
+            ],
+        )?
+        .get("document_id");
+
+    let relative = match file {
+        Some(file) => {
+            let name = file
+                .file_name()
+                .ok_or_else(|| Error::Invalid(format!("'{}' is not a file", file.display())))?;
+
+            Some(Path::new(&document_id.to_string()).join(name))
+        }
+        None => None,
+    };
+
+    let stored = (|| -> Result<(), Error> {
+        if let (Some(file), Some(relative)) = (file, &relative) {
+            let destination = storage.join(relative);
+
+            if let Some(directory) = destination.parent() {
+                fs::create_dir_all(directory)?;
+            }
+            fs::copy(file, &destination)?;
+
+            transaction.execute(
+                "UPDATE documents SET file_path = $2 WHERE document_id = $1",
+                &[&document_id, &relative.to_string_lossy().as_ref()],
+            )?;
+        }
+
+        transaction.commit()?;
+
+        Ok(())
+    })();
+
+    // the document isn't registered then, neither is its file kept
+    if let (Err(_), Some(relative)) = (&stored, &relative) {
+        let destination = storage.join(relative);
+
+        _ = fs::remove_file(&destination);
+        if let Some(directory) = destination.parent() {
+            _ = fs::remove_dir(directory);
+        }
+    }
+
+    stored.map(|_| document_id)
+}
+
+pub(crate) const MISSING: &str = "
+    SELECT
+        countries.name as country,
+        suppliers.name as supplier,
+        products.name as product,
+        document_types.name as document_type
+    FROM products
+    INNER JOIN supplier_products
+    ON products.product_id = supplier_products.product_id
+    AND supplier_products.active
+    INNER JOIN suppliers
+    ON supplier_products.supplier_id = suppliers.supplier_id
+    INNER JOIN countries
+    ON suppliers.country_id = countries.country_id
+    INNER JOIN required_documents
+    ON countries.country_id = required_documents.country_id
+    INNER JOIN document_types
+    ON required_documents.document_type_id = document_types.document_type_id
+    WHERE ($1::TEXT IS NULL OR countries.name = $1)
+    AND products.deleted_at IS NULL
+    AND suppliers.deleted_at IS NULL
+    AND NOT EXISTS (
+        SELECT 1
+        FROM documents
+        WHERE documents.document_type_id = required_documents.document_type_id
+        AND (
+            documents.product_id = products.product_id
+            OR (documents.product_id IS NULL AND documents.supplier_id = suppliers.supplier_id)
+        )
+        AND documents.valid_from <= CURRENT_DATE
+        AND (documents.valid_to IS NULL OR documents.valid_to >= CURRENT_DATE)
+    )
+    ORDER BY countries.name, suppliers.name, products.name, document_types.name
+    ";
+
+// Products whose active supplier's country requires a document that neither
+// the product nor that supplier has valid today
+pub fn missing(client: &mut Client, country: Option<&str>) -> Result<Vec<MissingDocument>, Error> {
+    Ok(client
+        .query(MISSING, &[&country])?
+        .iter()
+        .map(MissingDocument::from_row)
+        .collect())
+}
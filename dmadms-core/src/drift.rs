@@ -0,0 +1,684 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use postgres::Client;
+
+use crate::error::Error;
+use crate::schema;
+
+// Scratch schema the expected tables are built in, dropped after introspection
+const EXPECTED_SCHEMA: &str = "dmadms_expected";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub data_type: String,
+    pub nullable: bool,
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    // p(rimary key), u(nique), f(oreign key), c(heck) or x (exclusion)
+    pub kind: String,
+    pub definition: String,
+}
+
+// A table, view or materialized view
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relation {
+    pub kind: String,
+    // query of views
+    pub definition: Option<String>,
+    // in the order of the table
+    pub columns: Vec<(String, Column)>,
+    pub constraints: BTreeMap<String, Constraint>,
+    // indexes that don't back a constraint, by name
+    pub indexes: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    pub relations: BTreeMap<String, Relation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    MissingRelation {
+        name: String,
+        expected: Relation,
+    },
+    ExtraRelation {
+        name: String,
+        kind: String,
+    },
+    ChangedView {
+        name: String,
+        expected: Relation,
+    },
+    MissingColumn {
+        table: String,
+        column: String,
+        expected: Column,
+    },
+    ExtraColumn {
+        table: String,
+        column: String,
+    },
+    ChangedColumn {
+        table: String,
+        column: String,
+        expected: Column,
+        actual: Column,
+    },
+    MissingConstraint {
+        table: String,
+        name: String,
+        expected: Constraint,
+    },
+    ExtraConstraint {
+        table: String,
+        name: String,
+    },
+    ChangedConstraint {
+        table: String,
+        name: String,
+        expected: Constraint,
+        actual: Constraint,
+    },
+    MissingIndex {
+        name: String,
+        definition: String,
+    },
+    ExtraIndex {
+        name: String,
+    },
+    ChangedIndex {
+        name: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.data_type)?;
+        if !self.nullable {
+            f.write_str(" NOT NULL")?;
+        }
+        if let Some(default) = &self.default {
+            write!(f, " DEFAULT {default}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Drift::MissingRelation { name, expected } => {
+                write!(f, "missing {} {name}", expected.kind)
+            }
+            Drift::ExtraRelation { name, kind } => write!(f, "unexpected {kind} {name}"),
+            Drift::ChangedView { name, expected } => {
+                write!(f, "changed {} {name}: its query differs", expected.kind)
+            }
+            Drift::MissingColumn {
+                table,
+                column,
+                expected,
+            } => write!(f, "missing column {table}.{column} {expected}"),
+            Drift::ExtraColumn { table, column } => {
+                write!(f, "unexpected column {table}.{column}")
+            }
+            Drift::ChangedColumn {
+                table,
+                column,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "changed column {table}.{column}: expected {expected}, found {actual}"
+            ),
+            Drift::MissingConstraint {
+                table,
+                name,
+                expected,
+            } => write!(
+                f,
+                "missing constraint {table}.{name} {}",
+                expected.definition
+            ),
+            Drift::ExtraConstraint { table, name } => {
+                write!(f, "unexpected constraint {table}.{name}")
+            }
+            Drift::ChangedConstraint {
+                table,
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "changed constraint {table}.{name}: expected {}, found {}",
+                expected.definition, actual.definition
+            ),
+            Drift::MissingIndex { name, definition } => {
+                write!(f, "missing index {name}: {definition}")
+            }
+            Drift::ExtraIndex { name } => write!(f, "unexpected index {name}"),
+            Drift::ChangedIndex {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "changed index {name}: expected {expected}, found {actual}"
+            ),
+        }
+    }
+}
+
+// Definitions name objects of the introspected schema with its prefix now and then
+fn unqualified(schema: &str, text: &str) -> String {
+    text.replace(&format!("{schema}."), "")
+}
+
+// Reads tables, views, columns, constraints and indexes of a schema from pg_catalog
+pub fn introspect(client: &mut Client, schema: &str) -> Result<Schema, Error> {
+    // names of other objects are qualified only when they aren't on the search path
+    client.batch_execute(&format!("SET search_path TO {schema}, public"))?;
+
+    let mut relations = BTreeMap::new();
+
+    for row in client.query(
+        "
+        SELECT
+            pg_class.relname as name,
+            CASE pg_class.relkind
+                WHEN 'r' THEN 'table'
+                WHEN 'v' THEN 'view'
+                ELSE 'materialized view'
+            END as kind,
+            CASE WHEN pg_class.relkind IN ('v', 'm') THEN pg_get_viewdef(pg_class.oid) END as definition
+        FROM pg_class
+        INNER JOIN pg_namespace
+        ON pg_class.relnamespace = pg_namespace.oid
+        WHERE pg_namespace.nspname = $1
+        AND pg_class.relkind IN ('r', 'v', 'm')
+        ",
+        &[&schema],
+    )? {
+        let definition: Option<String> = row.get("definition");
+
+        relations.insert(
+            row.get("name"),
+            Relation {
+                kind: row.get("kind"),
+                definition: definition.map(|definition| {
+                    unqualified(schema, definition.trim().trim_end_matches(';'))
+                }),
+                columns: Vec::new(),
+                constraints: BTreeMap::new(),
+                indexes: BTreeMap::new(),
+            },
+        );
+    }
+
+    for row in client.query(
+        "
+        SELECT
+            pg_class.relname as relation,
+            quote_ident(pg_attribute.attname) as name,
+            format_type(pg_attribute.atttypid, pg_attribute.atttypmod) as data_type,
+            NOT pg_attribute.attnotnull as nullable,
+            pg_get_expr(pg_attrdef.adbin, pg_attrdef.adrelid) as default
+        FROM pg_attribute
+        INNER JOIN pg_class
+        ON pg_attribute.attrelid = pg_class.oid
+        INNER JOIN pg_namespace
+        ON pg_class.relnamespace = pg_namespace.oid
+        LEFT JOIN pg_attrdef
+        ON pg_attrdef.adrelid = pg_attribute.attrelid AND pg_attrdef.adnum = pg_attribute.attnum
+        WHERE pg_namespace.nspname = $1
+        AND pg_class.relkind IN ('r', 'v', 'm')
+        AND pg_attribute.attnum > 0
+        AND NOT pg_attribute.attisdropped
+        ORDER BY pg_attribute.attnum
+        ",
+        &[&schema],
+    )? {
+        let default: Option<String> = row.get("default");
+
+        if let Some(relation) = relations.get_mut(row.get::<_, &str>("relation")) {
+            relation.columns.push((
+                row.get("name"),
+                Column {
+                    data_type: row.get("data_type"),
+                    nullable: row.get("nullable"),
+                    default: default.map(|default| unqualified(schema, &default)),
+                },
+            ));
+        }
+    }
+
+    for row in client.query(
+        "
+        SELECT
+            pg_class.relname as relation,
+            pg_constraint.conname as name,
+            pg_constraint.contype::TEXT as kind,
+            pg_get_constraintdef(pg_constraint.oid) as definition
+        FROM pg_constraint
+        INNER JOIN pg_class
+        ON pg_constraint.conrelid = pg_class.oid
+        INNER JOIN pg_namespace
+        ON pg_class.relnamespace = pg_namespace.oid
+        WHERE pg_namespace.nspname = $1
+        ",
+        &[&schema],
+    )? {
+        if let Some(relation) = relations.get_mut(row.get::<_, &str>("relation")) {
+            relation.constraints.insert(
+                row.get("name"),
+                Constraint {
+                    kind: row.get("kind"),
+                    definition: unqualified(schema, row.get("definition")),
+                },
+            );
+        }
+    }
+
+    for row in client.query(
+        "
+        SELECT
+            pg_class.relname as relation,
+            indexes.relname as name,
+            pg_get_indexdef(pg_index.indexrelid) as definition
+        FROM pg_index
+        INNER JOIN pg_class as indexes
+        ON pg_index.indexrelid = indexes.oid
+        INNER JOIN pg_class
+        ON pg_index.indrelid = pg_class.oid
+        INNER JOIN pg_namespace
+        ON pg_class.relnamespace = pg_namespace.oid
+        WHERE pg_namespace.nspname = $1
+        AND NOT EXISTS (
+            SELECT 1 FROM pg_constraint WHERE pg_constraint.conindid = pg_index.indexrelid
+        )
+        ",
+        &[&schema],
+    )? {
+        if let Some(relation) = relations.get_mut(row.get::<_, &str>("relation")) {
+            relation
+                .indexes
+                .insert(row.get("name"), unqualified(schema, row.get("definition")));
+        }
+    }
+
+    Ok(Schema { relations })
+}
+
+// Builds the tables `init` creates in a scratch schema and introspects them
+pub fn expected(client: &mut Client) -> Result<Schema, Error> {
+    client.batch_execute(&format!(
+        "
+        DROP SCHEMA IF EXISTS {EXPECTED_SCHEMA} CASCADE;
+        CREATE SCHEMA {EXPECTED_SCHEMA};
+        SET search_path TO {EXPECTED_SCHEMA}, public;
+        "
+    ))?;
+
+    let expected = schema::create_tables(client)
+        .and_then(|_| schema::create_indexes(client))
+        .map_err(Error::from)
+        .and_then(|_| introspect(client, EXPECTED_SCHEMA));
+
+    client.batch_execute(&format!("DROP SCHEMA {EXPECTED_SCHEMA} CASCADE"))?;
+
+    expected
+}
+
+// Differences of the current schema of the database from the expected one
+pub fn diff(client: &mut Client) -> Result<Vec<Drift>, Error> {
+    let row = client.query_one(
+        "SELECT current_schema(), current_setting('search_path')",
+        &[],
+    )?;
+    let (current, search_path): (String, String) = (row.get(0), row.get(1));
+
+    let result = introspect(client, &current)
+        .and_then(|actual| expected(client).map(|expected| compare(&expected, &actual)));
+
+    client.batch_execute(&format!("SET search_path TO {search_path}"))?;
+
+    result
+}
+
+pub fn compare(expected: &Schema, actual: &Schema) -> Vec<Drift> {
+    let mut drifts = Vec::new();
+
+    for (name, expected) in &expected.relations {
+        let Some(actual) = actual
+            .relations
+            .get(name)
+            .filter(|actual| actual.kind == expected.kind)
+        else {
+            if let Some(actual) = actual.relations.get(name) {
+                drifts.push(Drift::ExtraRelation {
+                    name: name.clone(),
+                    kind: actual.kind.clone(),
+                });
+            }
+            drifts.push(Drift::MissingRelation {
+                name: name.clone(),
+                expected: expected.clone(),
+            });
+            continue;
+        };
+
+        if expected.definition.is_some() {
+            // columns of views follow from their queries
+            if expected.definition != actual.definition {
+                drifts.push(Drift::ChangedView {
+                    name: name.clone(),
+                    expected: expected.clone(),
+                });
+                continue;
+            }
+        } else {
+            compare_columns(name, expected, actual, &mut drifts);
+        }
+
+        compare_constraints(name, expected, actual, &mut drifts);
+        compare_indexes(expected, actual, &mut drifts);
+    }
+
+    for (name, actual) in &actual.relations {
+        if !expected.relations.contains_key(name) {
+            drifts.push(Drift::ExtraRelation {
+                name: name.clone(),
+                kind: actual.kind.clone(),
+            });
+        }
+    }
+
+    drifts
+}
+
+fn compare_columns(table: &str, expected: &Relation, actual: &Relation, drifts: &mut Vec<Drift>) {
+    let find = |columns: &[(String, Column)], name: &str| {
+        columns
+            .iter()
+            .find(|(column, _)| column == name)
+            .map(|(_, column)| column.clone())
+    };
+
+    for (column, expected_column) in &expected.columns {
+        match find(&actual.columns, column) {
+            None => drifts.push(Drift::MissingColumn {
+                table: table.to_owned(),
+                column: column.clone(),
+                expected: expected_column.clone(),
+            }),
+            Some(actual_column) if actual_column != *expected_column => {
+                drifts.push(Drift::ChangedColumn {
+                    table: table.to_owned(),
+                    column: column.clone(),
+                    expected: expected_column.clone(),
+                    actual: actual_column,
+                })
+            }
+            Some(_) => {}
+        }
+    }
+
+    for (column, _) in &actual.columns {
+        if find(&expected.columns, column).is_none() {
+            drifts.push(Drift::ExtraColumn {
+                table: table.to_owned(),
+                column: column.clone(),
+            });
+        }
+    }
+}
+
+fn compare_constraints(
+    table: &str,
+    expected: &Relation,
+    actual: &Relation,
+    drifts: &mut Vec<Drift>,
+) {
+    for (name, expected_constraint) in &expected.constraints {
+        match actual.constraints.get(name) {
+            None => drifts.push(Drift::MissingConstraint {
+                table: table.to_owned(),
+                name: name.clone(),
+                expected: expected_constraint.clone(),
+            }),
+            Some(actual_constraint) if actual_constraint != expected_constraint => {
+                drifts.push(Drift::ChangedConstraint {
+                    table: table.to_owned(),
+                    name: name.clone(),
+                    expected: expected_constraint.clone(),
+                    actual: actual_constraint.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+
+    for name in actual.constraints.keys() {
+        if !expected.constraints.contains_key(name) {
+            drifts.push(Drift::ExtraConstraint {
+                table: table.to_owned(),
+                name: name.clone(),
+            });
+        }
+    }
+}
+
+fn compare_indexes(expected: &Relation, actual: &Relation, drifts: &mut Vec<Drift>) {
+    for (name, definition) in &expected.indexes {
+        match actual.indexes.get(name) {
+            None => drifts.push(Drift::MissingIndex {
+                name: name.clone(),
+                definition: definition.clone(),
+            }),
+            Some(actual) if actual != definition => drifts.push(Drift::ChangedIndex {
+                name: name.clone(),
+                expected: definition.clone(),
+                actual: actual.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for name in actual.indexes.keys() {
+        if !expected.indexes.contains_key(name) {
+            drifts.push(Drift::ExtraIndex { name: name.clone() });
+        }
+    }
+}
+
+// Serial columns are created with their sequences rather than a default naming one
+fn column_sql(name: &str, column: &Column) -> String {
+    let sequence = column
+        .default
+        .as_deref()
+        .is_some_and(|default| default.starts_with("nextval("));
+
+    let serial = match column.data_type.as_str() {
+        "integer" if sequence => Some("SERIAL"),
+        "bigint" if sequence => Some("BIGSERIAL"),
+        _ => None,
+    };
+
+    match serial {
+        Some(serial) => format!("{name} {serial}"),
+        None => format!("{name} {column}"),
+    }
+}
+
+fn create_relation_sql(name: &str, relation: &Relation) -> String {
+    match (relation.kind.as_str(), &relation.definition) {
+        ("view", Some(definition)) => format!("CREATE OR REPLACE VIEW {name} AS\n{definition};"),
+        (_, Some(definition)) => {
+            format!("CREATE MATERIALIZED VIEW {name} AS\n{definition}\nWITH NO DATA;")
+        }
+        (_, None) => format!(
+            "CREATE TABLE {name} (\n    {}\n);",
+            relation
+                .columns
+                .iter()
+                .map(|(column, definition)| column_sql(column, definition))
+                .collect::<Vec<_>>()
+                .join(",\n    ")
+        ),
+    }
+}
+
+// Primary keys and unique constraints go before the foreign keys referring to them
+fn constraint_order(constraint: &Constraint) -> usize {
+    match constraint.kind.as_str() {
+        "p" => 0,
+        "u" => 1,
+        "f" => 3,
+        _ => 2,
+    }
+}
+
+// Statements that make the database match the expected schema. Statements that
+// would lose data, dropping tables and columns, are only given as comments.
+pub fn converge_sql(drifts: &[Drift]) -> Vec<String> {
+    let mut drops = Vec::new();
+    let mut relations = Vec::new();
+    let mut columns = Vec::new();
+    let mut constraints = Vec::new();
+    let mut views = Vec::new();
+    let mut indexes = Vec::new();
+    let mut destructive = Vec::new();
+
+    let mut add_constraint = |table: &str, name: &str, constraint: &Constraint| {
+        constraints.push((
+            constraint_order(constraint),
+            format!(
+                "ALTER TABLE {table} ADD CONSTRAINT {name} {};",
+                constraint.definition
+            ),
+        ));
+    };
+
+    for drift in drifts {
+        match drift {
+            Drift::MissingRelation { name, expected } => {
+                let statement = create_relation_sql(name, expected);
+
+                if expected.definition.is_some() {
+                    views.push(statement);
+                } else {
+                    relations.push(statement);
+                }
+                for (constraint, definition) in &expected.constraints {
+                    add_constraint(name, constraint, definition);
+                }
+                indexes.extend(expected.indexes.values().map(|index| format!("{index};")));
+            }
+            Drift::ExtraRelation { name, kind } if kind == "table" => {
+                destructive.push(format!("-- DROP TABLE {name} CASCADE;"));
+            }
+            Drift::ExtraRelation { name, kind } => {
+                drops.push(format!(
+                    "DROP {} IF EXISTS {name} CASCADE;",
+                    kind.to_uppercase()
+                ));
+            }
+            Drift::ChangedView { name, expected } if expected.kind == "view" => {
+                views.push(create_relation_sql(name, expected));
+            }
+            Drift::ChangedView { name, expected } => {
+                drops.push(format!("DROP MATERIALIZED VIEW IF EXISTS {name} CASCADE;"));
+                views.push(create_relation_sql(name, expected));
+                indexes.extend(expected.indexes.values().map(|index| format!("{index};")));
+            }
+            Drift::MissingColumn {
+                table,
+                column,
+                expected,
+            } => columns.push(format!(
+                "ALTER TABLE {table} ADD COLUMN {};",
+                column_sql(column, expected)
+            )),
+            Drift::ExtraColumn { table, column } => {
+                destructive.push(format!("-- ALTER TABLE {table} DROP COLUMN {column};"));
+            }
+            Drift::ChangedColumn {
+                table,
+                column,
+                expected,
+                actual,
+            } => {
+                if expected.data_type != actual.data_type {
+                    columns.push(format!(
+                        "ALTER TABLE {table} ALTER COLUMN {column} TYPE {data_type} USING {column}::{data_type};",
+                        data_type = expected.data_type
+                    ));
+                }
+                if expected.nullable != actual.nullable {
+                    columns.push(format!(
+                        "ALTER TABLE {table} ALTER COLUMN {column} {} NOT NULL;",
+                        if expected.nullable { "DROP" } else { "SET" }
+                    ));
+                }
+                if expected.default != actual.default {
+                    columns.push(match &expected.default {
+                        Some(default) => {
+                            format!(
+                                "ALTER TABLE {table} ALTER COLUMN {column} SET DEFAULT {default};"
+                            )
+                        }
+                        None => format!("ALTER TABLE {table} ALTER COLUMN {column} DROP DEFAULT;"),
+                    });
+                }
+            }
+            Drift::MissingConstraint {
+                table,
+                name,
+                expected,
+            } => add_constraint(table, name, expected),
+            Drift::ExtraConstraint { table, name } => {
+                drops.push(format!(
+                    "ALTER TABLE {table} DROP CONSTRAINT IF EXISTS {name};"
+                ));
+            }
+            Drift::ChangedConstraint {
+                table,
+                name,
+                expected,
+                ..
+            } => {
+                drops.push(format!(
+                    "ALTER TABLE {table} DROP CONSTRAINT IF EXISTS {name};"
+                ));
+                add_constraint(table, name, expected);
+            }
+            Drift::MissingIndex { definition, .. } => indexes.push(format!("{definition};")),
+            Drift::ExtraIndex { name } => drops.push(format!("DROP INDEX IF EXISTS {name};")),
+            Drift::ChangedIndex { name, expected, .. } => {
+                drops.push(format!("DROP INDEX IF EXISTS {name};"));
+                indexes.push(format!("{expected};"));
+            }
+        }
+    }
+
+    constraints.sort_by_key(|(order, _)| *order);
+
+    drops
+        .into_iter()
+        .chain(relations)
+        .chain(columns)
+        .chain(constraints.into_iter().map(|(_, statement)| statement))
+        .chain(views)
+        .chain(indexes)
+        .chain(destructive)
+        .collect()
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Postgres(postgres::Error),
+    Csv(csv::Error),
+    Io(std::io::Error),
+    Invalid(String),
+    NotFound(String),
+    Conflict(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Postgres(error) => match error.as_db_error() {
+                Some(db_error) => write!(f, "database error: {}", db_error.message()),
+                None => write!(f, "database error: {error}"),
+            },
+            Error::Csv(error) => write!(f, "csv error: {error}"),
+            Error::Io(error) => write!(f, "io error: {error}"),
+            Error::Invalid(message) => f.write_str(message),
+            Error::NotFound(what) => write!(f, "{what} not found"),
+            Error::Conflict(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Postgres(error) => Some(error),
+            Error::Csv(error) => Some(error),
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<postgres::Error> for Error {
+    fn from(error: postgres::Error) -> Self {
+        Error::Postgres(error)
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(error: csv::Error) -> Self {
+        Error::Csv(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,742 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use postgres::{Client, IsolationLevel, Transaction};
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+use crate::schema;
+
+pub const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    JsonLines,
+    Parquet,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Csv, Format::JsonLines, Format::Parquet];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::JsonLines => "jsonl",
+            Format::Parquet => "parquet",
+        }
+    }
+
+    // extension of the files, the name of the format too
+    pub fn extension(self) -> &'static str {
+        self.name()
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Format::ALL
+            .into_iter()
+            .find(|format| format.name() == s)
+            .ok_or_else(|| format!("unknown format '{s}', expected csv, jsonl or parquet"))
+    }
+}
+
+// Personal data replaced by made up values, unique where the originals are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Anonymization {
+    Emails,
+    Names,
+    Addresses,
+}
+
+impl Anonymization {
+    pub const ALL: [Anonymization; 3] = [
+        Anonymization::Emails,
+        Anonymization::Names,
+        Anonymization::Addresses,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Anonymization::Emails => "emails",
+            Anonymization::Names => "names",
+            Anonymization::Addresses => "addresses",
+        }
+    }
+}
+
+impl fmt::Display for Anonymization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for Anonymization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Anonymization::ALL
+            .into_iter()
+            .find(|anonymization| anonymization.name() == s)
+            .ok_or_else(|| {
+                format!("unknown anonymization '{s}', expected emails, names or addresses")
+            })
+    }
+}
+
+// (table, column, anonymization, expression replacing the column)
+const PERSONAL: [(&str, &str, Anonymization, &str); 8] = [
+    (
+        "suppliers",
+        "email",
+        Anonymization::Emails,
+        "'supplier' || supplier_id || '@example.com'",
+    ),
+    (
+        "clients",
+        "email",
+        Anonymization::Emails,
+        "'client' || client_id || '@example.com'",
+    ),
+    (
+        "suppliers",
+        "name",
+        Anonymization::Names,
+        "'Supplier ' || supplier_id",
+    ),
+    (
+        "clients",
+        "name",
+        Anonymization::Names,
+        "'Client ' || client_id",
+    ),
+    ("users", "name", Anonymization::Names, "'user' || user_id"),
+    (
+        "client_addresses",
+        "address",
+        Anonymization::Addresses,
+        "'Address ' || client_address_id",
+    ),
+    (
+        "client_addresses",
+        "latitude",
+        Anonymization::Addresses,
+        "NULL",
+    ),
+    (
+        "client_addresses",
+        "longitude",
+        Anonymization::Addresses,
+        "NULL",
+    ),
+];
+
+// Password hashes never leave the database, the column is exported empty
+const WITHHELD: [(&str, &str); 1] = [("users", "password")];
+
+// Rows of other suppliers and clients are left out, with every row referring
+// to them, and to those rows in turn
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    // name of the country of suppliers
+    pub country: Option<String>,
+    pub client_id: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub format: Format,
+    pub filter: Filter,
+    pub anonymize: BTreeSet<Anonymization>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedTable {
+    pub table: String,
+    pub file: String,
+    pub rows: usize,
+    // hex SHA-256 of the file
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub schema_version: String,
+    pub format: Format,
+    pub exported_at: NaiveDateTime,
+    pub filter: Filter,
+    pub anonymized: Vec<Anonymization>,
+    // in the order they can be loaded in, referred tables first
+    pub tables: Vec<ExportedTable>,
+}
+
+impl Manifest {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "schema_version": self.schema_version,
+            "format": self.format.name(),
+            "exported_at": self.exported_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            "filter": {
+                "country": self.filter.country,
+                "client_id": self.filter.client_id,
+            },
+            "anonymized": self.anonymized.iter().map(|anonymization| anonymization.name()).collect::<Vec<_>>(),
+            "tables": self.tables.iter().map(|table| serde_json::json!({
+                "table": table.table,
+                "file": table.file,
+                "rows": table.rows,
+                "sha256": table.checksum,
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+// Column types as they are written, everything else goes as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Integer,
+    BigInteger,
+    Boolean,
+    Float,
+    // precision and scale
+    Decimal(u8, i8),
+    Date,
+    Timestamp,
+    Text,
+}
+
+#[derive(Debug, Clone)]
+struct Column {
+    name: String,
+    kind: Kind,
+}
+
+// (columns, referenced table, referenced columns)
+type ForeignKey = (Vec<String>, String, Vec<String>);
+
+#[derive(Debug, Clone)]
+struct Table {
+    name: String,
+    columns: Vec<Column>,
+    foreign_keys: Vec<ForeignKey>,
+}
+
+type Values = Vec<Vec<Option<String>>>;
+
+// History and archive tables aren't entities and are left out. Read from the
+// catalog, columns the role may not read are listed too.
+fn tables(transaction: &mut Transaction) -> Result<Vec<Table>, Error> {
+    let mut tables: BTreeMap<String, Table> = BTreeMap::new();
+
+    for row in transaction.query(
+        "
+        SELECT
+            pg_class.relname::TEXT,
+            pg_attribute.attname::TEXT,
+            format_type(pg_attribute.atttypid, NULL),
+            CASE WHEN pg_attribute.atttypmod >= 4 THEN ((pg_attribute.atttypmod - 4) >> 16) & 65535 END,
+            CASE WHEN pg_attribute.atttypmod >= 4 THEN (pg_attribute.atttypmod - 4) & 65535 END
+        FROM pg_attribute
+        INNER JOIN pg_class
+        ON pg_attribute.attrelid = pg_class.oid
+        WHERE pg_class.relnamespace = current_schema()::regnamespace
+        AND pg_class.relkind = 'r'
+        AND pg_class.relname !~ '_(history|archive)$'
+        AND pg_attribute.attnum > 0
+        AND NOT pg_attribute.attisdropped
+        ORDER BY pg_class.relname, pg_attribute.attnum
+        ",
+        &[],
+    )? {
+        let name: String = row.get(0);
+        let precision: Option<i32> = row.get(3);
+        let scale: Option<i32> = row.get(4);
+
+        let kind = match (row.get::<_, &str>(2), precision, scale) {
+            ("smallint" | "integer", _, _) => Kind::Integer,
+            ("bigint", _, _) => Kind::BigInteger,
+            ("boolean", _, _) => Kind::Boolean,
+            ("real" | "double precision", _, _) => Kind::Float,
+            ("numeric", Some(precision), Some(scale)) => {
+                Kind::Decimal(precision as u8, scale as i8)
+            }
+            ("date", _, _) => Kind::Date,
+            ("timestamp without time zone", _, _) => Kind::Timestamp,
+            _ => Kind::Text,
+        };
+
+        tables
+            .entry(name.clone())
+            .or_insert_with(|| Table {
+                name,
+                columns: Vec::new(),
+                foreign_keys: Vec::new(),
+            })
+            .columns
+            .push(Column {
+                name: row.get(1),
+                kind,
+            });
+    }
+
+    for row in transaction.query(
+        "
+        SELECT
+            pg_constraint.conrelid::regclass::TEXT,
+            array_agg(columns.attname::TEXT ORDER BY keys.position),
+            pg_constraint.confrelid::regclass::TEXT,
+            array_agg(referenced_columns.attname::TEXT ORDER BY keys.position)
+        FROM pg_constraint
+        CROSS JOIN LATERAL unnest(pg_constraint.conkey, pg_constraint.confkey)
+            WITH ORDINALITY as keys(key, referenced_key, position)
+        INNER JOIN pg_attribute as columns
+        ON columns.attrelid = pg_constraint.conrelid AND columns.attnum = keys.key
+        INNER JOIN pg_attribute as referenced_columns
+        ON referenced_columns.attrelid = pg_constraint.confrelid
+        AND referenced_columns.attnum = keys.referenced_key
+        WHERE pg_constraint.contype = 'f'
+        AND pg_constraint.connamespace = current_schema()::regnamespace
+        GROUP BY pg_constraint.oid, pg_constraint.conrelid, pg_constraint.confrelid
+        ORDER BY pg_constraint.conname
+        ",
+        &[],
+    )? {
+        if let Some(table) = tables.get_mut(row.get::<_, &str>(0)) {
+            table
+                .foreign_keys
+                .push((row.get(1), row.get(2), row.get(3)));
+        }
+    }
+
+    sorted(tables)
+}
+
+// Referenced tables before the ones referring to them, by name otherwise
+fn sorted(mut tables: BTreeMap<String, Table>) -> Result<Vec<Table>, Error> {
+    let mut sorted = Vec::new();
+
+    while !tables.is_empty() {
+        let next = tables
+            .values()
+            .find(|table| {
+                table.foreign_keys.iter().all(|(_, referenced, _)| {
+                    *referenced == table.name || !tables.contains_key(referenced)
+                })
+            })
+            .map(|table| table.name.clone())
+            .ok_or_else(|| {
+                Error::Invalid(format!(
+                    "tables refer to each other in a cycle: {}",
+                    tables.keys().cloned().collect::<Vec<_>>().join(", ")
+                ))
+            })?;
+
+        sorted.extend(tables.remove(&next));
+    }
+
+    Ok(sorted)
+}
+
+// Conditions of the rows exported from each table. A table is restricted by
+// the filter or by referring to a restricted table.
+fn conditions(
+    transaction: &mut Transaction,
+    tables: &[Table],
+    filter: &Filter,
+) -> Result<BTreeMap<String, String>, Error> {
+    let mut conditions: BTreeMap<String, String> = BTreeMap::new();
+
+    if let Some(country) = &filter.country {
+        let country_id: i32 = transaction
+            .query_opt(
+                "SELECT country_id FROM countries WHERE name = $1",
+                &[country],
+            )?
+            .ok_or_else(|| Error::NotFound(format!("country '{country}'")))?
+            .get(0);
+
+        conditions.insert(
+            "suppliers".to_owned(),
+            format!("suppliers.country_id = {country_id}"),
+        );
+    }
+
+    if let Some(client_id) = filter.client_id {
+        conditions.insert(
+            "clients".to_owned(),
+            format!("clients.client_id = {client_id}"),
+        );
+    }
+
+    for table in tables {
+        let mut restrictions: Vec<String> =
+            conditions.get(&table.name).into_iter().cloned().collect();
+
+        for (columns, referenced, referenced_columns) in &table.foreign_keys {
+            let Some(condition) = conditions
+                .get(referenced)
+                .filter(|_| *referenced != table.name)
+            else {
+                continue;
+            };
+
+            let columns: Vec<String> = columns
+                .iter()
+                .map(|column| format!("{}.{column}", table.name))
+                .collect();
+
+            // a key with a null column refers to nothing
+            restrictions.push(format!(
+                "({} OR ({}) IN (SELECT {} FROM {referenced} WHERE {condition}))",
+                columns
+                    .iter()
+                    .map(|column| format!("{column} IS NULL"))
+                    .collect::<Vec<_>>()
+                    .join(" OR "),
+                columns.join(", "),
+                referenced_columns
+                    .iter()
+                    .map(|column| format!("{referenced}.{column}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        if !restrictions.is_empty() {
+            conditions.insert(table.name.clone(), restrictions.join(" AND "));
+        }
+    }
+
+    Ok(conditions)
+}
+
+fn select(
+    table: &Table,
+    condition: Option<&String>,
+    anonymize: &BTreeSet<Anonymization>,
+) -> String {
+    let columns: Vec<String> = table
+        .columns
+        .iter()
+        .map(|column| {
+            let personal = PERSONAL.iter().find(|(name, personal, anonymization, _)| {
+                *name == table.name && *personal == column.name && anonymize.contains(anonymization)
+            });
+            let withheld = WITHHELD.contains(&(table.name.as_str(), column.name.as_str()));
+
+            match personal {
+                _ if withheld => format!("NULL::TEXT as {}", column.name),
+                Some((_, _, _, expression)) => format!("({expression})::TEXT as {}", column.name),
+                None => format!("{}.{}::TEXT", table.name, column.name),
+            }
+        })
+        .collect();
+
+    // ordered by every column for the same files from the same rows
+    let order: Vec<String> = table
+        .columns
+        .iter()
+        .filter(|column| !WITHHELD.contains(&(table.name.as_str(), column.name.as_str())))
+        .map(|column| format!("{}.{}", table.name, column.name))
+        .collect();
+
+    format!(
+        "SELECT {} FROM {} WHERE {} ORDER BY {}",
+        columns.join(", "),
+        table.name,
+        condition.map(String::as_str).unwrap_or("TRUE"),
+        order.join(", ")
+    )
+}
+
+fn write_csv(path: &Path, table: &Table, values: &Values) -> Result<(), Error> {
+    let mut writer = csv::Writer::from_path(path)?;
+
+    writer.write_record(table.columns.iter().map(|column| &column.name))?;
+
+    for row in values {
+        writer.write_record(row.iter().map(|value| value.as_deref().unwrap_or("")))?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+// Exact decimals, dates and timestamps are written as strings
+fn json_value(kind: Kind, value: &str) -> serde_json::Value {
+    let number = match kind {
+        Kind::Integer | Kind::BigInteger => value.parse::<i64>().ok().map(serde_json::Value::from),
+        Kind::Float => value
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(serde_json::Value::Number),
+        Kind::Boolean => Some(serde_json::Value::Bool(value == "true")),
+        _ => None,
+    };
+
+    number.unwrap_or_else(|| serde_json::Value::String(value.to_owned()))
+}
+
+fn write_json_lines(path: &Path, table: &Table, values: &Values) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    for row in values {
+        let object: serde_json::Map<String, serde_json::Value> = table
+            .columns
+            .iter()
+            .zip(row)
+            .map(|(column, value)| {
+                let value = value
+                    .as_deref()
+                    .map(|value| json_value(column.kind, value))
+                    .unwrap_or(serde_json::Value::Null);
+
+                (column.name.clone(), value)
+            })
+            .collect();
+
+        serde_json::to_writer(&mut writer, &object).map_err(std::io::Error::from)?;
+        writer.write_all(b"\n")?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(path: &Path, table: &Table, values: &Values) -> Result<(), Error> {
+    use std::sync::Arc;
+
+    use arrow_array::{
+        ArrayRef, BooleanArray, Date32Array, Decimal128Array, Float64Array, Int32Array, Int64Array,
+        RecordBatch, StringArray, TimestampMicrosecondArray,
+    };
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).expect("epoch is a date");
+    let parquet_error =
+        |error: &dyn fmt::Display| Error::Invalid(format!("parquet error: {error}"));
+
+    // parses every value of the column, nulls stay null
+    fn parsed<'a, T>(
+        cells: impl Iterator<Item = Option<&'a str>>,
+        parse: impl Fn(&str) -> Option<T>,
+        error: impl Fn(&str) -> Error,
+    ) -> Result<Vec<Option<T>>, Error> {
+        cells
+            .map(|cell| {
+                cell.map(|value| parse(value).ok_or_else(|| error(value)))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    let mut fields = Vec::new();
+    let mut arrays: Vec<ArrayRef> = Vec::new();
+
+    for (index, column) in table.columns.iter().enumerate() {
+        let cells = values.iter().map(|row| row[index].as_deref());
+
+        let error = |value: &str| {
+            Error::Invalid(format!(
+                "can't export '{value}' of {}.{}",
+                table.name, column.name
+            ))
+        };
+
+        let (data_type, array): (DataType, ArrayRef) = match column.kind {
+            Kind::Integer => (
+                DataType::Int32,
+                Arc::new(Int32Array::from(parsed(
+                    cells,
+                    |value| value.parse().ok(),
+                    error,
+                )?)),
+            ),
+            Kind::BigInteger => (
+                DataType::Int64,
+                Arc::new(Int64Array::from(parsed(
+                    cells,
+                    |value| value.parse().ok(),
+                    error,
+                )?)),
+            ),
+            Kind::Boolean => (
+                DataType::Boolean,
+                Arc::new(BooleanArray::from(parsed(
+                    cells,
+                    |value| value.parse().ok(),
+                    error,
+                )?)),
+            ),
+            Kind::Float => (
+                DataType::Float64,
+                Arc::new(Float64Array::from(parsed(
+                    cells,
+                    |value| value.parse().ok(),
+                    error,
+                )?)),
+            ),
+            Kind::Decimal(precision, scale) => {
+                let mantissas = parsed(
+                    cells,
+                    |value| {
+                        let mut decimal: Decimal = value.parse().ok()?;
+                        decimal.rescale(scale as u32);
+                        Some(decimal.mantissa())
+                    },
+                    error,
+                )?;
+
+                (
+                    DataType::Decimal128(precision, scale),
+                    Arc::new(
+                        Decimal128Array::from(mantissas)
+                            .with_precision_and_scale(precision, scale)
+                            .map_err(|error| parquet_error(&error))?,
+                    ),
+                )
+            }
+            Kind::Date => (
+                DataType::Date32,
+                Arc::new(Date32Array::from(parsed(
+                    cells,
+                    |value| {
+                        let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+                        Some((date - epoch).num_days() as i32)
+                    },
+                    error,
+                )?)),
+            ),
+            Kind::Timestamp => (
+                DataType::Timestamp(TimeUnit::Microsecond, None),
+                Arc::new(TimestampMicrosecondArray::from(parsed(
+                    cells,
+                    |value| {
+                        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+                            .ok()
+                            .map(|timestamp| timestamp.and_utc().timestamp_micros())
+                    },
+                    error,
+                )?)),
+            ),
+            Kind::Text => (
+                DataType::Utf8,
+                Arc::new(StringArray::from(cells.collect::<Vec<_>>())),
+            ),
+        };
+
+        fields.push(Field::new(&column.name, data_type, true));
+        arrays.push(array);
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch =
+        RecordBatch::try_new(schema.clone(), arrays).map_err(|error| parquet_error(&error))?;
+
+    let mut writer = parquet::arrow::ArrowWriter::try_new(File::create(path)?, schema, None)
+        .map_err(|error| parquet_error(&error))?;
+    writer
+        .write(&batch)
+        .map_err(|error| parquet_error(&error))?;
+    writer.close().map_err(|error| parquet_error(&error))?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(_path: &Path, _table: &Table, _values: &Values) -> Result<(), Error> {
+    Err(Error::Invalid(
+        "parquet exports need the parquet feature of dmadms-core".to_owned(),
+    ))
+}
+
+fn checksum(path: &Path) -> Result<String, Error> {
+    let digest = Sha256::digest(std::fs::read(path)?);
+
+    Ok(digest.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+// Writes every table into a file of `dir` in the order they can be loaded in,
+// and the manifest last. The rows are read from one snapshot.
+pub fn export(client: &mut Client, dir: &Path, options: &Options) -> Result<Manifest, Error> {
+    std::fs::create_dir_all(dir)?;
+
+    let mut transaction = client
+        .build_transaction()
+        .isolation_level(IsolationLevel::RepeatableRead)
+        .read_only(true)
+        .start()?;
+
+    let exported_at: NaiveDateTime = transaction
+        .query_one("SELECT LOCALTIMESTAMP(0)", &[])?
+        .get(0);
+    let tables = tables(&mut transaction)?;
+    let conditions = conditions(&mut transaction, &tables, &options.filter)?;
+
+    let mut exported = Vec::new();
+
+    for table in &tables {
+        let values: Values = transaction
+            .query(
+                &select(table, conditions.get(&table.name), &options.anonymize),
+                &[],
+            )?
+            .iter()
+            .map(|row| (0..row.len()).map(|index| row.get(index)).collect())
+            .collect();
+
+        let file = format!("{}.{}", table.name, options.format.extension());
+        let path: PathBuf = dir.join(&file);
+
+        match options.format {
+            Format::Csv => write_csv(&path, table, &values)?,
+            Format::JsonLines => write_json_lines(&path, table, &values)?,
+            Format::Parquet => write_parquet(&path, table, &values)?,
+        }
+
+        exported.push(ExportedTable {
+            table: table.name.clone(),
+            file,
+            rows: values.len(),
+            checksum: checksum(&path)?,
+        });
+    }
+
+    transaction.commit()?;
+
+    let manifest = Manifest {
+        schema_version: schema::version(),
+        format: options.format,
+        exported_at,
+        filter: options.filter.clone(),
+        anonymized: options.anonymize.iter().copied().collect(),
+        tables: exported,
+    };
+
+    let mut writer = BufWriter::new(File::create(dir.join(MANIFEST))?);
+    serde_json::to_writer_pretty(&mut writer, &manifest.to_json()).map_err(std::io::Error::from)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+
+    Ok(manifest)
+}
@@ -0,0 +1,630 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use postgres::Client;
+
+use crate::error::Error;
+use crate::geocoding;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Greedy,
+    FairShare,
+    MinWarehouses,
+    Nearest,
+}
+
+impl Strategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Greedy => "greedy",
+            Strategy::FairShare => "fair-share",
+            Strategy::MinWarehouses => "min-warehouses",
+            Strategy::Nearest => "nearest",
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "greedy" => Ok(Strategy::Greedy),
+            "fair-share" => Ok(Strategy::FairShare),
+            "min-warehouses" => Ok(Strategy::MinWarehouses),
+            "nearest" => Ok(Strategy::Nearest),
+            _ => Err(format!(
+                "unknown strategy '{s}', expected greedy, fair-share, min-warehouses or nearest"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Requirement {
+    pub product_requirement_id: i32,
+    pub client_address_id: i32,
+    pub product_id: i32,
+    pub count: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stock {
+    pub warehouse_id: i32,
+    pub product_id: i32,
+    pub count: i32,
+}
+
+// Open requirements and free stock, i.e. what is left after already committed allocations
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    pub requirements: Vec<Requirement>,
+    pub stock: Vec<Stock>,
+    // (client_address_id, warehouse_id) -> kilometers, only for geocoded addresses
+    pub distances: HashMap<(i32, i32), f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allocation {
+    pub product_requirement_id: i32,
+    pub client_address_id: i32,
+    pub product_id: i32,
+    pub warehouse_id: i32,
+    pub count: i32,
+    pub distance_km: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shortfall {
+    pub product_id: i32,
+    pub required: i64,
+    pub allocated: i64,
+}
+
+impl Shortfall {
+    pub fn missing(&self) -> i64 {
+        self.required - self.allocated
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub strategy: Strategy,
+    pub allocations: Vec<Allocation>,
+    pub shortfalls: Vec<Shortfall>,
+}
+
+impl Inventory {
+    pub fn load(client: &mut Client) -> Result<Inventory, postgres::Error> {
+        let requirements = client
+            .query(
+                "
+                SELECT
+                    product_requirements.product_requirement_id,
+                    product_requirements.client_address_id,
+                    product_requirements.product_id,
+                    (product_requirements.count - COALESCE(SUM(allocations.count), 0))::INTEGER as count
+                FROM product_requirements
+                INNER JOIN products
+                ON product_requirements.product_id = products.product_id
+                INNER JOIN client_addresses
+                ON product_requirements.client_address_id = client_addresses.client_address_id
+                INNER JOIN clients
+                ON client_addresses.client_id = clients.client_id
+                LEFT JOIN allocations
+                ON product_requirements.product_requirement_id = allocations.product_requirement_id
+                WHERE products.deleted_at IS NULL AND clients.deleted_at IS NULL
+                GROUP BY product_requirements.product_requirement_id
+                HAVING product_requirements.count - COALESCE(SUM(allocations.count), 0) > 0
+                ORDER BY product_requirements.product_requirement_id
+                ",
+                &[],
+            )?
+            .iter()
+            .map(|row| Requirement {
+                product_requirement_id: row.get("product_requirement_id"),
+                client_address_id: row.get("client_address_id"),
+                product_id: row.get("product_id"),
+                count: row.get("count"),
+            })
+            .collect();
+
+        let stock = client
+            .query(
+                "
+                SELECT
+                    locations.warehouse_id,
+                    locations.product_id,
+                    (locations.count - COALESCE(reserved.count, 0))::INTEGER as count
+                FROM (
+                    SELECT product_locations.warehouse_id, product_locations.product_id, SUM(product_locations.count) as count
+                    FROM product_locations
+                    INNER JOIN warehouses
+                    ON product_locations.warehouse_id = warehouses.warehouse_id
+                    INNER JOIN products
+                    ON product_locations.product_id = products.product_id
+                    WHERE warehouses.deleted_at IS NULL AND products.deleted_at IS NULL
+                    GROUP BY product_locations.warehouse_id, product_locations.product_id
+                ) as locations
+                LEFT JOIN (
+                    SELECT allocations.warehouse_id, product_requirements.product_id, SUM(allocations.count) as count
+                    FROM allocations
+                    INNER JOIN product_requirements
+                    ON allocations.product_requirement_id = product_requirements.product_requirement_id
+                    GROUP BY allocations.warehouse_id, product_requirements.product_id
+                ) as reserved
+                ON locations.warehouse_id = reserved.warehouse_id
+                AND locations.product_id = reserved.product_id
+                WHERE locations.count - COALESCE(reserved.count, 0) > 0
+                ORDER BY locations.warehouse_id, locations.product_id
+                ",
+                &[],
+            )?
+            .iter()
+            .map(|row| Stock {
+                warehouse_id: row.get("warehouse_id"),
+                product_id: row.get("product_id"),
+                count: row.get("count"),
+            })
+            .collect();
+
+        let distances = geocoding::distances(client)?;
+
+        Ok(Inventory {
+            requirements,
+            stock,
+            distances,
+        })
+    }
+}
+
+struct Planner<'a> {
+    distances: &'a HashMap<(i32, i32), f64>,
+    nearest_first: bool,
+    // (warehouse_id, product_id) -> free count
+    available: HashMap<(i32, i32), i32>,
+    // product_id -> warehouse ids holding it
+    warehouses: HashMap<i32, Vec<i32>>,
+    allocations: Vec<Allocation>,
+}
+
+impl<'a> Planner<'a> {
+    fn new(inventory: &'a Inventory, nearest_first: bool) -> Planner<'a> {
+        let mut available = HashMap::new();
+        let mut warehouses: HashMap<i32, Vec<i32>> = HashMap::new();
+
+        for item in inventory.stock.iter().filter(|item| item.count > 0) {
+            *available
+                .entry((item.warehouse_id, item.product_id))
+                .or_insert(0) += item.count;
+            let ids = warehouses.entry(item.product_id).or_default();
+            if !ids.contains(&item.warehouse_id) {
+                ids.push(item.warehouse_id);
+            }
+        }
+
+        Planner {
+            distances: &inventory.distances,
+            nearest_first,
+            available,
+            warehouses,
+            allocations: Vec::new(),
+        }
+    }
+
+    fn available(&self, warehouse_id: i32, product_id: i32) -> i32 {
+        self.available
+            .get(&(warehouse_id, product_id))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn distance(&self, client_address_id: i32, warehouse_id: i32) -> Option<f64> {
+        self.distances
+            .get(&(client_address_id, warehouse_id))
+            .copied()
+    }
+
+    fn take(&mut self, requirement: &Requirement, warehouse_id: i32, count: i32) -> i32 {
+        let count = count.min(self.available(warehouse_id, requirement.product_id));
+
+        if count > 0 {
+            *self
+                .available
+                .get_mut(&(warehouse_id, requirement.product_id))
+                .unwrap() -= count;
+
+            self.allocations.push(Allocation {
+                product_requirement_id: requirement.product_requirement_id,
+                client_address_id: requirement.client_address_id,
+                product_id: requirement.product_id,
+                warehouse_id,
+                count,
+                distance_km: self.distance(requirement.client_address_id, warehouse_id),
+            });
+        }
+
+        count.max(0)
+    }
+
+    // Fills up to `count` units from the fullest warehouses first, or from the
+    // nearest ones when planning by distance (not geocoded warehouses go last)
+    fn fill(&mut self, requirement: &Requirement, count: i32) {
+        let mut warehouse_ids = self
+            .warehouses
+            .get(&requirement.product_id)
+            .cloned()
+            .unwrap_or_default();
+
+        warehouse_ids.sort_by_key(|&warehouse_id| {
+            (
+                -self.available(warehouse_id, requirement.product_id),
+                warehouse_id,
+            )
+        });
+
+        if self.nearest_first {
+            warehouse_ids.sort_by(|a, b| {
+                let distance = |warehouse_id| {
+                    self.distance(requirement.client_address_id, warehouse_id)
+                        .unwrap_or(f64::INFINITY)
+                };
+                distance(*a).total_cmp(&distance(*b))
+            });
+        }
+
+        let mut left = count;
+        for warehouse_id in warehouse_ids {
+            if left == 0 {
+                break;
+            }
+            left -= self.take(requirement, warehouse_id, left);
+        }
+    }
+
+    fn total_available(&self, product_id: i32) -> i64 {
+        self.warehouses
+            .get(&product_id)
+            .map(|ids| {
+                ids.iter()
+                    .map(|&warehouse_id| self.available(warehouse_id, product_id) as i64)
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+
+    fn greedy(&mut self, requirements: &[Requirement]) {
+        let mut requirements: Vec<&Requirement> = requirements.iter().collect();
+        requirements.sort_by_key(|r| (-r.count, r.product_requirement_id));
+
+        for requirement in requirements {
+            self.fill(requirement, requirement.count);
+        }
+    }
+
+    // Every requirement of a product gets the same share of the free stock,
+    // leftover units go to the largest remainders
+    fn fair_share(&mut self, requirements: &[Requirement]) {
+        for (product_id, requirements) in by_product(requirements) {
+            let required: i64 = requirements.iter().map(|r| r.count as i64).sum();
+            let available = self.total_available(product_id);
+
+            if available >= required {
+                for requirement in requirements {
+                    self.fill(requirement, requirement.count);
+                }
+                continue;
+            }
+
+            let mut shares: Vec<(&Requirement, i64, i64)> = requirements
+                .iter()
+                .map(|r| {
+                    let scaled = r.count as i64 * available;
+                    (*r, scaled / required, scaled % required)
+                })
+                .collect();
+
+            let mut leftover = available - shares.iter().map(|share| share.1).sum::<i64>();
+            shares.sort_by_key(|share| (-share.2, share.0.product_requirement_id));
+            for share in shares.iter_mut() {
+                if leftover == 0 {
+                    break;
+                }
+                share.1 += 1;
+                leftover -= 1;
+            }
+
+            shares.sort_by_key(|share| share.0.product_requirement_id);
+            for (requirement, share, _) in shares {
+                self.fill(requirement, share as i32);
+            }
+        }
+    }
+
+    // For every address repeatedly picks the warehouse covering the most of
+    // what is still missing there, so an address is served by few warehouses
+    fn min_warehouses(&mut self, requirements: &[Requirement]) {
+        let mut addresses: Vec<i32> = requirements.iter().map(|r| r.client_address_id).collect();
+        addresses.sort_unstable();
+        addresses.dedup();
+
+        for client_address_id in addresses {
+            let mut missing: Vec<(&Requirement, i32)> = requirements
+                .iter()
+                .filter(|r| r.client_address_id == client_address_id)
+                .map(|r| (r, r.count))
+                .collect();
+
+            let mut candidates: Vec<i32> = missing
+                .iter()
+                .flat_map(|(r, _)| {
+                    self.warehouses
+                        .get(&r.product_id)
+                        .cloned()
+                        .unwrap_or_default()
+                })
+                .collect();
+            candidates.sort_unstable();
+            candidates.dedup();
+
+            loop {
+                let best = candidates
+                    .iter()
+                    .map(|&warehouse_id| {
+                        let covered: i64 = missing
+                            .iter()
+                            .map(|(r, left)| {
+                                (*left).min(self.available(warehouse_id, r.product_id)) as i64
+                            })
+                            .sum();
+                        (covered, warehouse_id)
+                    })
+                    .max_by_key(|&(covered, warehouse_id)| (covered, -warehouse_id));
+
+                match best {
+                    Some((covered, warehouse_id)) if covered > 0 => {
+                        for (requirement, left) in missing.iter_mut() {
+                            *left -= self.take(requirement, warehouse_id, *left);
+                        }
+                        missing.retain(|(_, left)| *left > 0);
+                    }
+                    _ => break,
+                }
+            }
+        }
+    }
+}
+
+fn by_product(requirements: &[Requirement]) -> Vec<(i32, Vec<&Requirement>)> {
+    let mut groups: HashMap<i32, Vec<&Requirement>> = HashMap::new();
+    for requirement in requirements {
+        groups
+            .entry(requirement.product_id)
+            .or_default()
+            .push(requirement);
+    }
+
+    let mut groups: Vec<(i32, Vec<&Requirement>)> = groups.into_iter().collect();
+    groups.sort_by_key(|(product_id, _)| *product_id);
+    groups
+}
+
+pub fn plan(inventory: &Inventory, strategy: Strategy) -> Plan {
+    let requirements: Vec<Requirement> = inventory
+        .requirements
+        .iter()
+        .filter(|r| r.count > 0)
+        .cloned()
+        .collect();
+
+    let mut planner = Planner::new(inventory, strategy == Strategy::Nearest);
+
+    match strategy {
+        Strategy::Greedy | Strategy::Nearest => planner.greedy(&requirements),
+        Strategy::FairShare => planner.fair_share(&requirements),
+        Strategy::MinWarehouses => planner.min_warehouses(&requirements),
+    }
+
+    let mut allocations = planner.allocations;
+    allocations.sort_by_key(|a| (a.client_address_id, a.product_id, a.warehouse_id));
+
+    let mut shortfalls = Vec::new();
+    for (product_id, requirements) in by_product(&requirements) {
+        let required: i64 = requirements.iter().map(|r| r.count as i64).sum();
+        let allocated: i64 = allocations
+            .iter()
+            .filter(|a| a.product_id == product_id)
+            .map(|a| a.count as i64)
+            .sum();
+
+        if allocated < required {
+            shortfalls.push(Shortfall {
+                product_id,
+                required,
+                allocated,
+            });
+        }
+    }
+
+    Plan {
+        strategy,
+        allocations,
+        shortfalls,
+    }
+}
+
+// Stores the allocations of a plan. Stock and requirements are locked and
+// checked again, another plan may have taken them since the inventory was
+// loaded; nothing is stored then.
+pub fn commit_plan(client: &mut Client, plan: &Plan) -> Result<i32, Error> {
+    let mut transaction = client.transaction()?;
+
+    // locked in the same order by every plan
+    let mut stock: BTreeMap<(i32, i32), i64> = BTreeMap::new();
+    let mut requirements: BTreeMap<i32, i64> = BTreeMap::new();
+    for allocation in &plan.allocations {
+        *stock
+            .entry((allocation.warehouse_id, allocation.product_id))
+            .or_default() += allocation.count as i64;
+        *requirements
+            .entry(allocation.product_requirement_id)
+            .or_default() += allocation.count as i64;
+    }
+
+    for ((warehouse_id, product_id), count) in stock {
+        let stocked: i64 = transaction
+            .query_opt(
+                "
+                SELECT count
+                FROM product_locations
+                WHERE warehouse_id = $1 AND product_id = $2
+                FOR UPDATE
+                ",
+                &[&warehouse_id, &product_id],
+            )?
+            .map(|row| row.get::<_, i32>("count") as i64)
+            .unwrap_or(0);
+        let allocated: i64 = transaction
+            .query_one(
+                "
+                SELECT COALESCE(SUM(allocations.count), 0)
+                FROM allocations
+                INNER JOIN product_requirements
+                ON allocations.product_requirement_id = product_requirements.product_requirement_id
+                WHERE allocations.warehouse_id = $1 AND product_requirements.product_id = $2
+                ",
+                &[&warehouse_id, &product_id],
+            )?
+            .get(0);
+        let free = stocked - allocated;
+
+        if free < count {
+            return Err(Error::Conflict(format!(
+                "warehouse {warehouse_id} has {free} of product {product_id} free, \
+                 the plan allocates {count}, plan again"
+            )));
+        }
+    }
+
+    for (product_requirement_id, count) in requirements {
+        let required: i64 = transaction
+            .query_opt(
+                "
+                SELECT count
+                FROM product_requirements
+                WHERE product_requirement_id = $1
+                FOR UPDATE
+                ",
+                &[&product_requirement_id],
+            )?
+            .map(|row| row.get::<_, i32>("count") as i64)
+            .unwrap_or(0);
+        let allocated: i64 = transaction
+            .query_one(
+                "
+                SELECT COALESCE(SUM(count), 0)
+                FROM allocations
+                WHERE product_requirement_id = $1
+                ",
+                &[&product_requirement_id],
+            )?
+            .get(0);
+        let open = required - allocated;
+
+        if open < count {
+            return Err(Error::Conflict(format!(
+                "requirement {product_requirement_id} has {open} left to allocate, \
+                 the plan allocates {count}, plan again"
+            )));
+        }
+    }
+
+    let fulfillment_plan_id: i32 = transaction
+        .query_one(
+            "
+            INSERT INTO fulfillment_plans (strategy)
+            VALUES ($1)
+            RETURNING fulfillment_plan_id
+            ",
+            &[&plan.strategy.name()],
+        )?
+        .get("fulfillment_plan_id");
+
+    for allocation in &plan.allocations {
+        transaction.execute(
+            "
+            INSERT INTO allocations (fulfillment_plan_id, product_requirement_id, warehouse_id, count)
+            VALUES ($1, $2, $3, $4)
+            ",
+            &[
+                &fulfillment_plan_id,
+                &allocation.product_requirement_id,
+                &allocation.warehouse_id,
+                &allocation.count,
+            ],
+        )?;
+    }
+
+    transaction.commit()?;
+
+    Ok(fulfillment_plan_id)
+}
+
+pub fn print_plan(plan: &Plan) {
+    println!(
+        "
+Fulfillment plan ({})
+{:15} {:10} {:10} {:10} Distance, km
+",
+        plan.strategy, "Address", "Product", "Warehouse", "Count"
+    );
+
+    for allocation in &plan.allocations {
+        let distance = allocation
+            .distance_km
+            .map(|km| format!("{km:.1}"))
+            .unwrap_or_else(|| "-".to_owned());
+
+        println!(
+            "{:<15} {:<10} {:<10} {:<10} {distance}",
+            allocation.client_address_id,
+            allocation.product_id,
+            allocation.warehouse_id,
+            allocation.count
+        );
+    }
+
+    println!(
+        "
+Shortfalls
+{:10} {:10} {:10} Missing
+",
+        "Product", "Required", "Allocated"
+    );
+
+    for shortfall in &plan.shortfalls {
+        println!(
+            "{:<10} {:<10} {:<10} {}",
+            shortfall.product_id,
+            shortfall.required,
+            shortfall.allocated,
+            shortfall.missing()
+        );
+    }
+
+    let allocated: i64 = plan.allocations.iter().map(|a| a.count as i64).sum();
+    let missing: i64 = plan.shortfalls.iter().map(Shortfall::missing).sum();
+    println!();
+    println!(
+        "{} allocations, {allocated} units allocated, {missing} units missing",
+        plan.allocations.len()
+    );
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use postgres::{Client, Transaction};
+
+use crate::error::Error;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+#[derive(Debug, Clone)]
+pub struct Place {
+    pub address: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GeocodeSummary {
+    pub warehouses: usize,
+    pub client_addresses: usize,
+    pub unmatched: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NearestWarehouse {
+    pub warehouse_id: i32,
+    pub address: String,
+    pub distance_km: f64,
+}
+
+// Gazetteer is a CSV file with `address,latitude,longitude` columns
+pub fn read_gazetteer<R: Read>(reader: R) -> Result<Vec<Place>, Error> {
+    let mut places = Vec::new();
+
+    for (line, record) in csv::Reader::from_reader(reader).records().enumerate() {
+        let record = record?;
+        let invalid = || Error::Invalid(format!("invalid gazetteer entry on line {}", line + 2));
+
+        let coordinate = |index: usize, limit: f64| {
+            record
+                .get(index)
+                .and_then(|s| s.trim().parse::<f64>().ok())
+                .filter(|degrees| degrees.abs() <= limit)
+        };
+
+        let address = record.get(0).filter(|address| !address.trim().is_empty());
+
+        match (address, coordinate(1, 90.0), coordinate(2, 180.0)) {
+            (Some(address), Some(latitude), Some(longitude)) => places.push(Place {
+                address: address.to_owned(),
+                latitude,
+                longitude,
+            }),
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(places)
+}
+
+// Addresses are free text, so matching ignores case, punctuation and extra spaces
+pub fn normalize(address: &str) -> String {
+    address
+        .split(|c: char| c.is_whitespace() || c == ',' || c == '.')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) const SELECT_WAREHOUSES: &str = "SELECT warehouse_id, address FROM warehouses";
+
+pub(crate) const UPDATE_WAREHOUSE: &str = "
+    UPDATE warehouses
+    SET latitude = $2, longitude = $3
+    WHERE warehouse_id = $1
+    ";
+
+pub(crate) const SELECT_CLIENT_ADDRESSES: &str =
+    "SELECT client_address_id, address FROM client_addresses";
+
+pub(crate) const UPDATE_CLIENT_ADDRESS: &str = "
+    UPDATE client_addresses
+    SET latitude = $2, longitude = $3
+    WHERE client_address_id = $1
+    ";
+
+// Gazetteer places by normalized address
+pub(crate) fn index(places: &[Place]) -> HashMap<String, &Place> {
+    places
+        .iter()
+        .map(|place| (normalize(&place.address), place))
+        .collect()
+}
+
+// Places for the (id, address) rows found in the gazetteer, the addresses of
+// the others are added to `unmatched` once
+pub(crate) fn locate<'a>(
+    gazetteer: &HashMap<String, &'a Place>,
+    rows: Vec<(i32, String)>,
+    unmatched: &mut Vec<String>,
+) -> Vec<(i32, &'a Place)> {
+    let mut located = Vec::new();
+
+    for (id, address) in rows {
+        if let Some(place) = gazetteer.get(&normalize(&address)) {
+            located.push((id, *place));
+        } else if !unmatched.contains(&address) {
+            unmatched.push(address);
+        }
+    }
+
+    located
+}
+
+fn geocode_table(
+    transaction: &mut Transaction,
+    gazetteer: &HashMap<String, &Place>,
+    select: &str,
+    update: &str,
+    unmatched: &mut Vec<String>,
+) -> Result<usize, postgres::Error> {
+    let rows = transaction
+        .query(select, &[])?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+
+    let located = locate(gazetteer, rows, unmatched);
+
+    for (id, place) in &located {
+        transaction.execute(update, &[id, &place.latitude, &place.longitude])?;
+    }
+
+    Ok(located.len())
+}
+
+pub fn import_gazetteer(
+    client: &mut Client,
+    places: &[Place],
+) -> Result<GeocodeSummary, postgres::Error> {
+    let gazetteer = index(places);
+    let mut summary = GeocodeSummary::default();
+    let mut transaction = client.transaction()?;
+
+    summary.warehouses = geocode_table(
+        &mut transaction,
+        &gazetteer,
+        SELECT_WAREHOUSES,
+        UPDATE_WAREHOUSE,
+        &mut summary.unmatched,
+    )?;
+    summary.client_addresses = geocode_table(
+        &mut transaction,
+        &gazetteer,
+        SELECT_CLIENT_ADDRESSES,
+        UPDATE_CLIENT_ADDRESS,
+        &mut summary.unmatched,
+    )?;
+
+    transaction.commit()?;
+
+    Ok(summary)
+}
+
+pub fn postgis_available(client: &mut Client) -> Result<bool, postgres::Error> {
+    Ok(client
+        .query_one(
+            "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'postgis')",
+            &[],
+        )?
+        .get(0))
+}
+
+// SQL expression for the distance in kilometers between `client_addresses` and `warehouses`
+fn distance_sql(postgis: bool) -> String {
+    if postgis {
+        "
+        ST_DistanceSphere(
+            ST_MakePoint(client_addresses.longitude, client_addresses.latitude),
+            ST_MakePoint(warehouses.longitude, warehouses.latitude)
+        ) / 1000
+        "
+        .to_owned()
+    } else {
+        format!(
+            "
+            2 * {EARTH_RADIUS_KM} * asin(LEAST(1, sqrt(
+                power(sin(radians(warehouses.latitude - client_addresses.latitude) / 2), 2)
+                + cos(radians(client_addresses.latitude))
+                * cos(radians(warehouses.latitude))
+                * power(sin(radians(warehouses.longitude - client_addresses.longitude) / 2), 2)
+            )))
+            "
+        )
+    }
+}
+
+pub fn nearest_warehouses(
+    client: &mut Client,
+    client_address_id: i32,
+    limit: i64,
+) -> Result<Vec<NearestWarehouse>, postgres::Error> {
+    let distance = distance_sql(postgis_available(client)?);
+
+    Ok(client
+        .query(
+            &format!(
+                "
+                SELECT warehouses.warehouse_id, warehouses.address, ({distance})::FLOAT8 as distance_km
+                FROM client_addresses
+                CROSS JOIN warehouses
+                WHERE client_addresses.client_address_id = $1
+                AND client_addresses.latitude IS NOT NULL
+                AND warehouses.latitude IS NOT NULL
+                AND warehouses.deleted_at IS NULL
+                ORDER BY distance_km, warehouses.warehouse_id
+                LIMIT $2
+                "
+            ),
+            &[&client_address_id, &limit],
+        )?
+        .iter()
+        .map(|row| NearestWarehouse {
+            warehouse_id: row.get("warehouse_id"),
+            address: row.get("address"),
+            distance_km: row.get("distance_km"),
+        })
+        .collect())
+}
+
+// (client_address_id, warehouse_id) -> kilometers, for every geocoded pair
+pub fn distances(client: &mut Client) -> Result<HashMap<(i32, i32), f64>, postgres::Error> {
+    let distance = distance_sql(postgis_available(client)?);
+
+    Ok(client
+        .query(
+            &format!(
+                "
+                SELECT client_addresses.client_address_id, warehouses.warehouse_id, ({distance})::FLOAT8 as distance_km
+                FROM client_addresses
+                CROSS JOIN warehouses
+                WHERE client_addresses.latitude IS NOT NULL
+                AND warehouses.latitude IS NOT NULL
+                AND warehouses.deleted_at IS NULL
+                "
+            ),
+            &[],
+        )?
+        .iter()
+        .map(|row| {
+            (
+                (row.get("client_address_id"), row.get("warehouse_id")),
+                row.get("distance_km"),
+            )
+        })
+        .collect())
+}
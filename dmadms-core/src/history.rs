@@ -0,0 +1,321 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use postgres::Client;
+
+use crate::error::Error;
+
+// Tables whose every version is kept in `<table>_history` by a trigger. A
+// version is valid from `valid_from` until `valid_to`, the current one has no
+// `valid_to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    Product,
+    Supplier,
+    SupplierProduct,
+    Client,
+    ClientAddress,
+    Category,
+}
+
+impl Table {
+    pub const ALL: [Table; 6] = [
+        Table::Product,
+        Table::Supplier,
+        Table::SupplierProduct,
+        Table::Client,
+        Table::ClientAddress,
+        Table::Category,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Table::Product => "product",
+            Table::Supplier => "supplier",
+            Table::SupplierProduct => "offering",
+            Table::Client => "client",
+            Table::ClientAddress => "address",
+            Table::Category => "category",
+        }
+    }
+
+    pub fn table(self) -> &'static str {
+        match self {
+            Table::Product => "products",
+            Table::Supplier => "suppliers",
+            Table::SupplierProduct => "supplier_products",
+            Table::Client => "clients",
+            Table::ClientAddress => "client_addresses",
+            Table::Category => "categories",
+        }
+    }
+
+    fn id_column(self) -> &'static str {
+        match self {
+            Table::Product => "product_id",
+            Table::Supplier => "supplier_id",
+            Table::SupplierProduct => "supplier_product_id",
+            Table::Client => "client_id",
+            Table::ClientAddress => "client_address_id",
+            Table::Category => "category_id",
+        }
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for Table {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Table::ALL
+            .into_iter()
+            .find(|table| table.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown entity '{s}', expected product, supplier, offering, client, address or category"
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Version {
+    pub valid_from: NaiveDateTime,
+    pub valid_to: Option<NaiveDateTime>,
+    // column name and value as text, NULL values are `None`
+    pub fields: Vec<(String, Option<String>)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProductSnapshot {
+    pub product: Version,
+    pub category: Option<String>,
+    pub suppliers: Vec<String>,
+}
+
+// Closes the current version of a changed or deleted row and records the new one.
+// It runs as the owner, the roles changing the rows can't write history.
+// Versions change at the time of the statement rather than the start of its
+// transaction, a transaction older than the current version still ends it
+// after it began.
+pub(crate) const RECORD_HISTORY: &str = "
+        CREATE OR REPLACE FUNCTION record_history() RETURNS TRIGGER
+        LANGUAGE plpgsql SECURITY DEFINER SET search_path FROM CURRENT AS $$
+        DECLARE
+            changed_at TIMESTAMP := clock_timestamp();
+        BEGIN
+            IF TG_OP = 'UPDATE' AND OLD IS NOT DISTINCT FROM NEW THEN
+                RETURN NULL;
+            END IF;
+
+            IF TG_OP IN ('UPDATE', 'DELETE') THEN
+                EXECUTE format(
+                    'UPDATE %I SET valid_to = GREATEST(valid_from, $2) WHERE %I = ($1).%I AND valid_to IS NULL',
+                    TG_TABLE_NAME || '_history', TG_ARGV[0], TG_ARGV[0]
+                ) USING OLD, changed_at;
+            END IF;
+
+            IF TG_OP IN ('INSERT', 'UPDATE') THEN
+                EXECUTE format(
+                    'INSERT INTO %I SELECT ($1).*, $2, NULL',
+                    TG_TABLE_NAME || '_history'
+                ) USING NEW, changed_at;
+            END IF;
+
+            RETURN NULL;
+        END
+        $$;
+        ";
+
+pub fn table_statements() -> Vec<String> {
+    [RECORD_HISTORY.to_owned()]
+        .into_iter()
+        .chain(Table::ALL.into_iter().map(table_statement))
+        .collect()
+}
+
+// The history table of one table and its trigger, `record_history()` has to
+// exist
+pub(crate) fn table_statement(table: Table) -> String {
+    format!(
+        "
+        CREATE TABLE {name}_history (
+            LIKE {name},
+            valid_from  TIMESTAMP NOT NULL,
+            valid_to    TIMESTAMP, -- NULLABLE, the current version
+            CHECK (valid_to IS NULL OR valid_to >= valid_from)
+        );
+
+        CREATE INDEX {name}_history_index
+        ON {name}_history({id_column}, valid_from);
+
+        CREATE TRIGGER {name}_history_trigger
+        AFTER INSERT OR UPDATE OR DELETE ON {name}
+        FOR EACH ROW EXECUTE FUNCTION record_history('{id_column}');
+        ",
+        name = table.table(),
+        id_column = table.id_column()
+    )
+}
+
+// Accepts `2022-12-31`, `2022-12-31 23:59:59` and `2022-12-31T23:59:59`
+pub fn parse_timestamp(s: &str) -> Option<NaiveDateTime> {
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+fn query_versions(
+    client: &mut Client,
+    table: Table,
+    id: i32,
+    at: Option<NaiveDateTime>,
+) -> Result<Vec<Version>, Error> {
+    let rows = client.query(
+        &format!(
+            "
+            SELECT
+                history.valid_from,
+                history.valid_to,
+                array_agg(fields.key ORDER BY fields.key) as keys,
+                array_agg(fields.value ORDER BY fields.key) as values
+            FROM {table}_history as history
+            CROSS JOIN LATERAL jsonb_each_text(to_jsonb(history) - 'valid_from' - 'valid_to') as fields
+            WHERE history.{id_column} = $1
+            AND ($2::TIMESTAMP IS NULL OR (
+                history.valid_from <= $2 AND (history.valid_to IS NULL OR history.valid_to > $2)
+            ))
+            GROUP BY history.ctid, history.valid_from, history.valid_to
+            ORDER BY history.valid_from, history.valid_to NULLS LAST
+            ",
+            table = table.table(),
+            id_column = table.id_column()
+        ),
+        &[&id, &at],
+    )?;
+
+    Ok(rows
+        .iter()
+        .map(|row| Version {
+            valid_from: row.get("valid_from"),
+            valid_to: row.get("valid_to"),
+            fields: row
+                .get::<_, Vec<String>>("keys")
+                .into_iter()
+                .zip(row.get::<_, Vec<Option<String>>>("values"))
+                .collect(),
+        })
+        .collect())
+}
+
+// Every version of a row, oldest first
+pub fn versions(client: &mut Client, table: Table, id: i32) -> Result<Vec<Version>, Error> {
+    let versions = query_versions(client, table, id, None)?;
+
+    if versions.is_empty() {
+        return Err(Error::NotFound(format!("history of {table} {id}")));
+    }
+
+    Ok(versions)
+}
+
+// The version of a row that was valid at a moment
+pub fn as_of(
+    client: &mut Client,
+    table: Table,
+    id: i32,
+    at: NaiveDateTime,
+) -> Result<Version, Error> {
+    query_versions(client, table, id, Some(at))?
+        .pop()
+        .ok_or_else(|| Error::NotFound(format!("{table} {id} as of {at}")))
+}
+
+// A product with the path of its category and its active suppliers as they
+// were at a moment
+pub fn product_as_of(
+    client: &mut Client,
+    product_id: i32,
+    at: NaiveDateTime,
+) -> Result<ProductSnapshot, Error> {
+    let product = as_of(client, Table::Product, product_id, at)?;
+
+    let category = client
+        .query_one(
+            "
+            WITH RECURSIVE categories_at AS (
+                SELECT category_id, parent_id, name
+                FROM categories_history
+                WHERE valid_from <= $2 AND (valid_to IS NULL OR valid_to > $2)
+            ), path AS (
+                SELECT categories_at.*, 0 as depth
+                FROM categories_at
+                WHERE category_id = (
+                    SELECT category_id
+                    FROM products_history
+                    WHERE product_id = $1
+                    AND valid_from <= $2 AND (valid_to IS NULL OR valid_to > $2)
+                )
+                UNION ALL
+                SELECT categories_at.*, path.depth + 1
+                FROM categories_at
+                INNER JOIN path
+                ON categories_at.category_id = path.parent_id
+            )
+            SELECT string_agg(name, ' / ' ORDER BY depth DESC) as path
+            FROM path
+            ",
+            &[&product_id, &at],
+        )?
+        .get("path");
+
+    let suppliers = client
+        .query(
+            "
+            SELECT suppliers_history.name
+            FROM supplier_products_history
+            INNER JOIN suppliers_history
+            ON supplier_products_history.supplier_id = suppliers_history.supplier_id
+            WHERE supplier_products_history.product_id = $1
+            AND supplier_products_history.active
+            AND supplier_products_history.valid_from <= $2
+            AND (supplier_products_history.valid_to IS NULL OR supplier_products_history.valid_to > $2)
+            AND suppliers_history.deleted_at IS NULL
+            AND suppliers_history.valid_from <= $2
+            AND (suppliers_history.valid_to IS NULL OR suppliers_history.valid_to > $2)
+            ORDER BY suppliers_history.name
+            ",
+            &[&product_id, &at],
+        )?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect();
+
+    Ok(ProductSnapshot {
+        product,
+        category,
+        suppliers,
+    })
+}
+
+pub fn print_version(version: &Version) {
+    match version.valid_to {
+        Some(valid_to) => println!("valid from {} to {valid_to}", version.valid_from),
+        None => println!("valid from {}, current", version.valid_from),
+    }
+
+    for (key, value) in &version.fields {
+        println!("    {key:25} {}", value.as_deref().unwrap_or("NULL"));
+    }
+}
@@ -0,0 +1,679 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use postgres::{Client, Transaction};
+
+use crate::error::Error;
+use crate::{categories, search, units, validation};
+
+// What a file holds, one entity per file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entity {
+    Suppliers,
+    Products,
+    ClientAddresses,
+    Requirements,
+    Stock,
+}
+
+impl Entity {
+    pub const ALL: [Entity; 5] = [
+        Entity::Suppliers,
+        Entity::Products,
+        Entity::ClientAddresses,
+        Entity::Requirements,
+        Entity::Stock,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Entity::Suppliers => "suppliers",
+            Entity::Products => "products",
+            Entity::ClientAddresses => "addresses",
+            Entity::Requirements => "requirements",
+            Entity::Stock => "stock",
+        }
+    }
+
+    // columns every row needs, the natural key first
+    pub fn required_columns(self) -> &'static [&'static str] {
+        match self {
+            Entity::Suppliers => &["name", "country", "email"],
+            Entity::Products => &["name", "category"],
+            Entity::ClientAddresses => &["client", "address"],
+            Entity::Requirements => &["client", "address", "product", "count"],
+            Entity::Stock => &["warehouse", "product", "count"],
+        }
+    }
+
+    pub fn optional_columns(self) -> &'static [&'static str] {
+        match self {
+            Entity::Suppliers => &[],
+            Entity::Products => &["supplier", "sku", "lead_time_days", "min_order_quantity"],
+            Entity::ClientAddresses => &["latitude", "longitude"],
+            Entity::Requirements | Entity::Stock => &["unit"],
+        }
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for Entity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Entity::ALL
+            .into_iter()
+            .find(|entity| entity.name() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown entity '{s}', expected suppliers, products, addresses, requirements or stock"
+                )
+            })
+    }
+}
+
+// A row of a file by its column names, lowercase with underscores for spaces
+#[derive(Debug, Clone, Default)]
+pub struct Record {
+    // line of a CSV file or row of a sheet, the header is line 1
+    pub line: usize,
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Record {
+    fn optional(&self, column: &str) -> Option<&str> {
+        self.fields
+            .get(column)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn required(&self, column: &str) -> Result<&str, Error> {
+        self.optional(column)
+            .ok_or_else(|| Error::Invalid(format!("{column} is empty")))
+    }
+
+    fn parsed<T: FromStr>(&self, column: &str, value: &str) -> Result<T, Error> {
+        value
+            .parse()
+            .map_err(|_| Error::Invalid(format!("{column} '{value}' is not a number")))
+    }
+
+    fn number<T: FromStr>(&self, column: &str) -> Result<T, Error> {
+        self.parsed(column, self.required(column)?)
+    }
+
+    fn optional_number<T: FromStr>(&self, column: &str) -> Result<Option<T>, Error> {
+        self.optional(column)
+            .map(|value| self.parsed(column, value))
+            .transpose()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// Rows are committed only when none of them failed and it isn't a dry run
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    pub inserted: usize,
+    pub updated: usize,
+    pub errors: Vec<RowError>,
+    pub committed: bool,
+}
+
+enum Upserted {
+    Inserted,
+    Updated,
+}
+
+fn column_name(header: &str) -> String {
+    header.trim().to_lowercase().replace(' ', "_")
+}
+
+// Rows come with the line they were read from
+fn records(headers: Vec<String>, rows: Vec<(usize, Vec<String>)>) -> Vec<Record> {
+    let headers: Vec<String> = headers.iter().map(|header| column_name(header)).collect();
+
+    rows.into_iter()
+        .filter(|(_, row)| row.iter().any(|value| !value.trim().is_empty()))
+        .map(|(line, row)| Record {
+            line,
+            fields: headers.iter().cloned().zip(row).collect(),
+        })
+        .collect()
+}
+
+pub fn read_csv<R: Read>(mut reader: R) -> Result<Vec<Record>, Error> {
+    // read whole, the reader skips blank lines without counting them and a
+    // record's position is where the previous one ended
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let line = |byte: u64| {
+        let start = data[byte as usize..]
+            .iter()
+            .position(|&b| b != b'\n' && b != b'\r')
+            .map_or(data.len(), |offset| byte as usize + offset);
+
+        1 + data[..start].iter().filter(|&&b| b == b'\n').count()
+    };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(data.as_slice());
+
+    let headers = reader.headers()?.iter().map(str::to_owned).collect();
+    let rows = reader
+        .records()
+        .map(|record| {
+            let record = record?;
+            let line = record
+                .position()
+                .map_or(0, |position| line(position.byte()));
+
+            Ok((line, record.iter().map(str::to_owned).collect()))
+        })
+        .collect::<Result<_, Error>>()?;
+
+    Ok(records(headers, rows))
+}
+
+// First sheet of a workbook, its first row holds the column names
+#[cfg(feature = "xlsx")]
+pub fn read_workbook(path: &Path) -> Result<Vec<Record>, Error> {
+    use calamine::{open_workbook_auto, Data, Reader};
+
+    let mut workbook = open_workbook_auto(path)
+        .map_err(|error| Error::Invalid(format!("workbook error: {error}")))?;
+    let sheet = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| Error::Invalid(format!("{} has no sheets", path.display())))?
+        .map_err(|error| Error::Invalid(format!("workbook error: {error}")))?;
+
+    // the range starts at the first used row
+    let first = sheet.start().map_or(0, |(row, _)| row as usize) + 1;
+    let mut rows = sheet.rows().enumerate().map(|(index, row)| {
+        let values = row
+            .iter()
+            .map(|cell| match cell {
+                Data::Empty => String::new(),
+                cell => cell.to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        (first + index, values)
+    });
+
+    let headers = rows.next().map(|(_, row)| row).unwrap_or_default();
+
+    Ok(records(headers, rows.collect()))
+}
+
+#[cfg(not(feature = "xlsx"))]
+pub fn read_workbook(_path: &Path) -> Result<Vec<Record>, Error> {
+    Err(Error::Invalid(
+        "workbooks need the xlsx feature of dmadms-core".to_owned(),
+    ))
+}
+
+// CSV files by their extension, workbooks otherwise
+pub fn read(path: &Path) -> Result<Vec<Record>, Error> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("csv") => {
+            read_csv(std::fs::File::open(path)?)
+        }
+        _ => read_workbook(path),
+    }
+}
+
+fn id_by_name(
+    transaction: &mut Transaction,
+    what: &str,
+    query: &str,
+    name: &str,
+) -> Result<i32, Error> {
+    let rows = transaction.query(query, &[&name])?;
+
+    match rows.as_slice() {
+        [] => Err(Error::NotFound(format!("{what} '{name}'"))),
+        [row] => Ok(row.get(0)),
+        _ => Err(Error::Invalid(format!("{what} '{name}' is ambiguous"))),
+    }
+}
+
+fn country(transaction: &mut Transaction, name: &str) -> Result<i32, Error> {
+    id_by_name(
+        transaction,
+        "country",
+        "SELECT country_id FROM countries WHERE lower(name) = lower($1)",
+        name,
+    )
+}
+
+fn supplier(transaction: &mut Transaction, name: &str) -> Result<i32, Error> {
+    id_by_name(
+        transaction,
+        "supplier",
+        "SELECT supplier_id FROM suppliers WHERE lower(name) = lower($1) AND deleted_at IS NULL",
+        name,
+    )
+}
+
+fn client(transaction: &mut Transaction, name: &str) -> Result<i32, Error> {
+    id_by_name(
+        transaction,
+        "client",
+        "SELECT client_id FROM clients WHERE lower(name) = lower($1) AND deleted_at IS NULL",
+        name,
+    )
+}
+
+fn product(transaction: &mut Transaction, name: &str) -> Result<i32, Error> {
+    id_by_name(
+        transaction,
+        "product",
+        "SELECT product_id FROM products WHERE lower(name) = lower($1) AND deleted_at IS NULL",
+        name,
+    )
+}
+
+fn warehouse(transaction: &mut Transaction, address: &str) -> Result<i32, Error> {
+    id_by_name(
+        transaction,
+        "warehouse",
+        "SELECT warehouse_id FROM warehouses WHERE lower(address) = lower($1) AND deleted_at IS NULL",
+        address,
+    )
+}
+
+fn client_address(
+    transaction: &mut Transaction,
+    client_id: i32,
+    address: &str,
+) -> Result<i32, Error> {
+    transaction
+        .query_opt(
+            "
+            SELECT client_address_id
+            FROM client_addresses
+            WHERE client_id = $1 AND lower(address) = lower($2)
+            ORDER BY client_address_id
+            LIMIT 1
+            ",
+            &[&client_id, &address],
+        )?
+        .map(|row| row.get(0))
+        .ok_or_else(|| Error::NotFound(format!("address '{address}' of the client")))
+}
+
+// A category by id or path, or a subcategory by its name alone when only one
+// category has it
+fn category(transaction: &mut Transaction, category: &str) -> Result<i32, Error> {
+    match categories::resolve(transaction, category) {
+        Err(Error::NotFound(_)) => id_by_name(
+            transaction,
+            "category",
+            "SELECT category_id FROM categories WHERE lower(name) = lower($1)",
+            category,
+        ),
+        resolved => resolved,
+    }
+}
+
+// A count in base units, or in a unit of the product
+fn base_count(
+    transaction: &mut Transaction,
+    record: &Record,
+    product_id: i32,
+) -> Result<(i32, Option<String>), Error> {
+    let count: i32 = record.number("count")?;
+
+    if count < 0 {
+        return Err(Error::Invalid(format!("count {count} is negative")));
+    }
+
+    match record.optional("unit") {
+        Some(unit) => Ok((
+            units::to_base(transaction, product_id, count, unit)?,
+            Some(unit.to_owned()),
+        )),
+        None => Ok((count, None)),
+    }
+}
+
+// Suppliers by name
+fn upsert_supplier(transaction: &mut Transaction, record: &Record) -> Result<Upserted, Error> {
+    let name = validation::name("supplier", record.required("name")?)?;
+    let country_id = country(transaction, record.required("country")?)?;
+    let email = validation::email(record.required("email")?)?;
+
+    match supplier(transaction, name) {
+        Ok(supplier_id) => {
+            transaction.execute(
+                "UPDATE suppliers SET country_id = $2, email = $3 WHERE supplier_id = $1",
+                &[&supplier_id, &country_id, &email],
+            )?;
+            Ok(Upserted::Updated)
+        }
+        Err(Error::NotFound(_)) => {
+            transaction.execute(
+                "INSERT INTO suppliers (country_id, name, email) VALUES ($1, $2, $3)",
+                &[&country_id, &name, &email],
+            )?;
+            Ok(Upserted::Inserted)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+// Products by name, with the offering of a supplier by supplier and product
+// when the row names one. New products are counted in items.
+fn upsert_product(transaction: &mut Transaction, record: &Record) -> Result<Upserted, Error> {
+    let name = record.required("name")?;
+    let category_id = category(transaction, record.required("category")?)?;
+
+    let (product_id, upserted) = match product(transaction, name) {
+        Ok(product_id) => {
+            transaction.execute(
+                "UPDATE products SET category_id = $2 WHERE product_id = $1",
+                &[&product_id, &category_id],
+            )?;
+            (product_id, Upserted::Updated)
+        }
+        Err(Error::NotFound(_)) => {
+            let product_id: i32 = transaction
+                .query_one(
+                    "INSERT INTO products (category_id, name) VALUES ($1, $2) RETURNING product_id",
+                    &[&category_id, &name],
+                )?
+                .get(0);
+
+            transaction.execute(
+                "
+                INSERT INTO product_units (product_id, unit_id, factor)
+                SELECT $1, unit_id, 1
+                FROM units
+                WHERE code = 'each'
+                ",
+                &[&product_id],
+            )?;
+            (product_id, Upserted::Inserted)
+        }
+        Err(error) => return Err(error),
+    };
+
+    if let Some(supplier_name) = record.optional("supplier") {
+        let supplier_id = supplier(transaction, supplier_name)?;
+        let sku = record.required("sku")?;
+        let lead_time_days: i32 = record.number("lead_time_days")?;
+        let min_order_quantity: i32 = record.optional_number("min_order_quantity")?.unwrap_or(1);
+
+        transaction.execute(
+            "
+            INSERT INTO supplier_products (supplier_id, product_id, sku, lead_time_days, min_order_quantity)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (supplier_id, product_id) DO UPDATE
+            SET sku = EXCLUDED.sku,
+                lead_time_days = EXCLUDED.lead_time_days,
+                min_order_quantity = EXCLUDED.min_order_quantity
+            ",
+            &[&supplier_id, &product_id, &sku, &lead_time_days, &min_order_quantity],
+        )?;
+    }
+
+    Ok(upserted)
+}
+
+// Addresses by client and address, coordinates are updated when given
+fn upsert_client_address(
+    transaction: &mut Transaction,
+    record: &Record,
+) -> Result<Upserted, Error> {
+    let client_id = client(transaction, record.required("client")?)?;
+    let address = record.required("address")?;
+    let latitude: Option<f64> = record.optional_number("latitude")?;
+    let longitude: Option<f64> = record.optional_number("longitude")?;
+
+    match client_address(transaction, client_id, address) {
+        Ok(client_address_id) => {
+            transaction.execute(
+                "
+                UPDATE client_addresses
+                SET latitude = COALESCE($2, latitude), longitude = COALESCE($3, longitude)
+                WHERE client_address_id = $1
+                ",
+                &[&client_address_id, &latitude, &longitude],
+            )?;
+            Ok(Upserted::Updated)
+        }
+        Err(Error::NotFound(_)) => {
+            transaction.execute(
+                "
+                INSERT INTO client_addresses (client_id, address, latitude, longitude)
+                VALUES ($1, $2, $3, $4)
+                ",
+                &[&client_id, &address, &latitude, &longitude],
+            )?;
+            Ok(Upserted::Inserted)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+// Requirements by address and product, the count replaces the former one
+fn upsert_requirement(transaction: &mut Transaction, record: &Record) -> Result<Upserted, Error> {
+    let client_id = client(transaction, record.required("client")?)?;
+    let client_address_id = client_address(transaction, client_id, record.required("address")?)?;
+    let product_id = product(transaction, record.required("product")?)?;
+    let (count, unit) = base_count(transaction, record, product_id)?;
+
+    let updated = transaction.query(
+        "
+        UPDATE product_requirements
+        SET count = $3, unit_id = (SELECT unit_id FROM units WHERE code = $4)
+        WHERE client_address_id = $1 AND product_id = $2
+        RETURNING product_requirement_id
+        ",
+        &[&client_address_id, &product_id, &count, &unit],
+    )?;
+
+    match updated.len() {
+        0 => {
+            transaction.execute(
+                "
+                INSERT INTO product_requirements (client_address_id, product_id, count, unit_id)
+                VALUES ($1, $2, $3, (SELECT unit_id FROM units WHERE code = $4))
+                ",
+                &[&client_address_id, &product_id, &count, &unit],
+            )?;
+            Ok(Upserted::Inserted)
+        }
+        1 => Ok(Upserted::Updated),
+        _ => Err(Error::Conflict(
+            "the address has several requirements of the product".to_owned(),
+        )),
+    }
+}
+
+// Stock by warehouse and product, the count replaces the former one. It can't
+// go below what lots hold or allocations reserve there, those are changed by
+// receiving and shipping instead.
+fn upsert_stock(transaction: &mut Transaction, record: &Record) -> Result<Upserted, Error> {
+    let warehouse_id = warehouse(transaction, record.required("warehouse")?)?;
+    let product_id = product(transaction, record.required("product")?)?;
+    let (count, unit) = base_count(transaction, record, product_id)?;
+
+    transaction.execute(
+        "
+        SELECT 1
+        FROM product_locations
+        WHERE warehouse_id = $1 AND product_id = $2
+        FOR UPDATE
+        ",
+        &[&warehouse_id, &product_id],
+    )?;
+
+    let row = transaction.query_one(
+        "
+        SELECT
+            (
+                SELECT COALESCE(SUM(lot_locations.count), 0)
+                FROM lot_locations
+                INNER JOIN product_lots
+                ON lot_locations.product_lot_id = product_lots.product_lot_id
+                WHERE lot_locations.warehouse_id = $1
+                AND product_lots.product_id = $2
+            ) as lotted,
+            (
+                SELECT COALESCE(SUM(allocations.count), 0)
+                FROM allocations
+                INNER JOIN product_requirements
+                ON allocations.product_requirement_id = product_requirements.product_requirement_id
+                WHERE allocations.warehouse_id = $1
+                AND product_requirements.product_id = $2
+            ) as allocated
+        ",
+        &[&warehouse_id, &product_id],
+    )?;
+    let lotted: i64 = row.get("lotted");
+    let allocated: i64 = row.get("allocated");
+
+    if i64::from(count) < lotted {
+        return Err(Error::Conflict(format!(
+            "count {count} is below the {lotted} held in lots at the warehouse"
+        )));
+    }
+    if i64::from(count) < allocated {
+        return Err(Error::Conflict(format!(
+            "count {count} is below the {allocated} allocated at the warehouse"
+        )));
+    }
+
+    let inserted: bool = transaction
+        .query_one(
+            "
+            INSERT INTO product_locations (warehouse_id, product_id, count, unit_id)
+            VALUES ($1, $2, $3, (SELECT unit_id FROM units WHERE code = $4))
+            ON CONFLICT (warehouse_id, product_id) DO UPDATE
+            SET count = EXCLUDED.count,
+                unit_id = COALESCE(EXCLUDED.unit_id, product_locations.unit_id)
+            RETURNING xmax = 0
+            ",
+            &[&warehouse_id, &product_id, &count, &unit],
+        )?
+        .get(0);
+
+    Ok(if inserted {
+        Upserted::Inserted
+    } else {
+        Upserted::Updated
+    })
+}
+
+// Upserts every row in one transaction, each under a savepoint so that all of
+// them are checked. Nothing is kept when a row fails or on a dry run.
+pub fn import(
+    client: &mut Client,
+    entity: Entity,
+    records: &[Record],
+    dry_run: bool,
+) -> Result<ImportReport, Error> {
+    let mut report = ImportReport::default();
+
+    // the columns are checked once, every record has all of them
+    if let Some(record) = records.first() {
+        let missing: Vec<&str> = entity
+            .required_columns()
+            .iter()
+            .copied()
+            .filter(|column| !record.fields.contains_key(*column))
+            .collect();
+        let unknown: Vec<&str> = record
+            .fields
+            .keys()
+            .map(String::as_str)
+            .filter(|column| {
+                !column.is_empty()
+                    && !entity.required_columns().contains(column)
+                    && !entity.optional_columns().contains(column)
+            })
+            .collect();
+
+        for (columns, what) in [(missing, "missing"), (unknown, "unknown")] {
+            if !columns.is_empty() {
+                report.errors.push(RowError {
+                    line: 1,
+                    message: format!("{what} columns {}", columns.join(", ")),
+                });
+            }
+        }
+
+        if !report.errors.is_empty() {
+            return Ok(report);
+        }
+    }
+
+    let mut transaction = client.transaction()?;
+
+    for record in records {
+        let mut savepoint = transaction.transaction()?;
+
+        let upserted = match entity {
+            Entity::Suppliers => upsert_supplier(&mut savepoint, record),
+            Entity::Products => upsert_product(&mut savepoint, record),
+            Entity::ClientAddresses => upsert_client_address(&mut savepoint, record),
+            Entity::Requirements => upsert_requirement(&mut savepoint, record),
+            Entity::Stock => upsert_stock(&mut savepoint, record),
+        };
+
+        match upserted {
+            Ok(upserted) => {
+                savepoint.commit()?;
+
+                match upserted {
+                    Upserted::Inserted => report.inserted += 1,
+                    Upserted::Updated => report.updated += 1,
+                }
+            }
+            Err(error) => {
+                savepoint.rollback()?;
+
+                report.errors.push(RowError {
+                    line: record.line,
+                    message: error.to_string(),
+                });
+            }
+        }
+    }
+
+    if dry_run || !report.errors.is_empty() {
+        transaction.rollback()?;
+        return Ok(report);
+    }
+
+    transaction.commit()?;
+    report.committed = true;
+
+    if matches!(entity, Entity::Suppliers | Entity::Products) {
+        search::refresh(client)?;
+    }
+
+    Ok(report)
+}
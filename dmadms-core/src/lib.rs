@@ -0,0 +1,39 @@
+// Schema, models, repositories, seeding and reports of the distribution
+// database. Every module works on a `postgres::Client` from `connect`, or from
+// a pool of them with the `pool` feature. With the `async` feature `nonblocking`
+// offers seeding, reports and reads over tokio-postgres.
+
+pub mod anonymize;
+pub mod archive;
+pub mod attributes;
+pub mod barcodes;
+pub mod categories;
+pub mod connection;
+pub mod demo;
+pub mod documents;
+pub mod drift;
+pub mod error;
+pub mod export;
+pub mod fulfillment;
+pub mod geocoding;
+pub mod history;
+pub mod import;
+pub mod lots;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod offerings;
+#[cfg(feature = "pool")]
+pub mod pool;
+pub mod pricing;
+pub mod purchasing;
+pub mod roles;
+pub mod sales;
+pub mod schema;
+pub mod search;
+pub mod seed;
+pub mod tenancy;
+pub mod units;
+pub mod validation;
+
+pub use connection::{connect, Config};
+pub use error::Error;
@@ -0,0 +1,285 @@
+use chrono::NaiveDate;
+use postgres::{Client, GenericClient, Row};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone)]
+pub struct Lot {
+    pub supplier_lot_number: String,
+    pub production_date: NaiveDate,
+    pub expiry_date: NaiveDate,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpiringLot {
+    pub warehouse_id: i32,
+    pub warehouse: String,
+    pub product: String,
+    pub supplier_lot_number: String,
+    pub expiry_date: NaiveDate,
+    pub count: i32,
+}
+
+impl ExpiringLot {
+    pub(crate) fn from_row(row: &Row) -> Self {
+        ExpiringLot {
+            warehouse_id: row.get("warehouse_id"),
+            warehouse: row.get("warehouse"),
+            product: row.get("product"),
+            supplier_lot_number: row.get("supplier_lot_number"),
+            expiry_date: row.get("expiry_date"),
+            count: row.get("count"),
+        }
+    }
+}
+
+pub(crate) const SEED: &str = "
+    INSERT INTO product_lots (product_id, supplier_lot_number, production_date, expiry_date)
+    SELECT
+        products.product_id,
+        'L' || lpad(products.product_id::TEXT, 4, '0') || '-' || lot.n,
+        CURRENT_DATE - 100 + lot.n * 20 - products.product_id % 10,
+        CURRENT_DATE - 14 + lot.n * 20 + (products.product_id * 7) % 30
+    FROM products
+    CROSS JOIN generate_series(1, 3) as lot(n)
+    ORDER BY products.product_id, lot.n;
+
+    INSERT INTO lot_locations (product_lot_id, warehouse_id, count)
+    SELECT
+        product_lots.product_lot_id,
+        product_locations.warehouse_id,
+        CASE right(product_lots.supplier_lot_number, 1)
+            WHEN '1' THEN product_locations.count / 6
+            WHEN '2' THEN product_locations.count * 2 / 6
+            ELSE product_locations.count - product_locations.count / 6 - product_locations.count * 2 / 6
+        END
+    FROM product_locations
+    INNER JOIN product_lots
+    ON product_locations.product_id = product_lots.product_id
+    ORDER BY product_lots.product_lot_id, product_locations.warehouse_id;
+
+    DELETE FROM lot_locations WHERE count = 0;
+    ";
+
+// Every product gets three lots expiring within the next months, the stock of
+// each warehouse is split between them
+pub fn seed(client: &mut Client) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    transaction.batch_execute(SEED)?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+// Puts a lot into a warehouse, the lot is created on its first receipt
+pub fn receive(
+    client: &mut Client,
+    warehouse_id: i32,
+    product_id: i32,
+    lot: &Lot,
+    count: i32,
+) -> Result<i32, Error> {
+    if count <= 0 {
+        return Err(Error::Invalid(format!(
+            "received count of lot {} needs to be positive, not {count}",
+            lot.supplier_lot_number
+        )));
+    }
+
+    if lot.expiry_date < lot.production_date {
+        return Err(Error::Invalid(format!(
+            "lot {} expires before it is produced",
+            lot.supplier_lot_number
+        )));
+    }
+
+    let mut transaction = client.transaction()?;
+
+    let row = transaction.query_one(
+        "
+        INSERT INTO product_lots (product_id, supplier_lot_number, production_date, expiry_date)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (product_id, supplier_lot_number) DO UPDATE SET product_id = EXCLUDED.product_id
+        RETURNING product_lot_id, production_date, expiry_date
+        ",
+        &[
+            &product_id,
+            &lot.supplier_lot_number,
+            &lot.production_date,
+            &lot.expiry_date,
+        ],
+    )?;
+
+    let product_lot_id: i32 = row.get("product_lot_id");
+
+    if row.get::<_, NaiveDate>("production_date") != lot.production_date
+        || row.get::<_, NaiveDate>("expiry_date") != lot.expiry_date
+    {
+        return Err(Error::Conflict(format!(
+            "lot {} of product {product_id} is already known with other dates",
+            lot.supplier_lot_number
+        )));
+    }
+
+    transaction.execute(
+        "
+        INSERT INTO lot_locations (product_lot_id, warehouse_id, count)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (product_lot_id, warehouse_id)
+        DO UPDATE SET count = lot_locations.count + EXCLUDED.count
+        ",
+        &[&product_lot_id, &warehouse_id, &count],
+    )?;
+
+    transaction.execute(
+        "
+        INSERT INTO product_locations (warehouse_id, product_id, count)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (warehouse_id, product_id)
+        DO UPDATE SET count = product_locations.count + EXCLUDED.count
+        ",
+        &[&warehouse_id, &product_id, &count],
+    )?;
+
+    transaction.commit()?;
+
+    Ok(product_lot_id)
+}
+
+// Takes products out of a warehouse first-expired-first-out. Expired lots are
+// never picked, stock without a lot is used after all usable lots.
+// Returns picked counts per lot, `None` stands for stock without a lot.
+pub fn consume<C: GenericClient>(
+    client: &mut C,
+    warehouse_id: i32,
+    product_id: i32,
+    count: i32,
+) -> Result<Vec<(Option<i32>, i32)>, Error> {
+    let total: i32 = client
+        .query_opt(
+            "
+            SELECT count
+            FROM product_locations
+            WHERE warehouse_id = $1 AND product_id = $2
+            FOR UPDATE
+            ",
+            &[&warehouse_id, &product_id],
+        )?
+        .map(|row| row.get("count"))
+        .unwrap_or(0);
+
+    let lots: Vec<(i32, i32, i32, bool)> = client
+        .query(
+            "
+            SELECT
+                lot_locations.lot_location_id,
+                lot_locations.product_lot_id,
+                lot_locations.count,
+                product_lots.expiry_date < CURRENT_DATE as expired
+            FROM lot_locations
+            INNER JOIN product_lots
+            ON lot_locations.product_lot_id = product_lots.product_lot_id
+            WHERE lot_locations.warehouse_id = $1
+            AND product_lots.product_id = $2
+            AND lot_locations.count > 0
+            ORDER BY product_lots.expiry_date, product_lots.product_lot_id
+            FOR UPDATE OF lot_locations
+            ",
+            &[&warehouse_id, &product_id],
+        )?
+        .iter()
+        .map(|row| {
+            (
+                row.get("lot_location_id"),
+                row.get("product_lot_id"),
+                row.get("count"),
+                row.get("expired"),
+            )
+        })
+        .collect();
+
+    // summed wider, the counts of many lots can add up beyond an INTEGER
+    let in_lots: i64 = lots.iter().map(|lot| i64::from(lot.2)).sum();
+    let without_lot = (i64::from(total) - in_lots).max(0);
+    let usable: i64 = lots
+        .iter()
+        .filter(|lot| !lot.3)
+        .map(|lot| i64::from(lot.2))
+        .sum::<i64>()
+        + without_lot;
+
+    if usable < i64::from(count) {
+        return Err(Error::Conflict(format!(
+            "warehouse {warehouse_id} has only {usable} usable of product {product_id}, {count} needed"
+        )));
+    }
+
+    let mut picked = Vec::new();
+    let mut left = count;
+
+    for (lot_location_id, product_lot_id, available, _) in lots.into_iter().filter(|lot| !lot.3) {
+        if left == 0 {
+            break;
+        }
+
+        let take = left.min(available);
+        left -= take;
+
+        client.execute(
+            "
+            UPDATE lot_locations
+            SET count = count - $2
+            WHERE lot_location_id = $1
+            ",
+            &[&lot_location_id, &take],
+        )?;
+
+        picked.push((Some(product_lot_id), take));
+    }
+
+    if left > 0 {
+        picked.push((None, left));
+    }
+
+    client.execute(
+        "
+        UPDATE product_locations
+        SET count = count - $3
+        WHERE warehouse_id = $1 AND product_id = $2
+        ",
+        &[&warehouse_id, &product_id, &count],
+    )?;
+
+    Ok(picked)
+}
+
+pub(crate) const EXPIRING: &str = "
+    SELECT
+        warehouses.warehouse_id,
+        warehouses.address as warehouse,
+        products.name as product,
+        product_lots.supplier_lot_number,
+        product_lots.expiry_date,
+        lot_locations.count
+    FROM lot_locations
+    INNER JOIN product_lots
+    ON lot_locations.product_lot_id = product_lots.product_lot_id
+    INNER JOIN products
+    ON product_lots.product_id = products.product_id
+    INNER JOIN warehouses
+    ON lot_locations.warehouse_id = warehouses.warehouse_id
+    WHERE lot_locations.count > 0
+    AND warehouses.deleted_at IS NULL
+    AND product_lots.expiry_date <= CURRENT_DATE + $1::INTEGER
+    ORDER BY warehouses.warehouse_id, product_lots.expiry_date, products.name
+    ";
+
+pub fn expiring(client: &mut Client, days: i32) -> Result<Vec<ExpiringLot>, Error> {
+    Ok(client
+        .query(EXPIRING, &[&days])?
+        .iter()
+        .map(ExpiringLot::from_row)
+        .collect())
+}
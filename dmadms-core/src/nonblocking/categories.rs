@@ -0,0 +1,26 @@
+use tokio_postgres::Client;
+
+use crate::categories::{self, CATEGORIES_BY_PATH, CATEGORY_BY_ID};
+use crate::error::Error;
+
+// Category by its id or by its path from a root category
+pub async fn resolve(client: &Client, category: &str) -> Result<i32, Error> {
+    if let Ok(category_id) = category.parse::<i32>() {
+        return client
+            .query_opt(CATEGORY_BY_ID, &[&category_id])
+            .await?
+            .map(|row| row.get("category_id"))
+            .ok_or_else(|| Error::NotFound(format!("category {category_id}")));
+    }
+
+    let path = categories::path_of(category);
+
+    let matches: Vec<(i32, String)> = client
+        .query(CATEGORIES_BY_PATH, &[&path])
+        .await?
+        .iter()
+        .map(|row| (row.get("category_id"), row.get("path")))
+        .collect();
+
+    categories::pick(category, &path, &matches)
+}
@@ -0,0 +1,70 @@
+use tokio_postgres::Client;
+
+use crate::demo::{
+    COUNTRIES, COUNT_USERS, DELETE_SUPPLIER_USERS, GROCERIES, LOWERCASE_CATEGORIES, SUBCATEGORIES,
+    SUPPLIERS,
+};
+
+pub async fn countries(client: &Client) -> Result<Vec<String>, tokio_postgres::Error> {
+    Ok(client
+        .query(COUNTRIES, &[])
+        .await?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect())
+}
+
+// (category, subcategory) of root categories except the excluded ones
+pub async fn subcategories(
+    client: &Client,
+    except: &[&str],
+) -> Result<Vec<(String, String)>, tokio_postgres::Error> {
+    Ok(client
+        .query(SUBCATEGORIES, &[&except])
+        .await?
+        .iter()
+        .map(|row| (row.get("category"), row.get("subcategory")))
+        .collect())
+}
+
+// (supplier, email, country)
+pub async fn suppliers(
+    client: &Client,
+    limit: i64,
+) -> Result<Vec<(String, String, String)>, tokio_postgres::Error> {
+    Ok(client
+        .query(SUPPLIERS, &[&limit])
+        .await?
+        .iter()
+        .map(|row| (row.get("supplier"), row.get("email"), row.get("country")))
+        .collect())
+}
+
+// (subcategory, product) of groceries in subcategories that start with a prefix
+pub async fn groceries(
+    client: &Client,
+    prefix: &str,
+) -> Result<Vec<(String, String)>, tokio_postgres::Error> {
+    Ok(client
+        .query(GROCERIES, &[&prefix])
+        .await?
+        .iter()
+        .map(|row| (row.get("subcategory"), row.get("product")))
+        .collect())
+}
+
+pub async fn count_users(client: &Client) -> Result<i64, tokio_postgres::Error> {
+    Ok(client.query_one(COUNT_USERS, &[]).await?.get(0))
+}
+
+// Soft deletes supplier users with a password, returns how many were deleted
+pub async fn delete_supplier_users(
+    client: &Client,
+    password: &str,
+) -> Result<u64, tokio_postgres::Error> {
+    client.execute(DELETE_SUPPLIER_USERS, &[&password]).await
+}
+
+pub async fn lowercase_categories(client: &Client) -> Result<(), tokio_postgres::Error> {
+    client.batch_execute(LOWERCASE_CATEGORIES).await
+}
@@ -0,0 +1,16 @@
+use tokio_postgres::Client;
+
+use crate::documents::{MissingDocument, MISSING};
+use crate::error::Error;
+
+pub async fn missing(
+    client: &Client,
+    country: Option<&str>,
+) -> Result<Vec<MissingDocument>, Error> {
+    Ok(client
+        .query(MISSING, &[&country])
+        .await?
+        .iter()
+        .map(MissingDocument::from_row)
+        .collect())
+}
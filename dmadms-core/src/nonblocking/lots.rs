@@ -0,0 +1,13 @@
+use tokio_postgres::Client;
+
+use crate::error::Error;
+use crate::lots::{ExpiringLot, EXPIRING};
+
+pub async fn expiring(client: &Client, days: i32) -> Result<Vec<ExpiringLot>, Error> {
+    Ok(client
+        .query(EXPIRING, &[&days])
+        .await?
+        .iter()
+        .map(ExpiringLot::from_row)
+        .collect())
+}
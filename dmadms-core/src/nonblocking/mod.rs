@@ -0,0 +1,64 @@
+// The seeding, reporting and read side of the repositories over tokio-postgres.
+// Functions mirror their blocking counterparts of the same name and share
+// their SQL, taking a `tokio_postgres::Client`, or a client of a deadpool
+// `Pool`, instead of a `postgres::Client`.
+
+use deadpool_postgres::{Manager, ManagerConfig, PoolError, RecyclingMethod};
+use tokio_postgres::Client;
+
+use crate::connection::{self, Config};
+use crate::error::Error;
+
+pub mod categories;
+pub mod demo;
+pub mod documents;
+pub mod lots;
+pub mod offerings;
+pub mod pricing;
+pub mod search;
+pub mod seed;
+
+pub type Pool = deadpool_postgres::Pool;
+pub type PooledClient = deadpool_postgres::Object;
+
+// The connection is driven by a task spawned on the current tokio runtime
+pub async fn connect(config: &Config) -> Result<Client, Error> {
+    let (client, connection) =
+        tokio_postgres::connect(&config.params(), connection::tls()?).await?;
+
+    tokio::spawn(async move {
+        if let Err(error) = connection.await {
+            eprintln!("connection error: {error}");
+        }
+    });
+
+    Ok(client)
+}
+
+// Opens up to `size` connections, handed out by `get`
+pub fn pool(config: &Config, size: usize) -> Result<Pool, Error> {
+    let params = config
+        .params()
+        .parse()
+        .map_err(|error| Error::Invalid(format!("bad connection parameters: {error}")))?;
+
+    let manager = Manager::from_config(
+        params,
+        connection::tls()?,
+        ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        },
+    );
+
+    Pool::builder(manager)
+        .max_size(size)
+        .build()
+        .map_err(|error| Error::Invalid(format!("can't create a pool: {error}")))
+}
+
+pub async fn get(pool: &Pool) -> Result<PooledClient, Error> {
+    pool.get().await.map_err(|error| match error {
+        PoolError::Backend(error) => Error::Postgres(error),
+        error => Error::Invalid(format!("can't connect to postgresql database: {error}")),
+    })
+}
@@ -0,0 +1,23 @@
+use tokio_postgres::Client;
+
+use crate::error::Error;
+use crate::offerings::{offerings_query, Offering};
+
+async fn offerings(client: &Client, filter: &str, id: i32) -> Result<Vec<Offering>, Error> {
+    Ok(client
+        .query(&offerings_query(filter), &[&id])
+        .await?
+        .iter()
+        .map(Offering::from_row)
+        .collect())
+}
+
+// Suppliers offering a product, the preferred one first
+pub async fn suppliers_of(client: &Client, product_id: i32) -> Result<Vec<Offering>, Error> {
+    offerings(client, "supplier_products.product_id", product_id).await
+}
+
+// Products available from a supplier
+pub async fn products_of(client: &Client, supplier_id: i32) -> Result<Vec<Offering>, Error> {
+    offerings(client, "supplier_products.supplier_id", supplier_id).await
+}
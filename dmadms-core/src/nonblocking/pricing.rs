@@ -0,0 +1,40 @@
+use tokio_postgres::Client;
+
+use crate::error::Error;
+use crate::pricing::{
+    ClientValue, WarehouseValue, CURRENCY_KNOWN, REQUIREMENTS_VALUE, STOCK_VALUE,
+};
+
+async fn check_currency(client: &Client, code: &str) -> Result<(), Error> {
+    let known: bool = client.query_one(CURRENCY_KNOWN, &[&code]).await?.get(0);
+
+    if known {
+        Ok(())
+    } else {
+        Err(Error::NotFound(format!("exchange rate of {code}")))
+    }
+}
+
+// Stock valued at current purchase prices of the preferred suppliers
+pub async fn stock_value(client: &Client, base: &str) -> Result<Vec<WarehouseValue>, Error> {
+    check_currency(client, base).await?;
+
+    Ok(client
+        .query(STOCK_VALUE, &[&base])
+        .await?
+        .iter()
+        .map(WarehouseValue::from_row)
+        .collect())
+}
+
+// Outstanding requirements valued at current client prices
+pub async fn requirements_value(client: &Client, base: &str) -> Result<Vec<ClientValue>, Error> {
+    check_currency(client, base).await?;
+
+    Ok(client
+        .query(REQUIREMENTS_VALUE, &[&base])
+        .await?
+        .iter()
+        .map(ClientValue::from_row)
+        .collect())
+}
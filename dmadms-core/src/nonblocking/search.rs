@@ -0,0 +1,34 @@
+use tokio_postgres::Client;
+
+use crate::error::Error;
+use crate::nonblocking::categories;
+use crate::search::{self, Query, SearchResults, SEARCH};
+
+pub async fn refresh(client: &Client) -> Result<(), Error> {
+    client
+        .batch_execute("SELECT refresh_product_search()")
+        .await?;
+
+    Ok(())
+}
+
+pub async fn search(client: &Client, query: &Query) -> Result<SearchResults, Error> {
+    let category_id = match query.category.as_deref() {
+        Some(category) => Some(categories::resolve(client, category).await?),
+        None => None,
+    };
+
+    let rows = client
+        .query(
+            SEARCH,
+            &[
+                &query.text,
+                &category_id,
+                &query.country,
+                &query.warehouse_id,
+            ],
+        )
+        .await?;
+
+    Ok(search::collect(rows, query))
+}
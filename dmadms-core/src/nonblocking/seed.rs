@@ -0,0 +1,554 @@
+use std::collections::HashMap;
+
+use tokio_postgres::Transaction;
+
+use crate::barcodes::{self, Barcode};
+use crate::error::Error;
+use crate::geocoding::{self, GeocodeSummary, Place};
+use crate::nonblocking::{get, search, Pool};
+use crate::pricing::{self, ExchangeRate};
+use crate::schema;
+use crate::seed::{SeedSummary, BARCODES, EXCHANGE_RATES, GAZETTEER};
+use crate::{attributes, categories, documents, lots, offerings, units, validation};
+
+// Drops every table and creates them again, empty
+pub async fn reset(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+
+    for statement in schema::drop_statements() {
+        _ = client.batch_execute(&statement).await;
+    }
+
+    let transaction = client.transaction().await?;
+
+    for statement in schema::create_statements() {
+        transaction.batch_execute(&statement).await?;
+    }
+
+    transaction.commit().await?;
+
+    for index in schema::INDEXES {
+        client.batch_execute(index).await?;
+    }
+
+    Ok(())
+}
+
+async fn ids(transaction: &Transaction<'_>, query: &str) -> Result<Vec<i32>, Error> {
+    Ok(transaction
+        .query(query, &[])
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect())
+}
+
+// Runs statements in a transaction of their own
+async fn batch(pool: &Pool, statements: &str) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    transaction.batch_execute(statements).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn countries(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    for country in include!("../init_data/countries") {
+        transaction
+            .execute(schema::INSERT_COUNTRY, &[&country])
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn clients(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    let clients = include!("../init_data/clients")
+        .iter()
+        .zip(include!("../init_data/emails").iter());
+
+    for (name, email) in clients {
+        transaction
+            .execute(
+                schema::INSERT_CLIENT,
+                &[name, &validation::normalize_email(email)],
+            )
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn categories(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    for (category, subcategories) in include!("../init_data/product_categories") {
+        let category_id: i32 = transaction
+            .query_one(categories::INSERT, &[&None::<i32>, &category])
+            .await?
+            .get("category_id");
+
+        for subcategory in subcategories {
+            transaction
+                .execute(categories::INSERT, &[&Some(category_id), &subcategory])
+                .await?;
+        }
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn warehouses(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    for address in include!("../init_data/addresses") {
+        transaction
+            .execute(schema::INSERT_WAREHOUSE, &[&address])
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn user_roles(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    for user_role in schema::USER_ROLES {
+        transaction
+            .execute(schema::INSERT_USER_ROLE, &[&user_role])
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+// Suppliers take turns over the countries
+async fn suppliers(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    let country_ids = ids(&transaction, "SELECT country_id FROM countries").await?;
+
+    let suppliers = include!("../init_data/suppliers")
+        .iter()
+        .zip(include!("../init_data/emails").iter())
+        .zip(country_ids.iter().cycle());
+
+    for ((name, email), country_id) in suppliers {
+        transaction
+            .execute(
+                schema::INSERT_SUPPLIER,
+                &[&country_id, name, &validation::normalize_email(email)],
+            )
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+// Every warehouse address is also an address of a client
+async fn client_addresses(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    let client_ids = ids(&transaction, "SELECT client_id FROM clients").await?;
+
+    let addresses = include!("../init_data/addresses");
+
+    for (address, client_id) in addresses.iter().zip(client_ids.iter().cycle()) {
+        transaction
+            .execute(schema::INSERT_CLIENT_ADDRESS, &[&client_id, address])
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+// Products take turns over the subcategories and are offered by the suppliers
+// in turn
+async fn products(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    let subcategory_ids = ids(&transaction, schema::SUBCATEGORY_IDS).await?;
+    let supplier_ids = ids(&transaction, "SELECT supplier_id FROM suppliers").await?;
+
+    let products = include!("../init_data/products").iter().zip(
+        subcategory_ids
+            .iter()
+            .cycle()
+            .zip(supplier_ids.iter().cycle()),
+    );
+
+    for (product_name, (subcategory_id, supplier_id)) in products {
+        let product_id: i32 = transaction
+            .query_one(schema::INSERT_PRODUCT, &[&subcategory_id, product_name])
+            .await?
+            .get("product_id");
+
+        let (sku, lead_time_days, min_order_quantity) =
+            schema::offering_terms(*supplier_id, product_id);
+
+        transaction
+            .execute(
+                schema::INSERT_OFFERING,
+                &[
+                    &supplier_id,
+                    &product_id,
+                    &sku,
+                    &lead_time_days,
+                    &min_order_quantity,
+                ],
+            )
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+// The admin, the managers, then a user for every supplier and client
+async fn users(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    let mut role_ids = HashMap::new();
+
+    for user_role in schema::USER_ROLES {
+        let role_id: i32 = transaction
+            .query_one(schema::USER_ROLE_ID, &[&user_role])
+            .await?
+            .get(0);
+        role_ids.insert(user_role, role_id);
+    }
+
+    let (name, password) = schema::ADMIN;
+
+    transaction
+        .execute(schema::INSERT_USER, &[&name, &password, &role_ids["admin"]])
+        .await?;
+
+    for (name, password) in schema::MANAGERS {
+        transaction
+            .execute(
+                schema::INSERT_USER,
+                &[&name, &password, &role_ids["manager"]],
+            )
+            .await?;
+    }
+
+    for supplier_id in ids(&transaction, "SELECT supplier_id FROM suppliers").await? {
+        transaction
+            .execute(
+                schema::INSERT_SUPPLIER_USER,
+                &[&supplier_id, &role_ids["supplier"]],
+            )
+            .await?;
+    }
+
+    for client_id in ids(&transaction, "SELECT client_id FROM clients").await? {
+        transaction
+            .execute(
+                schema::INSERT_CLIENT_USER,
+                &[&client_id, &role_ids["client"]],
+            )
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+// Counts of the first products at every row of `owners`, requirements of client
+// addresses or stock of warehouses
+async fn counts(pool: &Pool, owners: &str, insert: &str) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    let owner_ids = ids(&transaction, owners).await?;
+    let product_ids = ids(&transaction, schema::FIRST_PRODUCT_IDS).await?;
+
+    for owner_id in owner_ids {
+        for product_id in &product_ids {
+            let count = schema::stock_count(owner_id, *product_id);
+
+            if count != 0 {
+                transaction
+                    .execute(insert, &[&owner_id, &product_id, &count])
+                    .await?;
+            }
+        }
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+// Fills the tables `schema::init_tables` does, loading tables which don't refer
+// to each other at the same time. Every table is loaded in a transaction of its
+// own, a failure leaves the tables loaded before it.
+pub async fn init_tables(pool: &Pool) -> Result<(), Error> {
+    tokio::try_join!(
+        countries(pool),
+        clients(pool),
+        categories(pool),
+        warehouses(pool),
+        user_roles(pool),
+    )?;
+
+    tokio::try_join!(suppliers(pool), client_addresses(pool))?;
+
+    tokio::try_join!(products(pool), users(pool))?;
+
+    tokio::try_join!(
+        counts(
+            pool,
+            "SELECT client_address_id FROM client_addresses",
+            schema::INSERT_REQUIREMENT,
+        ),
+        counts(
+            pool,
+            "SELECT warehouse_id FROM warehouses",
+            schema::INSERT_LOCATION,
+        ),
+    )?;
+
+    Ok(())
+}
+
+async fn geocode_table(
+    transaction: &Transaction<'_>,
+    gazetteer: &HashMap<String, &Place>,
+    select: &str,
+    update: &str,
+    unmatched: &mut Vec<String>,
+) -> Result<usize, Error> {
+    let rows = transaction
+        .query(select, &[])
+        .await?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+
+    let located = geocoding::locate(gazetteer, rows, unmatched);
+
+    for (id, place) in &located {
+        transaction
+            .execute(update, &[id, &place.latitude, &place.longitude])
+            .await?;
+    }
+
+    Ok(located.len())
+}
+
+async fn geocode(pool: &Pool, places: &[Place]) -> Result<GeocodeSummary, Error> {
+    let gazetteer = geocoding::index(places);
+    let mut summary = GeocodeSummary::default();
+
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    summary.warehouses = geocode_table(
+        &transaction,
+        &gazetteer,
+        geocoding::SELECT_WAREHOUSES,
+        geocoding::UPDATE_WAREHOUSE,
+        &mut summary.unmatched,
+    )
+    .await?;
+    summary.client_addresses = geocode_table(
+        &transaction,
+        &gazetteer,
+        geocoding::SELECT_CLIENT_ADDRESSES,
+        geocoding::UPDATE_CLIENT_ADDRESS,
+        &mut summary.unmatched,
+    )
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(summary)
+}
+
+async fn exchange_rates(pool: &Pool, rates: &[ExchangeRate]) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    for rate in rates {
+        transaction
+            .execute(pricing::UPSERT_EXCHANGE_RATE, &rate.params())
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn prices(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    for (country, currency) in pricing::COUNTRY_CURRENCIES {
+        transaction
+            .execute(pricing::SET_COUNTRY_CURRENCY, &[country, currency])
+            .await?;
+    }
+
+    transaction.batch_execute(pricing::SEED_PRICES).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn units(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    for (code, name) in units::UNITS {
+        transaction
+            .execute(units::INSERT_UNIT, &[&code, &name])
+            .await?;
+    }
+
+    transaction.batch_execute(units::SEED).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn documents(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    for document_type in documents::DOCUMENT_TYPES {
+        transaction
+            .execute(documents::INSERT_DOCUMENT_TYPE, &[&document_type])
+            .await?;
+    }
+
+    transaction.batch_execute(documents::SEED).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn attributes(pool: &Pool) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    for allergen in attributes::ALLERGENS {
+        transaction
+            .execute(attributes::INSERT_ALLERGEN, &[&allergen])
+            .await?;
+    }
+
+    for flag in attributes::DIETARY_FLAGS {
+        transaction
+            .execute(attributes::INSERT_DIETARY_FLAG, &[&flag])
+            .await?;
+    }
+
+    transaction.batch_execute(attributes::SEED).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+// Imports all barcodes or none of them
+async fn barcodes(pool: &Pool, barcodes: &[Barcode]) -> Result<(), Error> {
+    let mut client = get(pool).await?;
+    let transaction = client.transaction().await?;
+
+    for (line, barcode) in barcodes.iter().enumerate() {
+        let on_line = barcodes::on_line(line);
+
+        let product_id = barcodes::product_id(
+            transaction
+                .query_opt(barcodes::PRODUCT_ID, &[&barcode.product])
+                .await?,
+            &barcode.product,
+        )
+        .map_err(&on_line)?;
+
+        let product_unit_id = barcodes::product_unit_id(
+            transaction
+                .query_opt(barcodes::PRODUCT_UNIT_ID, &[&product_id, &barcode.unit])
+                .await?,
+            product_id,
+            &barcode.unit,
+        )
+        .map_err(&on_line)?;
+
+        barcodes::product_barcode_id(
+            transaction
+                .query_opt(barcodes::INSERT_BARCODE, &[&product_unit_id, &barcode.gtin])
+                .await?,
+            &barcode.gtin,
+        )
+        .map_err(&on_line)?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+// Fills freshly created tables like `seed::seed` does, running the loads that
+// don't depend on each other concurrently on connections of the pool
+pub async fn seed(pool: &Pool) -> Result<SeedSummary, Error> {
+    let places = geocoding::read_gazetteer(GAZETTEER.as_bytes())?;
+    let rates = pricing::read_exchange_rates(EXCHANGE_RATES.as_bytes())?;
+    let gtins = barcodes::read_barcodes(BARCODES.as_bytes())?;
+
+    init_tables(pool).await?;
+
+    let (geocoded, ..) = tokio::try_join!(
+        geocode(pool, &places),
+        exchange_rates(pool, &rates),
+        batch(pool, offerings::SEED),
+        units(pool),
+        batch(pool, lots::SEED),
+        documents(pool),
+        attributes(pool),
+    )?;
+
+    tokio::try_join!(prices(pool), barcodes(pool, &gtins))?;
+
+    search::refresh(&*get(pool).await?).await?;
+
+    Ok(SeedSummary {
+        geocoded,
+        gazetteer_places: places.len(),
+        exchange_rates: rates.len(),
+        barcodes: gtins.len(),
+    })
+}
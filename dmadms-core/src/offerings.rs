@@ -0,0 +1,213 @@
+use postgres::{Client, Row};
+use rust_decimal::Decimal;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone)]
+pub struct Offering {
+    pub supplier_id: i32,
+    pub supplier: String,
+    pub country: String,
+    pub product_id: i32,
+    pub product: String,
+    pub sku: String,
+    pub lead_time_days: i32,
+    pub min_order_quantity: i32,
+    pub active: bool,
+    // current purchase price, if any
+    pub price: Option<Decimal>,
+    pub currency: Option<String>,
+}
+
+impl Offering {
+    pub(crate) fn from_row(row: &Row) -> Self {
+        Offering {
+            supplier_id: row.get("supplier_id"),
+            supplier: row.get("supplier"),
+            country: row.get("country"),
+            product_id: row.get("product_id"),
+            product: row.get("product"),
+            sku: row.get("sku"),
+            lead_time_days: row.get("lead_time_days"),
+            min_order_quantity: row.get("min_order_quantity"),
+            active: row.get("active"),
+            price: row.get("price"),
+            currency: row.get("currency"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewOffering {
+    pub supplier_id: i32,
+    pub product_id: i32,
+    pub sku: String,
+    pub lead_time_days: i32,
+    pub min_order_quantity: i32,
+}
+
+pub(crate) const SEED: &str = "
+    INSERT INTO supplier_products (supplier_id, product_id, sku, lead_time_days, min_order_quantity)
+    SELECT
+        next_supplier.supplier_id,
+        supplier_products.product_id,
+        'S' || next_supplier.supplier_id || '-' || lpad(supplier_products.product_id::TEXT, 5, '0'),
+        supplier_products.lead_time_days + 5,
+        supplier_products.min_order_quantity * 2
+    FROM supplier_products
+    CROSS JOIN LATERAL (
+        SELECT supplier_id
+        FROM suppliers
+        WHERE suppliers.supplier_id <> supplier_products.supplier_id
+        ORDER BY suppliers.supplier_id <= supplier_products.supplier_id, suppliers.supplier_id
+        LIMIT 1
+    ) as next_supplier
+    WHERE supplier_products.product_id % 3 = 0;
+    ";
+
+// Every third product is also offered by the next supplier with a longer lead
+// time and a larger minimum order quantity
+pub fn seed(client: &mut Client) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    transaction.batch_execute(SEED)?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+pub fn add(client: &mut Client, offering: &NewOffering) -> Result<i32, Error> {
+    client
+        .query_one(
+            "
+            INSERT INTO supplier_products (supplier_id, product_id, sku, lead_time_days, min_order_quantity)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING supplier_product_id
+            ",
+            &[
+                &offering.supplier_id,
+                &offering.product_id,
+                &offering.sku,
+                &offering.lead_time_days,
+                &offering.min_order_quantity,
+            ],
+        )
+        .map(|row| row.get("supplier_product_id"))
+        .map_err(|error| match error.code() {
+            Some(&postgres::error::SqlState::UNIQUE_VIOLATION) => Error::Conflict(format!(
+                "supplier {} already offers product {} or uses SKU {}",
+                offering.supplier_id, offering.product_id, offering.sku
+            )),
+            Some(&postgres::error::SqlState::FOREIGN_KEY_VIOLATION) => Error::NotFound(format!(
+                "supplier {} or product {}",
+                offering.supplier_id, offering.product_id
+            )),
+            _ => error.into(),
+        })
+}
+
+pub fn set_active(
+    client: &mut Client,
+    supplier_id: i32,
+    product_id: i32,
+    active: bool,
+) -> Result<(), Error> {
+    let updated = client.execute(
+        "
+        UPDATE supplier_products
+        SET active = $3
+        WHERE supplier_id = $1 AND product_id = $2
+        ",
+        &[&supplier_id, &product_id, &active],
+    )?;
+
+    if updated == 0 {
+        return Err(Error::NotFound(format!(
+            "offering of product {product_id} by supplier {supplier_id}"
+        )));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn offerings_query(filter: &str) -> String {
+    format!(
+        "
+        SELECT
+            suppliers.supplier_id,
+            suppliers.name as supplier,
+            countries.name as country,
+            products.product_id,
+            products.name as product,
+            supplier_products.sku,
+            supplier_products.lead_time_days,
+            supplier_products.min_order_quantity,
+            supplier_products.active,
+            current_supplier_prices.price,
+            currencies.code as currency
+        FROM supplier_products
+        INNER JOIN suppliers
+        ON supplier_products.supplier_id = suppliers.supplier_id
+        INNER JOIN countries
+        ON suppliers.country_id = countries.country_id
+        INNER JOIN products
+        ON supplier_products.product_id = products.product_id
+        LEFT JOIN current_supplier_prices
+        ON supplier_products.supplier_id = current_supplier_prices.supplier_id
+        AND supplier_products.product_id = current_supplier_prices.product_id
+        LEFT JOIN currencies
+        ON current_supplier_prices.currency_id = currencies.currency_id
+        WHERE {filter} = $1
+        AND suppliers.deleted_at IS NULL
+        AND products.deleted_at IS NULL
+        ORDER BY supplier_products.active DESC, supplier_products.lead_time_days, suppliers.name, products.name
+        "
+    )
+}
+
+fn offerings(client: &mut Client, filter: &str, id: i32) -> Result<Vec<Offering>, Error> {
+    Ok(client
+        .query(&offerings_query(filter), &[&id])?
+        .iter()
+        .map(Offering::from_row)
+        .collect())
+}
+
+// Suppliers offering a product, the preferred one first
+pub fn suppliers_of(client: &mut Client, product_id: i32) -> Result<Vec<Offering>, Error> {
+    offerings(client, "supplier_products.product_id", product_id)
+}
+
+// Products available from a supplier
+pub fn products_of(client: &mut Client, supplier_id: i32) -> Result<Vec<Offering>, Error> {
+    offerings(client, "supplier_products.supplier_id", supplier_id)
+}
+
+pub fn print_offerings(offerings: &[Offering]) {
+    println!(
+        "
+{:30} {:15} {:30} {:14} {:>5} {:>5} {:>12}  Active
+",
+        "Supplier", "Country", "Product", "SKU", "Days", "MOQ", "Price"
+    );
+
+    for offering in offerings {
+        let price = match (offering.price, &offering.currency) {
+            (Some(price), Some(currency)) => format!("{price} {currency}"),
+            _ => "-".to_owned(),
+        };
+
+        println!(
+            "{:30} {:15} {:30} {:14} {:>5} {:>5} {:>12}  {}",
+            format!("{} {}", offering.supplier_id, offering.supplier),
+            offering.country,
+            format!("{} {}", offering.product_id, offering.product),
+            offering.sku,
+            offering.lead_time_days,
+            offering.min_order_quantity,
+            price,
+            if offering.active { "yes" } else { "no" }
+        );
+    }
+}
@@ -0,0 +1,22 @@
+use r2d2_postgres::PostgresConnectionManager;
+
+use crate::connection::{self, Config, Tls};
+use crate::error::Error;
+
+pub type Pool = r2d2::Pool<PostgresConnectionManager<Tls>>;
+pub type PooledClient = r2d2::PooledConnection<PostgresConnectionManager<Tls>>;
+
+// Opens up to `size` connections, handed out by `Pool::get`
+pub fn pool(config: &Config, size: u32) -> Result<Pool, Error> {
+    let params = config
+        .params()
+        .parse()
+        .map_err(|error| Error::Invalid(format!("bad connection parameters: {error}")))?;
+
+    let manager = PostgresConnectionManager::new(params, connection::tls()?);
+
+    r2d2::Pool::builder()
+        .max_size(size)
+        .build(manager)
+        .map_err(|error| Error::Invalid(format!("can't connect to postgresql database: {error}")))
+}
@@ -0,0 +1,276 @@
+use std::io::Read;
+
+use chrono::NaiveDate;
+use postgres::types::ToSql;
+use postgres::{Client, Row};
+use rust_decimal::Decimal;
+
+use crate::error::Error;
+
+pub const DEFAULT_BASE_CURRENCY: &str = "EUR";
+
+// Rate is the value of one unit of the currency in the reference currency of the
+// file, reports convert through it into any base currency
+#[derive(Debug, Clone)]
+pub struct ExchangeRate {
+    pub code: String,
+    pub name: String,
+    pub valid_from: NaiveDate,
+    pub rate: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct WarehouseValue {
+    pub warehouse_id: i32,
+    pub address: String,
+    pub value: Decimal,
+    pub unpriced: i64,
+}
+
+impl WarehouseValue {
+    pub(crate) fn from_row(row: &Row) -> Self {
+        WarehouseValue {
+            warehouse_id: row.get("warehouse_id"),
+            address: row.get("address"),
+            value: row.get("value"),
+            unpriced: row.get("unpriced"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientValue {
+    pub client: String,
+    pub value: Decimal,
+    pub unpriced: i64,
+}
+
+impl ClientValue {
+    pub(crate) fn from_row(row: &Row) -> Self {
+        ClientValue {
+            client: row.get("client"),
+            value: row.get("value"),
+            unpriced: row.get("unpriced"),
+        }
+    }
+}
+
+// CSV file with `code,name,valid_from,rate` columns
+pub fn read_exchange_rates<R: Read>(reader: R) -> Result<Vec<ExchangeRate>, Error> {
+    let mut rates = Vec::new();
+
+    for (line, record) in csv::Reader::from_reader(reader).records().enumerate() {
+        let record = record?;
+        let invalid = || Error::Invalid(format!("invalid exchange rate on line {}", line + 2));
+
+        let [code, name, valid_from, rate] = [0, 1, 2, 3].map(|i| record.get(i).map(str::trim));
+
+        let code =
+            code.filter(|code| code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase()));
+        let valid_from = valid_from.and_then(|date| date.parse().ok());
+        let rate = rate
+            .and_then(|rate| rate.parse::<Decimal>().ok())
+            .filter(|rate| rate.is_sign_positive() && !rate.is_zero());
+
+        match (code, name, valid_from, rate) {
+            (Some(code), Some(name), Some(valid_from), Some(rate)) => rates.push(ExchangeRate {
+                code: code.to_owned(),
+                name: name.to_owned(),
+                valid_from,
+                rate,
+            }),
+            _ => return Err(invalid()),
+        }
+    }
+
+    Ok(rates)
+}
+
+// Upserts the currency and its rate, shared with the concurrent seeder
+pub(crate) const UPSERT_EXCHANGE_RATE: &str = "
+    WITH currency AS (
+        INSERT INTO currencies (code, name)
+        VALUES ($1, $2)
+        ON CONFLICT (code) DO UPDATE SET name = EXCLUDED.name
+        RETURNING currency_id
+    )
+    INSERT INTO exchange_rates (currency_id, valid_from, rate)
+    SELECT currency_id, $3, $4
+    FROM currency
+    ON CONFLICT (currency_id, valid_from) DO UPDATE SET rate = EXCLUDED.rate
+    ";
+
+impl ExchangeRate {
+    pub(crate) fn params(&self) -> [&(dyn ToSql + Sync); 4] {
+        [&self.code, &self.name, &self.valid_from, &self.rate]
+    }
+}
+
+pub fn import_exchange_rates(client: &mut Client, rates: &[ExchangeRate]) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    for rate in rates {
+        transaction.execute(UPSERT_EXCHANGE_RATE, &rate.params())?;
+    }
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+pub(crate) const COUNTRY_CURRENCIES: &[(&str, &str)] = &include!("init_data/country_currencies");
+
+pub(crate) const SET_COUNTRY_CURRENCY: &str = "
+    UPDATE countries
+    SET currency_id = (SELECT currency_id FROM currencies WHERE code = $2)
+    WHERE name = $1
+    ";
+
+pub(crate) const SEED_PRICES: &str = "
+    INSERT INTO supplier_prices (supplier_id, product_id, currency_id, price, valid_from)
+    SELECT
+        supplier_products.supplier_id,
+        supplier_products.product_id,
+        countries.currency_id,
+        ROUND(
+            ((supplier_products.product_id * 37) % 900 + 100) / 100.0 / current_exchange_rates.rate
+            * CASE WHEN preferred_supplier_products.supplier_product_id IS NULL THEN 1.1 ELSE 1 END,
+            2
+        ),
+        DATE '2022-01-01'
+    FROM supplier_products
+    LEFT JOIN preferred_supplier_products
+    ON supplier_products.supplier_product_id = preferred_supplier_products.supplier_product_id
+    INNER JOIN suppliers
+    ON supplier_products.supplier_id = suppliers.supplier_id
+    INNER JOIN countries
+    ON suppliers.country_id = countries.country_id
+    INNER JOIN current_exchange_rates
+    ON countries.currency_id = current_exchange_rates.currency_id;
+
+    INSERT INTO client_prices (client_id, product_id, currency_id, price, valid_from)
+    SELECT DISTINCT
+        client_addresses.client_id,
+        product_requirements.product_id,
+        (SELECT currency_id FROM currencies WHERE code = 'EUR'),
+        ROUND(((product_requirements.product_id * 37) % 900 + 100) / 100.0 * 1.3, 2),
+        DATE '2022-01-01'
+    FROM product_requirements
+    INNER JOIN client_addresses
+    ON product_requirements.client_address_id = client_addresses.client_address_id;
+    ";
+
+// Countries get their currencies, every supplier offering a purchase price in
+// the supplier currency, alternative suppliers a bit higher, and clients a sale
+// price with a margin for everything they require
+pub fn seed_prices(client: &mut Client) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    for (country, currency) in COUNTRY_CURRENCIES {
+        transaction.execute(SET_COUNTRY_CURRENCY, &[country, currency])?;
+    }
+
+    transaction.batch_execute(SEED_PRICES)?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+pub(crate) const CURRENCY_KNOWN: &str = "
+    SELECT EXISTS (
+        SELECT 1
+        FROM current_exchange_rates
+        INNER JOIN currencies
+        ON current_exchange_rates.currency_id = currencies.currency_id
+        WHERE currencies.code = $1
+    )
+    ";
+
+fn check_currency(client: &mut Client, code: &str) -> Result<(), Error> {
+    let known: bool = client.query_one(CURRENCY_KNOWN, &[&code])?.get(0);
+
+    if known {
+        Ok(())
+    } else {
+        Err(Error::NotFound(format!("exchange rate of {code}")))
+    }
+}
+
+pub(crate) const STOCK_VALUE: &str = "
+    SELECT
+        warehouses.warehouse_id,
+        warehouses.address,
+        ROUND(COALESCE(SUM(product_locations.count * prices.price * rates.rate / base.rate), 0), 2) as value,
+        COUNT(product_locations.product_id) FILTER (WHERE prices.price IS NULL) as unpriced
+    FROM warehouses
+    CROSS JOIN (
+        SELECT current_exchange_rates.rate
+        FROM current_exchange_rates
+        INNER JOIN currencies
+        ON current_exchange_rates.currency_id = currencies.currency_id
+        WHERE currencies.code = $1
+    ) as base
+    LEFT JOIN product_locations
+    ON warehouses.warehouse_id = product_locations.warehouse_id
+    LEFT JOIN preferred_supplier_products
+    ON product_locations.product_id = preferred_supplier_products.product_id
+    LEFT JOIN current_supplier_prices as prices
+    ON preferred_supplier_products.product_id = prices.product_id
+    AND preferred_supplier_products.supplier_id = prices.supplier_id
+    LEFT JOIN current_exchange_rates as rates
+    ON prices.currency_id = rates.currency_id
+    WHERE warehouses.deleted_at IS NULL
+    GROUP BY warehouses.warehouse_id
+    ORDER BY value DESC, warehouses.warehouse_id
+    ";
+
+// Stock valued at current purchase prices of the preferred suppliers
+pub fn stock_value(client: &mut Client, base: &str) -> Result<Vec<WarehouseValue>, Error> {
+    check_currency(client, base)?;
+
+    Ok(client
+        .query(STOCK_VALUE, &[&base])?
+        .iter()
+        .map(WarehouseValue::from_row)
+        .collect())
+}
+
+pub(crate) const REQUIREMENTS_VALUE: &str = "
+    SELECT
+        clients.name as client,
+        ROUND(COALESCE(SUM(product_requirements.count * prices.price * rates.rate / base.rate), 0), 2) as value,
+        COUNT(*) FILTER (WHERE prices.price IS NULL) as unpriced
+    FROM product_requirements
+    CROSS JOIN (
+        SELECT current_exchange_rates.rate
+        FROM current_exchange_rates
+        INNER JOIN currencies
+        ON current_exchange_rates.currency_id = currencies.currency_id
+        WHERE currencies.code = $1
+    ) as base
+    INNER JOIN client_addresses
+    ON product_requirements.client_address_id = client_addresses.client_address_id
+    INNER JOIN clients
+    ON client_addresses.client_id = clients.client_id
+    LEFT JOIN current_client_prices as prices
+    ON clients.client_id = prices.client_id
+    AND product_requirements.product_id = prices.product_id
+    LEFT JOIN current_exchange_rates as rates
+    ON prices.currency_id = rates.currency_id
+    WHERE product_requirements.count > 0
+    AND clients.deleted_at IS NULL
+    GROUP BY clients.client_id
+    ORDER BY value DESC, clients.name
+    ";
+
+// Outstanding requirements valued at current client prices
+pub fn requirements_value(client: &mut Client, base: &str) -> Result<Vec<ClientValue>, Error> {
+    check_currency(client, base)?;
+
+    Ok(client
+        .query(REQUIREMENTS_VALUE, &[&base])?
+        .iter()
+        .map(ClientValue::from_row)
+        .collect())
+}
@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use postgres::{Client, Transaction};
+
+use crate::error::Error;
+use crate::fulfillment::Shortfall;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Draft,
+    Sent,
+    Confirmed,
+    Received,
+}
+
+impl Status {
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Draft => "draft",
+            Status::Sent => "sent",
+            Status::Confirmed => "confirmed",
+            Status::Received => "received",
+        }
+    }
+
+    // Status an order has to be in to move to this one
+    pub fn previous(self) -> Option<Status> {
+        match self {
+            Status::Draft => None,
+            Status::Sent => Some(Status::Draft),
+            Status::Confirmed => Some(Status::Sent),
+            Status::Received => Some(Status::Confirmed),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "draft" => Ok(Status::Draft),
+            "sent" => Ok(Status::Sent),
+            "confirmed" => Ok(Status::Confirmed),
+            "received" => Ok(Status::Received),
+            _ => Err(format!("unknown purchase order status '{s}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub product: String,
+    pub count: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PurchaseOrder {
+    pub purchase_order_id: i32,
+    pub supplier: String,
+    pub status: Status,
+    pub warehouse_id: Option<i32>,
+    pub lines: Vec<Line>,
+}
+
+// Drafts one order per preferred supplier for what the shortfalls miss beyond
+// already open orders, at least the minimum order quantity of the supplier
+pub fn generate(client: &mut Client, shortfalls: &[Shortfall]) -> Result<Vec<i32>, Error> {
+    let mut transaction = client.transaction()?;
+
+    let ordered: HashMap<i32, i64> = transaction
+        .query(
+            "
+            SELECT purchase_order_lines.product_id, SUM(purchase_order_lines.count) as count
+            FROM purchase_order_lines
+            INNER JOIN purchase_orders
+            ON purchase_order_lines.purchase_order_id = purchase_orders.purchase_order_id
+            WHERE purchase_orders.status <> 'received'
+            GROUP BY purchase_order_lines.product_id
+            ",
+            &[],
+        )?
+        .iter()
+        .map(|row| (row.get("product_id"), row.get("count")))
+        .collect();
+
+    let product_ids: Vec<i32> = shortfalls.iter().map(|s| s.product_id).collect();
+
+    let suppliers: HashMap<i32, (i32, i32)> = transaction
+        .query(
+            "
+            SELECT product_id, supplier_id, min_order_quantity
+            FROM preferred_supplier_products
+            WHERE product_id = ANY($1)
+            ",
+            &[&product_ids],
+        )?
+        .iter()
+        .map(|row| {
+            (
+                row.get("product_id"),
+                (row.get("supplier_id"), row.get("min_order_quantity")),
+            )
+        })
+        .collect();
+
+    let mut orders: BTreeMap<i32, Vec<(i32, i32)>> = BTreeMap::new();
+
+    for shortfall in shortfalls {
+        let count = shortfall.missing() - ordered.get(&shortfall.product_id).unwrap_or(&0);
+
+        if let (Some(&(supplier_id, min_order_quantity)), Ok(count @ 1..)) =
+            (suppliers.get(&shortfall.product_id), i32::try_from(count))
+        {
+            orders
+                .entry(supplier_id)
+                .or_default()
+                .push((shortfall.product_id, count.max(min_order_quantity)));
+        }
+    }
+
+    let mut purchase_order_ids = Vec::new();
+
+    for (supplier_id, lines) in orders {
+        let purchase_order_id: i32 = transaction
+            .query_one(
+                "
+                INSERT INTO purchase_orders (supplier_id)
+                VALUES ($1)
+                RETURNING purchase_order_id
+                ",
+                &[&supplier_id],
+            )?
+            .get("purchase_order_id");
+
+        for (product_id, count) in lines {
+            transaction.execute(
+                "
+                INSERT INTO purchase_order_lines (purchase_order_id, product_id, count)
+                VALUES ($1, $2, $3)
+                ",
+                &[&purchase_order_id, &product_id, &count],
+            )?;
+        }
+
+        purchase_order_ids.push(purchase_order_id);
+    }
+
+    transaction.commit()?;
+
+    Ok(purchase_order_ids)
+}
+
+fn transition(
+    transaction: &mut Transaction,
+    purchase_order_id: i32,
+    status: Status,
+) -> Result<(), Error> {
+    let row = transaction
+        .query_opt(
+            "
+            SELECT status
+            FROM purchase_orders
+            WHERE purchase_order_id = $1
+            FOR UPDATE
+            ",
+            &[&purchase_order_id],
+        )?
+        .ok_or_else(|| Error::NotFound(format!("purchase order {purchase_order_id}")))?;
+
+    let current: Status = row
+        .get::<_, &str>("status")
+        .parse()
+        .map_err(Error::Conflict)?;
+
+    if Some(current) != status.previous() {
+        return Err(Error::Conflict(format!(
+            "purchase order {purchase_order_id} is {current}, it can't become {status}"
+        )));
+    }
+
+    transaction.execute(
+        "
+        UPDATE purchase_orders
+        SET status = $2
+        WHERE purchase_order_id = $1
+        ",
+        &[&purchase_order_id, &status.name()],
+    )?;
+
+    Ok(())
+}
+
+pub fn send(client: &mut Client, purchase_order_id: i32) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+    transition(&mut transaction, purchase_order_id, Status::Sent)?;
+    transaction.commit()?;
+    Ok(())
+}
+
+pub fn confirm(client: &mut Client, purchase_order_id: i32) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+    transition(&mut transaction, purchase_order_id, Status::Confirmed)?;
+    transaction.commit()?;
+    Ok(())
+}
+
+// Puts everything ordered into the warehouse
+pub fn receive(
+    client: &mut Client,
+    purchase_order_id: i32,
+    warehouse_id: i32,
+) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    // deleted warehouses don't take stock, nor are they deleted meanwhile
+    transaction
+        .query_opt(
+            "
+            SELECT 1
+            FROM warehouses
+            WHERE warehouse_id = $1 AND deleted_at IS NULL
+            FOR SHARE
+            ",
+            &[&warehouse_id],
+        )?
+        .ok_or_else(|| Error::NotFound(format!("warehouse {warehouse_id}")))?;
+
+    transition(&mut transaction, purchase_order_id, Status::Received)?;
+
+    transaction.execute(
+        "
+        UPDATE purchase_orders
+        SET warehouse_id = $2
+        WHERE purchase_order_id = $1
+        ",
+        &[&purchase_order_id, &warehouse_id],
+    )?;
+
+    transaction.execute(
+        "
+        INSERT INTO product_locations (warehouse_id, product_id, count)
+        SELECT $2, product_id, count
+        FROM purchase_order_lines
+        WHERE purchase_order_id = $1
+        ON CONFLICT (warehouse_id, product_id)
+        DO UPDATE SET count = product_locations.count + EXCLUDED.count
+        ",
+        &[&purchase_order_id, &warehouse_id],
+    )?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+pub fn list(client: &mut Client) -> Result<Vec<PurchaseOrder>, Error> {
+    let mut orders: Vec<PurchaseOrder> = Vec::new();
+
+    for row in client.query(
+        "
+        SELECT
+            purchase_orders.purchase_order_id,
+            suppliers.name as supplier,
+            purchase_orders.status,
+            purchase_orders.warehouse_id,
+            products.name as product,
+            purchase_order_lines.count
+        FROM purchase_orders
+        INNER JOIN suppliers
+        ON purchase_orders.supplier_id = suppliers.supplier_id
+        INNER JOIN purchase_order_lines
+        ON purchase_orders.purchase_order_id = purchase_order_lines.purchase_order_id
+        INNER JOIN products
+        ON purchase_order_lines.product_id = products.product_id
+        ORDER BY purchase_orders.purchase_order_id, purchase_order_lines.product_id
+        ",
+        &[],
+    )? {
+        let purchase_order_id: i32 = row.get("purchase_order_id");
+
+        if orders.last().map(|order| order.purchase_order_id) != Some(purchase_order_id) {
+            orders.push(PurchaseOrder {
+                purchase_order_id,
+                supplier: row.get("supplier"),
+                status: row
+                    .get::<_, &str>("status")
+                    .parse()
+                    .map_err(Error::Conflict)?,
+                warehouse_id: row.get("warehouse_id"),
+                lines: Vec::new(),
+            });
+        }
+
+        if let Some(order) = orders.last_mut() {
+            order.lines.push(Line {
+                product: row.get("product"),
+                count: row.get("count"),
+            });
+        }
+    }
+
+    Ok(orders)
+}
+
+pub fn print_purchase_orders(orders: &[PurchaseOrder]) {
+    println!(
+        "
+Purchase orders
+{:8} {:50} {:10} {:10} {:40} Count
+",
+        "Order", "Supplier", "Status", "Warehouse", "Product"
+    );
+
+    for order in orders {
+        let warehouse = order
+            .warehouse_id
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| "-".to_owned());
+
+        for line in &order.lines {
+            println!(
+                "{:<8} {:50} {:10} {:10} {:40} {}",
+                order.purchase_order_id,
+                order.supplier,
+                order.status,
+                warehouse,
+                line.product,
+                line.count
+            );
+        }
+    }
+}
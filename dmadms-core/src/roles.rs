@@ -0,0 +1,208 @@
+use postgres::{Client, Transaction};
+
+use crate::error::Error;
+use crate::{archive, history};
+
+// Roles of the staff, assumed by the connection with `assume`. The owner owns
+// the tables and is the only one running DDL, the app reads and writes rows,
+// reporting only reads them and the auditor reads the history, the archive and
+// who the users are. Password hashes are left out of every role but the owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Owner,
+    App,
+    Reporting,
+    Auditor,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Owner, Role::App, Role::Reporting, Role::Auditor];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Owner => "dmadms_owner",
+            Role::App => "dmadms_app",
+            Role::Reporting => "dmadms_reporting",
+            Role::Auditor => "dmadms_auditor",
+        }
+    }
+}
+
+// Roles are shared by every database of the server, they are created once and
+// granted to the user creating the schema so it can switch to them
+const ROLES: &str = "
+    DO $$
+    DECLARE
+        role_name TEXT;
+    BEGIN
+        FOREACH role_name IN ARRAY ARRAY[
+            'dmadms_owner', 'dmadms_app', 'dmadms_reporting', 'dmadms_auditor',
+            'dmadms_client', 'dmadms_supplier'
+        ] LOOP
+            BEGIN
+                EXECUTE format('CREATE ROLE %I NOLOGIN', role_name);
+            EXCEPTION WHEN duplicate_object OR unique_violation THEN
+                NULL;
+            END;
+
+            BEGIN
+                EXECUTE format('GRANT %I TO %I', role_name, current_user);
+            EXCEPTION WHEN duplicate_object OR unique_violation THEN
+                NULL;
+            END;
+        END LOOP;
+    END
+    $$
+    ";
+
+// Run by the user creating the schema before the owner takes over. The rest of
+// the transaction creates tables as the owner, each granted to the app and
+// reporting roles as it's created.
+const SETUP_STATEMENTS: [&str; 5] = [
+    ROLES,
+    "
+    DO $$
+    BEGIN
+        EXECUTE format('GRANT USAGE, CREATE ON SCHEMA %I TO dmadms_owner', current_schema());
+        EXECUTE format(
+            'GRANT USAGE ON SCHEMA %I TO dmadms_app, dmadms_reporting, dmadms_auditor, dmadms_client, dmadms_supplier',
+            current_schema()
+        );
+    END
+    $$
+    ",
+    "
+    CREATE EXTENSION IF NOT EXISTS pgcrypto;
+    CREATE EXTENSION IF NOT EXISTS pg_trgm;
+    ",
+    "SET LOCAL ROLE dmadms_owner",
+    "
+    ALTER DEFAULT PRIVILEGES GRANT SELECT, INSERT, UPDATE, DELETE ON TABLES TO dmadms_app;
+    ALTER DEFAULT PRIVILEGES GRANT USAGE, SELECT ON SEQUENCES TO dmadms_app;
+    ALTER DEFAULT PRIVILEGES GRANT SELECT ON TABLES TO dmadms_reporting;
+    ",
+];
+
+pub fn setup_statements() -> Vec<String> {
+    SETUP_STATEMENTS.into_iter().map(str::to_owned).collect()
+}
+
+// Lets a migration alter a database created before the roles existed: the
+// roles are set up, the owner takes over the tables and views of the user
+// running it, and the rest of the transaction runs as the owner. Tables taken
+// over aren't granted to the other roles.
+pub(crate) fn take_over(transaction: &mut Transaction) -> Result<(), Error> {
+    let (setup, owner) = SETUP_STATEMENTS.split_at(3);
+
+    for statement in setup {
+        transaction.batch_execute(statement)?;
+    }
+
+    let relations = transaction.query(
+        "
+        SELECT
+            pg_class.relname as name,
+            pg_get_userbyid(pg_class.relowner) as owner,
+            pg_has_role(pg_class.relowner, 'MEMBER') as member,
+            format(
+                'ALTER %s %I OWNER TO dmadms_owner',
+                CASE pg_class.relkind
+                    WHEN 'r' THEN 'TABLE'
+                    WHEN 'v' THEN 'VIEW'
+                    ELSE 'MATERIALIZED VIEW'
+                END,
+                pg_class.relname
+            ) as statement
+        FROM pg_class
+        INNER JOIN pg_namespace
+        ON pg_class.relnamespace = pg_namespace.oid
+        WHERE pg_namespace.nspname = current_schema()
+        AND pg_class.relkind IN ('r', 'v', 'm')
+        AND pg_get_userbyid(pg_class.relowner) <> 'dmadms_owner'
+        ORDER BY pg_class.relname
+        ",
+        &[],
+    )?;
+
+    for relation in &relations {
+        if !relation.get::<_, bool>("member") {
+            return Err(Error::Invalid(format!(
+                "{} is owned by {}, run the migration as that role or a superuser",
+                relation.get::<_, &str>("name"),
+                relation.get::<_, &str>("owner")
+            )));
+        }
+
+        transaction.batch_execute(relation.get("statement"))?;
+    }
+
+    for statement in owner {
+        transaction.batch_execute(statement)?;
+    }
+
+    Ok(())
+}
+
+// Narrows the default privileges once every table exists: history is written
+// by its trigger only, and users are read without their passwords
+pub fn grant_statements() -> Vec<String> {
+    let archive: Vec<String> = archive::tables()
+        .iter()
+        .map(|table| format!("{table}_archive"))
+        .collect();
+    let users = "user_id, supplier_id, client_id, user_role_id, name, deleted_at";
+
+    vec![
+        history_grant(&history::Table::ALL),
+        "REVOKE UPDATE, DELETE ON sales_order_status_history FROM dmadms_app".to_owned(),
+        format!(
+            "
+            REVOKE SELECT ON users, users_archive FROM dmadms_app, dmadms_reporting;
+            GRANT SELECT ({users}) ON users TO dmadms_app, dmadms_reporting, dmadms_auditor;
+            GRANT SELECT ({users}, archived_at) ON users_archive TO dmadms_auditor;
+            "
+        ),
+        format!(
+            "
+            GRANT SELECT
+            ON {}, sales_order_status_history, user_roles, suppliers, clients, products, warehouses
+            TO dmadms_auditor
+            ",
+            archive
+                .iter()
+                .filter(|table| *table != "users_archive")
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    ]
+}
+
+// History is written by its trigger only and read by the auditor
+pub(crate) fn history_grant(tables: &[history::Table]) -> String {
+    let history = tables
+        .iter()
+        .map(|table| format!("{}_history", table.table()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "
+        REVOKE INSERT, UPDATE, DELETE ON {history} FROM dmadms_app;
+        GRANT SELECT ON {history} TO dmadms_auditor;
+        "
+    )
+}
+
+// Switches the session to a staff role until `reset`
+pub fn assume(client: &mut Client, role: Role) -> Result<(), Error> {
+    client.batch_execute(&format!("SET ROLE {}", role.name()))?;
+
+    Ok(())
+}
+
+pub fn reset(client: &mut Client) -> Result<(), Error> {
+    client.batch_execute("RESET ROLE")?;
+
+    Ok(())
+}
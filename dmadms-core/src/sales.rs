@@ -0,0 +1,570 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDateTime;
+use postgres::{Client, Transaction};
+
+use crate::error::Error;
+use crate::lots;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    New,
+    Confirmed,
+    Shipped,
+    Delivered,
+    Cancelled,
+}
+
+impl Status {
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::New => "new",
+            Status::Confirmed => "confirmed",
+            Status::Shipped => "shipped",
+            Status::Delivered => "delivered",
+            Status::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn can_become(self, status: Status) -> bool {
+        matches!(
+            (self, status),
+            (Status::New, Status::Confirmed)
+                | (Status::Confirmed, Status::Shipped)
+                | (Status::Shipped, Status::Delivered)
+                | (Status::New | Status::Confirmed, Status::Cancelled)
+        )
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "new" => Ok(Status::New),
+            "confirmed" => Ok(Status::Confirmed),
+            "shipped" => Ok(Status::Shipped),
+            "delivered" => Ok(Status::Delivered),
+            "cancelled" => Ok(Status::Cancelled),
+            _ => Err(format!("unknown sales order status '{s}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub product: String,
+    pub count: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct SalesOrder {
+    pub sales_order_id: i32,
+    pub client: String,
+    pub address: String,
+    pub status: Status,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StatusChange {
+    pub status: Status,
+    pub changed_at: NaiveDateTime,
+}
+
+fn record_status(
+    transaction: &mut Transaction,
+    sales_order_id: i32,
+    status: Status,
+) -> Result<(), Error> {
+    transaction.execute(
+        "
+        INSERT INTO sales_order_status_history (sales_order_id, status)
+        VALUES ($1, $2)
+        ",
+        &[&sales_order_id, &status.name()],
+    )?;
+
+    Ok(())
+}
+
+// Orders whatever the address still requires and isn't already on an open order
+pub fn create_from_requirements(client: &mut Client, client_address_id: i32) -> Result<i32, Error> {
+    let mut transaction = client.transaction()?;
+
+    let client_id: i32 = transaction
+        .query_opt(
+            "
+            SELECT clients.client_id
+            FROM client_addresses
+            INNER JOIN clients
+            ON client_addresses.client_id = clients.client_id
+            WHERE client_addresses.client_address_id = $1
+            AND clients.deleted_at IS NULL
+            ",
+            &[&client_address_id],
+        )?
+        .ok_or_else(|| Error::NotFound(format!("client address {client_address_id}")))?
+        .get("client_id");
+
+    let lines: Vec<(i32, i32)> = transaction
+        .query(
+            "
+            SELECT required.product_id, (required.count - COALESCE(ordered.count, 0))::INTEGER as count
+            FROM (
+                SELECT product_requirements.product_id, SUM(product_requirements.count) as count
+                FROM product_requirements
+                INNER JOIN products
+                ON product_requirements.product_id = products.product_id
+                WHERE product_requirements.client_address_id = $1
+                AND products.deleted_at IS NULL
+                GROUP BY product_requirements.product_id
+            ) as required
+            LEFT JOIN (
+                SELECT sales_order_lines.product_id, SUM(sales_order_lines.count) as count
+                FROM sales_order_lines
+                INNER JOIN sales_orders
+                ON sales_order_lines.sales_order_id = sales_orders.sales_order_id
+                WHERE sales_orders.client_address_id = $1
+                AND sales_orders.status IN ('new', 'confirmed')
+                GROUP BY sales_order_lines.product_id
+            ) as ordered
+            ON required.product_id = ordered.product_id
+            WHERE required.count - COALESCE(ordered.count, 0) > 0
+            ORDER BY required.product_id
+            ",
+            &[&client_address_id],
+        )?
+        .iter()
+        .map(|row| (row.get("product_id"), row.get("count")))
+        .collect();
+
+    if lines.is_empty() {
+        return Err(Error::Conflict(format!(
+            "client address {client_address_id} has no outstanding requirements"
+        )));
+    }
+
+    let sales_order_id: i32 = transaction
+        .query_one(
+            "
+            INSERT INTO sales_orders (client_id, client_address_id)
+            VALUES ($1, $2)
+            RETURNING sales_order_id
+            ",
+            &[&client_id, &client_address_id],
+        )?
+        .get("sales_order_id");
+
+    for (product_id, count) in lines {
+        transaction.execute(
+            "
+            INSERT INTO sales_order_lines (sales_order_id, product_id, count)
+            VALUES ($1, $2, $3)
+            ",
+            &[&sales_order_id, &product_id, &count],
+        )?;
+    }
+
+    record_status(&mut transaction, sales_order_id, Status::New)?;
+
+    transaction.commit()?;
+
+    Ok(sales_order_id)
+}
+
+fn transition(
+    transaction: &mut Transaction,
+    sales_order_id: i32,
+    status: Status,
+) -> Result<(), Error> {
+    let row = transaction
+        .query_opt(
+            "
+            SELECT status
+            FROM sales_orders
+            WHERE sales_order_id = $1
+            FOR UPDATE
+            ",
+            &[&sales_order_id],
+        )?
+        .ok_or_else(|| Error::NotFound(format!("sales order {sales_order_id}")))?;
+
+    let current: Status = row
+        .get::<_, &str>("status")
+        .parse()
+        .map_err(Error::Conflict)?;
+
+    if !current.can_become(status) {
+        return Err(Error::Conflict(format!(
+            "sales order {sales_order_id} is {current}, it can't become {status}"
+        )));
+    }
+
+    transaction.execute(
+        "
+        UPDATE sales_orders
+        SET status = $2
+        WHERE sales_order_id = $1
+        ",
+        &[&sales_order_id, &status.name()],
+    )?;
+
+    record_status(transaction, sales_order_id, status)
+}
+
+fn change_status(client: &mut Client, sales_order_id: i32, status: Status) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+    transition(&mut transaction, sales_order_id, status)?;
+    transaction.commit()?;
+    Ok(())
+}
+
+pub fn confirm(client: &mut Client, sales_order_id: i32) -> Result<(), Error> {
+    change_status(client, sales_order_id, Status::Confirmed)
+}
+
+pub fn deliver(client: &mut Client, sales_order_id: i32) -> Result<(), Error> {
+    change_status(client, sales_order_id, Status::Delivered)
+}
+
+pub fn cancel(client: &mut Client, sales_order_id: i32) -> Result<(), Error> {
+    change_status(client, sales_order_id, Status::Cancelled)
+}
+
+// Ships the whole order from one warehouse: takes products out of the
+// warehouse first-expired-first-out, reduces the address requirements and releases allocations
+// reserved for them. Stock allocated to requirements of other addresses isn't shipped.
+pub fn ship(client: &mut Client, sales_order_id: i32, warehouse_id: i32) -> Result<i32, Error> {
+    let mut transaction = client.transaction()?;
+
+    transition(&mut transaction, sales_order_id, Status::Shipped)?;
+
+    let shipment_id: i32 = transaction
+        .query_one(
+            "
+            INSERT INTO shipments (sales_order_id, warehouse_id)
+            VALUES ($1, $2)
+            RETURNING shipment_id
+            ",
+            &[&sales_order_id, &warehouse_id],
+        )?
+        .get("shipment_id");
+
+    let lines: Vec<(i32, i32, i32)> = transaction
+        .query(
+            "
+            SELECT sales_orders.client_address_id, sales_order_lines.product_id, sales_order_lines.count
+            FROM sales_order_lines
+            INNER JOIN sales_orders
+            ON sales_order_lines.sales_order_id = sales_orders.sales_order_id
+            WHERE sales_orders.sales_order_id = $1
+            ORDER BY sales_order_lines.product_id
+            ",
+            &[&sales_order_id],
+        )?
+        .iter()
+        .map(|row| {
+            (
+                row.get("client_address_id"),
+                row.get("product_id"),
+                row.get("count"),
+            )
+        })
+        .collect();
+
+    for (client_address_id, product_id, count) in lines {
+        let free = unreserved(
+            &mut transaction,
+            warehouse_id,
+            product_id,
+            client_address_id,
+        )?;
+
+        if free < count as i64 {
+            return Err(Error::Conflict(format!(
+                "only {free} of product {product_id} at warehouse {warehouse_id} aren't reserved \
+                 for other requirements, {count} to ship"
+            )));
+        }
+
+        for (product_lot_id, picked) in
+            lots::consume(&mut transaction, warehouse_id, product_id, count)?
+        {
+            transaction.execute(
+                "
+                INSERT INTO shipment_lines (shipment_id, product_id, product_lot_id, count)
+                VALUES ($1, $2, $3, $4)
+                ",
+                &[&shipment_id, &product_id, &product_lot_id, &picked],
+            )?;
+        }
+
+        let requirements: Vec<(i32, i32)> = transaction
+            .query(
+                "
+                SELECT product_requirement_id, count
+                FROM product_requirements
+                WHERE client_address_id = $1 AND product_id = $2 AND count > 0
+                ORDER BY product_requirement_id
+                FOR UPDATE
+                ",
+                &[&client_address_id, &product_id],
+            )?
+            .iter()
+            .map(|row| (row.get("product_requirement_id"), row.get("count")))
+            .collect();
+
+        let mut left = count;
+        for (product_requirement_id, required) in requirements {
+            if left == 0 {
+                break;
+            }
+
+            let fulfilled = left.min(required);
+            left -= fulfilled;
+
+            transaction.execute(
+                "
+                UPDATE product_requirements
+                SET count = count - $2
+                WHERE product_requirement_id = $1
+                ",
+                &[&product_requirement_id, &fulfilled],
+            )?;
+
+            release_allocations(
+                &mut transaction,
+                product_requirement_id,
+                warehouse_id,
+                fulfilled,
+            )?;
+        }
+    }
+
+    transaction.commit()?;
+
+    Ok(shipment_id)
+}
+
+// Stock of a warehouse not allocated to requirements of other addresses. The
+// stock row stays locked, plans can't be committed against it meanwhile.
+fn unreserved(
+    transaction: &mut Transaction,
+    warehouse_id: i32,
+    product_id: i32,
+    client_address_id: i32,
+) -> Result<i64, Error> {
+    let stock: i32 = transaction
+        .query_opt(
+            "
+            SELECT count
+            FROM product_locations
+            WHERE warehouse_id = $1 AND product_id = $2
+            FOR UPDATE
+            ",
+            &[&warehouse_id, &product_id],
+        )?
+        .map(|row| row.get("count"))
+        .unwrap_or(0);
+
+    let reserved: i64 = transaction
+        .query_one(
+            "
+            SELECT COALESCE(SUM(allocations.count), 0)
+            FROM allocations
+            INNER JOIN product_requirements
+            ON allocations.product_requirement_id = product_requirements.product_requirement_id
+            WHERE allocations.warehouse_id = $1
+            AND product_requirements.product_id = $2
+            AND product_requirements.client_address_id <> $3
+            ",
+            &[&warehouse_id, &product_id, &client_address_id],
+        )?
+        .get(0);
+
+    Ok(stock as i64 - reserved)
+}
+
+// Releases what was shipped from the allocations at the shipping warehouse,
+// then whatever the requirement has allocated beyond what it still needs at
+// any warehouse
+fn release_allocations(
+    transaction: &mut Transaction,
+    product_requirement_id: i32,
+    warehouse_id: i32,
+    count: i32,
+) -> Result<(), Error> {
+    let allocations: Vec<(i32, i32, i32)> = transaction
+        .query(
+            "
+            SELECT allocation_id, warehouse_id, count
+            FROM allocations
+            WHERE product_requirement_id = $1
+            ORDER BY allocation_id
+            FOR UPDATE
+            ",
+            &[&product_requirement_id],
+        )?
+        .iter()
+        .map(|row| {
+            (
+                row.get("allocation_id"),
+                row.get("warehouse_id"),
+                row.get("count"),
+            )
+        })
+        .collect();
+
+    let required: i32 = transaction
+        .query_one(
+            "SELECT count FROM product_requirements WHERE product_requirement_id = $1",
+            &[&product_requirement_id],
+        )?
+        .get("count");
+
+    let mut shipped = count;
+    let mut kept: Vec<(i32, i32)> = Vec::new();
+
+    for (allocation_id, allocated_at, allocated) in allocations {
+        let released = if allocated_at == warehouse_id {
+            allocated.min(shipped)
+        } else {
+            0
+        };
+        shipped -= released;
+
+        kept.push((allocation_id, allocated - released));
+    }
+
+    // the last allocations go first when more is allocated than required
+    let mut excess = kept.iter().map(|(_, left)| *left as i64).sum::<i64>() - required as i64;
+    for (_, left) in kept.iter_mut().rev() {
+        let released = (*left as i64).min(excess.max(0)) as i32;
+        *left -= released;
+        excess -= released as i64;
+    }
+
+    for (allocation_id, left) in kept {
+        if left == 0 {
+            transaction.execute(
+                "DELETE FROM allocations WHERE allocation_id = $1",
+                &[&allocation_id],
+            )?;
+        } else {
+            transaction.execute(
+                "UPDATE allocations SET count = $2 WHERE allocation_id = $1 AND count <> $2",
+                &[&allocation_id, &left],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn list(client: &mut Client) -> Result<Vec<SalesOrder>, Error> {
+    let mut orders: Vec<SalesOrder> = Vec::new();
+
+    for row in client.query(
+        "
+        SELECT
+            sales_orders.sales_order_id,
+            clients.name as client,
+            client_addresses.address,
+            sales_orders.status,
+            products.name as product,
+            sales_order_lines.count
+        FROM sales_orders
+        INNER JOIN clients
+        ON sales_orders.client_id = clients.client_id
+        INNER JOIN client_addresses
+        ON sales_orders.client_address_id = client_addresses.client_address_id
+        INNER JOIN sales_order_lines
+        ON sales_orders.sales_order_id = sales_order_lines.sales_order_id
+        INNER JOIN products
+        ON sales_order_lines.product_id = products.product_id
+        ORDER BY sales_orders.sales_order_id, sales_order_lines.product_id
+        ",
+        &[],
+    )? {
+        let sales_order_id: i32 = row.get("sales_order_id");
+
+        if orders.last().map(|order| order.sales_order_id) != Some(sales_order_id) {
+            orders.push(SalesOrder {
+                sales_order_id,
+                client: row.get("client"),
+                address: row.get("address"),
+                status: row
+                    .get::<_, &str>("status")
+                    .parse()
+                    .map_err(Error::Conflict)?,
+                lines: Vec::new(),
+            });
+        }
+
+        if let Some(order) = orders.last_mut() {
+            order.lines.push(Line {
+                product: row.get("product"),
+                count: row.get("count"),
+            });
+        }
+    }
+
+    Ok(orders)
+}
+
+pub fn history(client: &mut Client, sales_order_id: i32) -> Result<Vec<StatusChange>, Error> {
+    client
+        .query(
+            "
+            SELECT status, changed_at
+            FROM sales_order_status_history
+            WHERE sales_order_id = $1
+            ORDER BY changed_at, sales_order_status_id
+            ",
+            &[&sales_order_id],
+        )?
+        .iter()
+        .map(|row| {
+            Ok(StatusChange {
+                status: row
+                    .get::<_, &str>("status")
+                    .parse()
+                    .map_err(Error::Conflict)?,
+                changed_at: row.get("changed_at"),
+            })
+        })
+        .collect()
+}
+
+pub fn print_sales_orders(orders: &[SalesOrder]) {
+    println!(
+        "
+Sales orders
+{:8} {:30} {:30} {:10} {:40} Count
+",
+        "Order", "Client", "Address", "Status", "Product"
+    );
+
+    for order in orders {
+        for line in &order.lines {
+            println!(
+                "{:<8} {:30} {:30} {:10} {:40} {}",
+                order.sales_order_id,
+                order.client,
+                order.address,
+                order.status,
+                line.product,
+                line.count
+            );
+        }
+    }
+}
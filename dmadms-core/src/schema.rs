@@ -0,0 +1,963 @@
+use postgres::Client;
+use sha2::{Digest, Sha256};
+
+use crate::{archive, categories, history, roles, tenancy, validation};
+
+// Tables init creates, and former ones, all dropped by a reset
+pub const TABLES: [&str; 67] = [
+    "countries",
+    "suppliers",
+    "product_categories",
+    "product_subcategories",
+    "categories",
+    "category_closure",
+    "products",
+    "supplier_products",
+    "clients",
+    "client_addresses",
+    "product_requirements",
+    "warehouses",
+    "product_locations",
+    "user_roles",
+    "users",
+    "fulfillment_plans",
+    "allocations",
+    "purchase_orders",
+    "purchase_order_lines",
+    "sales_orders",
+    "sales_order_lines",
+    "sales_order_status_history",
+    "shipments",
+    "shipment_lines",
+    "currencies",
+    "exchange_rates",
+    "supplier_prices",
+    "client_prices",
+    "product_lots",
+    "lot_locations",
+    "document_types",
+    "required_documents",
+    "documents",
+    "units",
+    "product_units",
+    "allergens",
+    "product_allergens",
+    "dietary_flags",
+    "product_dietary_flags",
+    "product_nutrition",
+    "product_ingredients",
+    "product_barcodes",
+    "users_archive",
+    "suppliers_archive",
+    "clients_archive",
+    "products_archive",
+    "warehouses_archive",
+    "supplier_prices_archive",
+    "supplier_products_archive",
+    "client_prices_archive",
+    "product_requirements_archive",
+    "client_addresses_archive",
+    "product_barcodes_archive",
+    "product_units_archive",
+    "product_nutrition_archive",
+    "product_allergens_archive",
+    "product_dietary_flags_archive",
+    "product_ingredients_archive",
+    "product_locations_archive",
+    "lot_locations_archive",
+    "products_history",
+    "suppliers_history",
+    "supplier_products_history",
+    "clients_history",
+    "client_addresses_history",
+    "categories_history",
+    "tenant_roles",
+];
+
+// Functions init creates, dropped after the tables so the owner can create them again
+pub const FUNCTIONS: [&str; 6] = [
+    "gtin_valid(TEXT)",
+    "record_history()",
+    "archive_user(INTEGER)",
+    "current_client_id()",
+    "current_supplier_id()",
+    "refresh_product_search()",
+];
+
+// Statements dropping every table and function, each may fail when it is
+// missing or already dropped with another one
+pub(crate) fn drop_statements() -> impl Iterator<Item = String> {
+    TABLES
+        .into_iter()
+        .map(|table_name| format!("DROP TABLE {table_name} CASCADE"))
+        .chain(
+            FUNCTIONS
+                .into_iter()
+                .map(|function| format!("DROP FUNCTION {function} CASCADE")),
+        )
+}
+
+pub fn drop_tables(client: &mut Client) {
+    for statement in drop_statements() {
+        _ = client.batch_execute(&statement);
+    }
+}
+
+// Tables, views and functions in the order they refer to each other
+const TABLE_STATEMENTS: [&str; 40] = [
+    "
+    CREATE TABLE currencies (
+        currency_id SERIAL PRIMARY KEY,
+        code        TEXT UNIQUE NOT NULL,
+        name        TEXT NOT NULL,
+        CHECK (code ~ '^[A-Z]{3}$')
+    )
+    ",
+    "
+    CREATE TABLE countries (
+        country_id  SERIAL PRIMARY KEY,
+        currency_id INTEGER REFERENCES currencies, -- NULLABLE
+        name        TEXT UNIQUE NOT NULL
+    )
+    ",
+    "
+    CREATE TABLE suppliers (
+        supplier_id SERIAL PRIMARY KEY,
+        country_id  INTEGER REFERENCES countries NOT NULL,
+        name        TEXT NOT NULL,
+        email       TEXT NOT NULL,
+        deleted_at  TIMESTAMP -- NULLABLE, soft deleted when set
+    )
+    ",
+    categories::TABLES,
+    "
+    CREATE TABLE products (
+        product_id      SERIAL PRIMARY KEY,
+        category_id     INTEGER REFERENCES categories NOT NULL,
+        name            TEXT NOT NULL,
+        deleted_at      TIMESTAMP -- NULLABLE, soft deleted when set
+    );
+
+    CREATE UNIQUE INDEX product_name_index
+    ON products(name)
+    WHERE deleted_at IS NULL;
+    ",
+    "
+    CREATE TABLE supplier_products (
+        supplier_product_id SERIAL PRIMARY KEY,
+        supplier_id         INTEGER REFERENCES suppliers NOT NULL,
+        product_id          INTEGER REFERENCES products NOT NULL,
+        sku                 TEXT NOT NULL, -- supplier's own code of the product
+        lead_time_days      INTEGER NOT NULL,
+        min_order_quantity  INTEGER NOT NULL DEFAULT 1,
+        active              BOOLEAN NOT NULL DEFAULT TRUE,
+        CHECK (lead_time_days >= 0),
+        CHECK (min_order_quantity > 0),
+        UNIQUE (supplier_id, product_id),
+        UNIQUE (supplier_id, sku)
+    );
+
+    -- Active offering with the shortest lead time is where a product is bought
+    CREATE VIEW preferred_supplier_products AS
+    SELECT DISTINCT ON (supplier_products.product_id) supplier_products.*
+    FROM supplier_products
+    INNER JOIN suppliers
+    ON supplier_products.supplier_id = suppliers.supplier_id
+    WHERE supplier_products.active AND suppliers.deleted_at IS NULL
+    ORDER BY supplier_products.product_id, supplier_products.lead_time_days, supplier_products.supplier_product_id;
+    ",
+    "
+    CREATE TABLE clients (
+        client_id   SERIAL PRIMARY KEY,
+        name        TEXT NOT NULL,
+        email       TEXT NOT NULL,
+        deleted_at  TIMESTAMP -- NULLABLE, soft deleted when set
+    );
+
+    CREATE UNIQUE INDEX client_name_index
+    ON clients(name)
+    WHERE deleted_at IS NULL;
+    ",
+    "
+    CREATE TABLE client_addresses (
+        client_address_id   SERIAL PRIMARY KEY,
+        client_id           INTEGER REFERENCES clients NOT NULL,
+        address             TEXT NOT NULL,
+        latitude            DOUBLE PRECISION, -- NULLABLE
+        longitude           DOUBLE PRECISION, -- NULLABLE
+        CHECK ((latitude IS NULL) = (longitude IS NULL)),
+        UNIQUE (client_address_id, client_id)
+    )
+    ",
+    "
+    CREATE TABLE units (
+        unit_id SERIAL PRIMARY KEY,
+        code    TEXT UNIQUE NOT NULL,
+        name    TEXT NOT NULL
+    )
+    ",
+    "
+    CREATE TABLE product_units (
+        product_unit_id SERIAL PRIMARY KEY,
+        product_id      INTEGER REFERENCES products NOT NULL,
+        unit_id         INTEGER REFERENCES units NOT NULL,
+        factor          INTEGER NOT NULL, -- count of base units in this unit
+        CHECK (factor > 0),
+        UNIQUE (product_id, unit_id)
+    );
+
+    CREATE UNIQUE INDEX product_base_unit_index
+    ON product_units(product_id)
+    WHERE factor = 1;
+    ",
+    "
+    CREATE OR REPLACE FUNCTION gtin_valid(gtin TEXT) RETURNS BOOLEAN
+    LANGUAGE SQL IMMUTABLE AS $$
+        SELECT CASE
+            WHEN gtin ~ '^([0-9]{8}|[0-9]{12,14})$' THEN (
+                SELECT SUM(substr(lpad(gtin, 14, '0'), i, 1)::INTEGER * (2 * (i % 2) + 1)) % 10 = 0
+                FROM generate_series(1, 14) as i
+            )
+            ELSE FALSE
+        END
+    $$;
+
+    CREATE TABLE product_barcodes (
+        product_barcode_id  SERIAL PRIMARY KEY,
+        product_unit_id     INTEGER REFERENCES product_units NOT NULL,
+        gtin                TEXT NOT NULL, -- GTIN-8, GTIN-12, GTIN-13 or GTIN-14
+        CHECK (gtin_valid(gtin))
+    );
+
+    CREATE UNIQUE INDEX product_barcode_gtin_index
+    ON product_barcodes((lpad(gtin, 14, '0')));
+    ",
+    "
+    CREATE TABLE product_requirements (
+        product_requirement_id  SERIAL PRIMARY KEY,
+        product_id              INTEGER REFERENCES products NOT NULL,
+        client_address_id       INTEGER REFERENCES client_addresses NOT NULL,
+        count                   INTEGER NOT NULL, -- in base units of the product
+        unit_id                 INTEGER REFERENCES units, -- NULLABLE, unit the count is stated in
+        CHECK (count >= 0)
+    )
+    ",
+    "
+    CREATE TABLE warehouses (
+        warehouse_id    SERIAL PRIMARY KEY,
+        address         TEXT NOT NULL,
+        latitude        DOUBLE PRECISION, -- NULLABLE
+        longitude       DOUBLE PRECISION, -- NULLABLE
+        deleted_at      TIMESTAMP, -- NULLABLE, soft deleted when set
+        CHECK ((latitude IS NULL) = (longitude IS NULL))
+    );
+
+    CREATE UNIQUE INDEX warehouse_address_index
+    ON warehouses(address)
+    WHERE deleted_at IS NULL;
+    ",
+    "
+    CREATE TABLE product_locations (
+        product_location_id SERIAL PRIMARY KEY,
+        warehouse_id        INTEGER REFERENCES warehouses NOT NULL,
+        product_id          INTEGER REFERENCES products NOT NULL,
+        count               INTEGER NOT NULL, -- in base units of the product
+        unit_id             INTEGER REFERENCES units, -- NULLABLE, unit the product is stored in
+        CHECK (count >= 0),
+        UNIQUE (warehouse_id, product_id)
+    )
+    ",
+    "
+    CREATE TABLE user_roles (
+        user_role_id    SERIAL PRIMARY KEY,
+        name            TEXT UNIQUE NOT NULL
+    )
+    ",
+    "
+    CREATE TABLE users (
+        user_id             SERIAL PRIMARY KEY,
+        supplier_id         INTEGER REFERENCES suppliers, -- NULLABLE
+        client_id           INTEGER REFERENCES clients, -- NULLABLE
+        user_role_id        INTEGER REFERENCES user_roles NOT NULL,
+        name                TEXT NOT NULL,
+        password            TEXT NOT NULL, -- use encryption
+        deleted_at          TIMESTAMP, -- NULLABLE, soft deleted when set
+        CHECK ((supplier_id IS NULL) OR (client_id IS NULL))
+    );
+
+    -- names and owners of deleted users can be taken again
+    CREATE UNIQUE INDEX user_name_index
+    ON users(name)
+    WHERE deleted_at IS NULL;
+
+    CREATE UNIQUE INDEX user_supplier_index
+    ON users(supplier_id)
+    WHERE deleted_at IS NULL;
+
+    CREATE UNIQUE INDEX user_client_index
+    ON users(client_id)
+    WHERE deleted_at IS NULL;
+    ",
+    "
+    CREATE TABLE fulfillment_plans (
+        fulfillment_plan_id SERIAL PRIMARY KEY,
+        strategy            TEXT NOT NULL,
+        created_at          TIMESTAMP NOT NULL DEFAULT now()
+    )
+    ",
+    "
+    CREATE TABLE allocations (
+        allocation_id           SERIAL PRIMARY KEY,
+        fulfillment_plan_id     INTEGER REFERENCES fulfillment_plans NOT NULL,
+        product_requirement_id  INTEGER REFERENCES product_requirements NOT NULL,
+        warehouse_id            INTEGER REFERENCES warehouses NOT NULL,
+        count                   INTEGER NOT NULL,
+        CHECK (count > 0)
+    )
+    ",
+    "
+    CREATE TABLE purchase_orders (
+        purchase_order_id   SERIAL PRIMARY KEY,
+        supplier_id         INTEGER REFERENCES suppliers NOT NULL,
+        warehouse_id        INTEGER REFERENCES warehouses, -- NULLABLE, set when received
+        status              TEXT NOT NULL DEFAULT 'draft',
+        created_at          TIMESTAMP NOT NULL DEFAULT now(),
+        CHECK (status IN ('draft', 'sent', 'confirmed', 'received')),
+        CHECK (status = 'received' OR warehouse_id IS NULL)
+    )
+    ",
+    "
+    CREATE TABLE purchase_order_lines (
+        purchase_order_line_id  SERIAL PRIMARY KEY,
+        purchase_order_id       INTEGER REFERENCES purchase_orders NOT NULL,
+        product_id              INTEGER REFERENCES products NOT NULL,
+        count                   INTEGER NOT NULL,
+        CHECK (count > 0),
+        UNIQUE (purchase_order_id, product_id)
+    )
+    ",
+    "
+    CREATE TABLE sales_orders (
+        sales_order_id      SERIAL PRIMARY KEY,
+        client_id           INTEGER REFERENCES clients NOT NULL,
+        client_address_id   INTEGER NOT NULL,
+        status              TEXT NOT NULL DEFAULT 'new',
+        created_at          TIMESTAMP NOT NULL DEFAULT now(),
+        CHECK (status IN ('new', 'confirmed', 'shipped', 'delivered', 'cancelled')),
+        FOREIGN KEY (client_address_id, client_id) REFERENCES client_addresses (client_address_id, client_id)
+    )
+    ",
+    "
+    CREATE TABLE sales_order_lines (
+        sales_order_line_id SERIAL PRIMARY KEY,
+        sales_order_id      INTEGER REFERENCES sales_orders NOT NULL,
+        product_id          INTEGER REFERENCES products NOT NULL,
+        count               INTEGER NOT NULL,
+        CHECK (count > 0),
+        UNIQUE (sales_order_id, product_id)
+    )
+    ",
+    "
+    CREATE TABLE sales_order_status_history (
+        sales_order_status_id   SERIAL PRIMARY KEY,
+        sales_order_id          INTEGER REFERENCES sales_orders NOT NULL,
+        status                  TEXT NOT NULL,
+        changed_at              TIMESTAMP NOT NULL DEFAULT clock_timestamp()
+    )
+    ",
+    "
+    CREATE TABLE shipments (
+        shipment_id     SERIAL PRIMARY KEY,
+        sales_order_id  INTEGER REFERENCES sales_orders NOT NULL,
+        warehouse_id    INTEGER REFERENCES warehouses NOT NULL,
+        shipped_at      TIMESTAMP NOT NULL DEFAULT now()
+    )
+    ",
+    "
+    CREATE TABLE product_lots (
+        product_lot_id      SERIAL PRIMARY KEY,
+        product_id          INTEGER REFERENCES products NOT NULL,
+        supplier_lot_number TEXT NOT NULL,
+        production_date     DATE NOT NULL,
+        expiry_date         DATE NOT NULL,
+        CHECK (expiry_date >= production_date),
+        UNIQUE (product_id, supplier_lot_number)
+    )
+    ",
+    "
+    CREATE TABLE lot_locations (
+        lot_location_id SERIAL PRIMARY KEY,
+        product_lot_id  INTEGER REFERENCES product_lots NOT NULL,
+        warehouse_id    INTEGER REFERENCES warehouses NOT NULL,
+        count           INTEGER NOT NULL,
+        CHECK (count >= 0),
+        UNIQUE (product_lot_id, warehouse_id)
+    )
+    ",
+    "
+    CREATE TABLE shipment_lines (
+        shipment_line_id    SERIAL PRIMARY KEY,
+        shipment_id         INTEGER REFERENCES shipments NOT NULL,
+        product_id          INTEGER REFERENCES products NOT NULL,
+        product_lot_id      INTEGER REFERENCES product_lots, -- NULLABLE, stock without a lot
+        count               INTEGER NOT NULL,
+        CHECK (count > 0)
+    )
+    ",
+    "
+    CREATE TABLE exchange_rates (
+        exchange_rate_id    SERIAL PRIMARY KEY,
+        currency_id         INTEGER REFERENCES currencies NOT NULL,
+        valid_from          DATE NOT NULL,
+        rate                NUMERIC(20, 10) NOT NULL, -- value of one unit in the reference currency
+        CHECK (rate > 0),
+        UNIQUE (currency_id, valid_from)
+    )
+    ",
+    "
+    CREATE TABLE supplier_prices (
+        supplier_price_id   SERIAL PRIMARY KEY,
+        supplier_id         INTEGER REFERENCES suppliers NOT NULL,
+        product_id          INTEGER REFERENCES products NOT NULL,
+        currency_id         INTEGER REFERENCES currencies NOT NULL,
+        price               NUMERIC(14, 2) NOT NULL,
+        valid_from          DATE NOT NULL,
+        valid_to            DATE, -- NULLABLE, open ended
+        CHECK (price >= 0),
+        CHECK (valid_to IS NULL OR valid_to > valid_from)
+    )
+    ",
+    "
+    CREATE TABLE client_prices (
+        client_price_id SERIAL PRIMARY KEY,
+        client_id       INTEGER REFERENCES clients NOT NULL,
+        product_id      INTEGER REFERENCES products NOT NULL,
+        currency_id     INTEGER REFERENCES currencies NOT NULL,
+        price           NUMERIC(14, 2) NOT NULL,
+        valid_from      DATE NOT NULL,
+        valid_to        DATE, -- NULLABLE, open ended
+        CHECK (price >= 0),
+        CHECK (valid_to IS NULL OR valid_to > valid_from)
+    )
+    ",
+    "
+    CREATE TABLE document_types (
+        document_type_id    SERIAL PRIMARY KEY,
+        name                TEXT UNIQUE NOT NULL
+    )
+    ",
+    "
+    CREATE TABLE required_documents (
+        required_document_id    SERIAL PRIMARY KEY,
+        country_id              INTEGER REFERENCES countries NOT NULL,
+        document_type_id        INTEGER REFERENCES document_types NOT NULL,
+        UNIQUE (country_id, document_type_id)
+    )
+    ",
+    "
+    CREATE TABLE documents (
+        document_id         SERIAL PRIMARY KEY,
+        document_type_id    INTEGER REFERENCES document_types NOT NULL,
+        supplier_id         INTEGER REFERENCES suppliers, -- NULLABLE
+        product_id          INTEGER REFERENCES products, -- NULLABLE
+        purchase_order_id   INTEGER REFERENCES purchase_orders, -- NULLABLE
+        number              TEXT NOT NULL,
+        valid_from          DATE NOT NULL,
+        valid_to            DATE, -- NULLABLE, open ended
+        file_path           TEXT, -- NULLABLE, relative to the documents directory
+        CHECK ((supplier_id IS NOT NULL) OR (product_id IS NOT NULL) OR (purchase_order_id IS NOT NULL)),
+        CHECK (valid_to IS NULL OR valid_to >= valid_from),
+        UNIQUE (document_type_id, number)
+    )
+    ",
+    "
+    CREATE TABLE allergens (
+        allergen_id     SERIAL PRIMARY KEY,
+        name            TEXT UNIQUE NOT NULL
+    )
+    ",
+    "
+    CREATE TABLE product_allergens (
+        product_allergen_id SERIAL PRIMARY KEY,
+        product_id          INTEGER REFERENCES products NOT NULL,
+        allergen_id         INTEGER REFERENCES allergens NOT NULL,
+        presence            TEXT NOT NULL,
+        CHECK (presence IN ('contains', 'may_contain')),
+        UNIQUE (product_id, allergen_id)
+    )
+    ",
+    "
+    CREATE TABLE dietary_flags (
+        dietary_flag_id SERIAL PRIMARY KEY,
+        name            TEXT UNIQUE NOT NULL
+    )
+    ",
+    "
+    CREATE TABLE product_dietary_flags (
+        product_dietary_flag_id SERIAL PRIMARY KEY,
+        product_id              INTEGER REFERENCES products NOT NULL,
+        dietary_flag_id         INTEGER REFERENCES dietary_flags NOT NULL,
+        UNIQUE (product_id, dietary_flag_id)
+    )
+    ",
+    "
+    CREATE TABLE product_nutrition (
+        product_id      INTEGER PRIMARY KEY REFERENCES products,
+        energy_kcal     NUMERIC(7, 2) NOT NULL, -- all values per 100g
+        fat             NUMERIC(6, 2) NOT NULL,
+        saturated_fat   NUMERIC(6, 2) NOT NULL,
+        carbohydrates   NUMERIC(6, 2) NOT NULL,
+        sugars          NUMERIC(6, 2) NOT NULL,
+        protein         NUMERIC(6, 2) NOT NULL,
+        salt            NUMERIC(6, 2) NOT NULL,
+        CHECK (energy_kcal >= 0 AND fat >= 0 AND carbohydrates >= 0 AND protein >= 0 AND salt >= 0),
+        CHECK (saturated_fat BETWEEN 0 AND fat),
+        CHECK (sugars BETWEEN 0 AND carbohydrates),
+        CHECK (fat + carbohydrates + protein + salt <= 100)
+    )
+    ",
+    "
+    CREATE TABLE product_ingredients (
+        product_ingredient_id   SERIAL PRIMARY KEY,
+        product_id              INTEGER REFERENCES products NOT NULL,
+        position                INTEGER NOT NULL, -- in descending order of weight
+        name                    TEXT NOT NULL,
+        CHECK (position > 0),
+        UNIQUE (product_id, position)
+    )
+    ",
+    "
+    CREATE VIEW current_exchange_rates AS
+    SELECT DISTINCT ON (currency_id) currency_id, rate
+    FROM exchange_rates
+    WHERE valid_from <= CURRENT_DATE
+    ORDER BY currency_id, valid_from DESC;
+
+    CREATE VIEW current_supplier_prices AS
+    SELECT DISTINCT ON (supplier_id, product_id) supplier_id, product_id, currency_id, price
+    FROM supplier_prices
+    WHERE valid_from <= CURRENT_DATE AND (valid_to IS NULL OR valid_to > CURRENT_DATE)
+    ORDER BY supplier_id, product_id, valid_from DESC;
+
+    CREATE VIEW current_client_prices AS
+    SELECT DISTINCT ON (client_id, product_id) client_id, product_id, currency_id, price
+    FROM client_prices
+    WHERE valid_from <= CURRENT_DATE AND (valid_to IS NULL OR valid_to > CURRENT_DATE)
+    ORDER BY client_id, product_id, valid_from DESC;
+    ",
+];
+
+// The search index is created after the archive and history tables and the policies
+const SEARCH_STATEMENTS: [&str; 2] = [
+    "
+    CREATE MATERIALIZED VIEW product_search AS
+    SELECT
+        products.product_id,
+        products.name,
+        products.category_id,
+        category_paths.path as category,
+        split_part(category_paths.path, ' / ', 1) as root_category,
+        COALESCE(string_agg(suppliers.name, ', ' ORDER BY suppliers.name), '') as suppliers,
+        array_remove(array_agg(DISTINCT countries.name), NULL) as countries,
+        setweight(to_tsvector('english', products.name), 'A')
+        || setweight(to_tsvector('english', category_paths.path), 'B')
+        || setweight(to_tsvector('english', COALESCE(string_agg(suppliers.name, ' '), '')), 'C') as document,
+        products.name || ' ' || category_paths.path || ' ' || COALESCE(string_agg(suppliers.name, ' '), '') as search_text
+    FROM products
+    INNER JOIN category_paths
+    ON products.category_id = category_paths.category_id
+    LEFT JOIN supplier_products
+    ON products.product_id = supplier_products.product_id
+    AND supplier_products.active
+    LEFT JOIN suppliers
+    ON supplier_products.supplier_id = suppliers.supplier_id
+    AND suppliers.deleted_at IS NULL
+    LEFT JOIN countries
+    ON suppliers.country_id = countries.country_id
+    WHERE products.deleted_at IS NULL
+    GROUP BY products.product_id, category_paths.path
+    WITH NO DATA;
+
+    CREATE UNIQUE INDEX product_search_product_index
+    ON product_search(product_id);
+
+    CREATE INDEX product_search_document_index
+    ON product_search USING GIN (document);
+
+    CREATE INDEX product_search_text_index
+    ON product_search USING GIN (search_text gin_trgm_ops);
+    ",
+    // only the owner of a materialized view may refresh it, the app does so
+    // through this function
+    "
+    CREATE OR REPLACE FUNCTION refresh_product_search() RETURNS VOID
+    LANGUAGE sql SECURITY DEFINER SET search_path FROM CURRENT AS $$
+        REFRESH MATERIALIZED VIEW product_search
+    $$;
+
+    REVOKE EXECUTE ON FUNCTION refresh_product_search() FROM PUBLIC;
+    GRANT EXECUTE ON FUNCTION refresh_product_search() TO dmadms_app;
+    ",
+];
+
+// Statements creating the roles, tables, views and functions, run in one
+// transaction
+pub fn create_statements() -> Vec<String> {
+    roles::setup_statements()
+        .into_iter()
+        .chain(TABLE_STATEMENTS.into_iter().map(str::to_owned))
+        .chain(validation::table_statements())
+        .chain(archive::table_statements())
+        .chain(history::table_statements())
+        .chain(tenancy::table_statements())
+        .chain(SEARCH_STATEMENTS.into_iter().map(str::to_owned))
+        .chain(roles::grant_statements())
+        .collect()
+}
+
+// Fingerprint of the statements creating the schema, it changes with them
+pub fn version() -> String {
+    let digest = Sha256::digest(create_statements().concat());
+
+    digest[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+pub fn create_tables(client: &mut Client) -> Result<(), postgres::Error> {
+    let mut transaction = client.transaction()?;
+
+    for statement in create_statements() {
+        transaction.batch_execute(&statement)?;
+    }
+
+    transaction.commit()
+}
+
+pub const INDEXES: [&str; 2] = [
+    "
+    CREATE INDEX user_index
+    ON users(supplier_id, client_id)
+    ",
+    "
+    CREATE INDEX user_role_index
+    ON user_roles(name)
+    ",
+];
+
+pub fn create_indexes(client: &mut Client) -> Result<(), postgres::Error> {
+    for index in INDEXES {
+        client.batch_execute(index)?;
+    }
+
+    Ok(())
+}
+
+// Statements filling the tables with the bundled data, shared with the
+// concurrent seeder
+pub(crate) const INSERT_COUNTRY: &str = "INSERT INTO countries (name) VALUES ($1)";
+
+pub(crate) const INSERT_SUPPLIER: &str = "
+    INSERT INTO suppliers (country_id, name, email)
+    VALUES ($1, $2, $3)
+    ";
+
+pub(crate) const INSERT_CLIENT: &str = "
+    INSERT INTO clients (name, email)
+    VALUES ($1, $2)
+    ";
+
+pub(crate) const INSERT_PRODUCT: &str = "
+    INSERT INTO products (category_id, name)
+    VALUES ($1, $2)
+    RETURNING product_id
+    ";
+
+pub(crate) const INSERT_OFFERING: &str = "
+    INSERT INTO supplier_products (supplier_id, product_id, sku, lead_time_days, min_order_quantity)
+    VALUES ($1, $2, $3, $4, $5)
+    ";
+
+pub(crate) const INSERT_WAREHOUSE: &str = "
+    INSERT INTO warehouses (address)
+    VALUES ($1)
+    ";
+
+pub(crate) const INSERT_CLIENT_ADDRESS: &str = "
+    INSERT INTO client_addresses (client_id, address)
+    VALUES ($1, $2)
+    ";
+
+pub(crate) const INSERT_REQUIREMENT: &str = "
+    INSERT INTO product_requirements (client_address_id, product_id, count)
+    VALUES ($1, $2, $3)
+    ";
+
+pub(crate) const INSERT_LOCATION: &str = "
+    INSERT INTO product_locations (warehouse_id, product_id, count)
+    VALUES ($1, $2, $3)
+    ";
+
+pub(crate) const INSERT_USER_ROLE: &str = "
+    INSERT INTO user_roles (name)
+    VALUES ($1)
+    ";
+
+pub(crate) const USER_ROLE_ID: &str = "
+    SELECT user_role_id
+    FROM user_roles
+    WHERE name = $1
+    ";
+
+pub(crate) const INSERT_USER: &str = "
+    INSERT INTO users (name, password, user_role_id)
+    VALUES ($1, crypt($2, gen_salt('md5')), $3)
+    ";
+
+pub(crate) const INSERT_SUPPLIER_USER: &str = "
+    INSERT INTO users (name, password, user_role_id, supplier_id)
+    VALUES (
+        (
+            SELECT name
+            FROM suppliers
+            WHERE supplier_id = $1
+        ),
+        crypt('password', gen_salt('md5')),
+        $2,
+        $1
+    )
+    ";
+
+pub(crate) const INSERT_CLIENT_USER: &str = "
+    INSERT INTO users (name, password, user_role_id, client_id)
+    VALUES (
+        (
+            SELECT name
+            FROM clients
+            WHERE client_id = $1
+        ),
+        crypt('password', gen_salt('md5')),
+        $2,
+        $1
+    )
+    ";
+
+pub(crate) const USER_ROLES: [&str; 4] = ["admin", "manager", "client", "supplier"];
+
+pub(crate) const ADMIN: (&str, &str) = ("Gigachad", "adminadmin");
+
+pub(crate) const MANAGERS: [(&str, &str); 8] = [
+    ("Helmer", "array"),
+    ("Macey", "capacitor"),
+    ("Melvina", "interface"),
+    ("Priscilla", "driver"),
+    ("Mollie", "capacitor"),
+    ("Jaren", "driver"),
+    ("Addison", "port"),
+    ("Jerrold", "firewall"),
+];
+
+// SKU, lead time and minimum order quantity of the first offering of a product
+pub(crate) fn offering_terms(supplier_id: i32, product_id: i32) -> (String, i32, i32) {
+    (
+        format!("S{supplier_id}-{product_id:05}"),
+        2 + product_id % 12,
+        [1, 6, 12][product_id as usize % 3],
+    )
+}
+
+// Required and stocked counts of the first products, zero counts aren't stored
+pub(crate) fn stock_count(id: i32, product_id: i32) -> i32 {
+    id.wrapping_mul(73)
+        .wrapping_add(product_id.wrapping_add(42))
+        % 300
+}
+
+pub(crate) const SUBCATEGORY_IDS: &str = "
+    SELECT category_id
+    FROM categories
+    WHERE parent_id IS NOT NULL
+    ORDER BY category_id
+    ";
+
+// Only the first products are required and stocked
+pub(crate) const FIRST_PRODUCT_IDS: &str = "SELECT product_id FROM products LIMIT 10";
+
+pub(crate) fn ids(client: &mut Client, query: &str) -> Result<Vec<i32>, postgres::Error> {
+    Ok(client
+        .query(query, &[])?
+        .iter()
+        .map(|row| row.get(0))
+        .collect())
+}
+
+pub fn init_tables(client: &mut Client) -> Result<(), postgres::Error> {
+    /* TODO LIST:
+     * client_addresses
+     * clients
+     * countries
+     * categories
+     * product_locations
+     * product_requirements
+     * products
+     * suppliers
+     * user
+     * user_roles
+     * warehouses
+     */
+
+    client.batch_execute("BEGIN TRANSACTION")?;
+
+    let countries = include!("init_data/countries");
+
+    for country in countries {
+        client.execute(INSERT_COUNTRY, &[&country])?;
+    }
+
+    let supplier_names = include!("init_data/suppliers").iter();
+    let emails = include!("init_data/emails").iter();
+
+    let mut suppliers = supplier_names.zip(emails);
+
+    let country_ids = ids(client, "SELECT country_id FROM countries")?;
+
+    for country_id in country_ids.iter().cycle() {
+        if let Some(supplier) = suppliers.next() {
+            client.execute(
+                INSERT_SUPPLIER,
+                &[
+                    &country_id,
+                    supplier.0,
+                    &validation::normalize_email(supplier.1),
+                ],
+            )?;
+        } else {
+            break;
+        }
+    }
+
+    let client_names = include!("init_data/clients").iter();
+    let emails = include!("init_data/emails").iter();
+    let clients = client_names.zip(emails);
+
+    for my_client in clients {
+        client.execute(
+            INSERT_CLIENT,
+            &[my_client.0, &validation::normalize_email(my_client.1)],
+        )?;
+    }
+
+    let product_categories = include!("init_data/product_categories");
+
+    for (category, subcategories) in product_categories {
+        let category_id = categories::insert(client, None, category)?;
+
+        for subcategory in subcategories {
+            categories::insert(client, Some(category_id), subcategory)?;
+        }
+    }
+
+    let subcategory_ids = ids(client, SUBCATEGORY_IDS)?;
+    let supplier_ids = ids(client, "SELECT supplier_id FROM suppliers")?;
+
+    let product_names = include!("init_data/products");
+
+    let products = product_names.iter().zip(
+        subcategory_ids
+            .iter()
+            .cycle()
+            .zip(supplier_ids.iter().cycle()),
+    );
+
+    for (product_name, (subcategory_id, supplier_id)) in products {
+        let product_id: i32 = client
+            .query_one(INSERT_PRODUCT, &[&subcategory_id, &product_name])?
+            .get("product_id");
+
+        let (sku, lead_time_days, min_order_quantity) = offering_terms(*supplier_id, product_id);
+
+        client.execute(
+            INSERT_OFFERING,
+            &[
+                &supplier_id,
+                &product_id,
+                &sku,
+                &lead_time_days,
+                &min_order_quantity,
+            ],
+        )?;
+    }
+
+    let addresses = include!("init_data/addresses");
+
+    for address in &addresses {
+        client.execute(INSERT_WAREHOUSE, &[address])?;
+    }
+
+    let client_ids = ids(client, "SELECT client_id FROM clients")?;
+
+    let client_addresses = addresses.iter().zip(client_ids.iter().cycle());
+
+    for (address, client_id) in client_addresses {
+        client.execute(INSERT_CLIENT_ADDRESS, &[&client_id, address])?;
+    }
+
+    let client_address_ids = ids(client, "SELECT client_address_id from client_addresses")?;
+    let product_ids = ids(client, FIRST_PRODUCT_IDS)?;
+
+    for client_address_id in client_address_ids {
+        for product_id in &product_ids {
+            let count = stock_count(client_address_id, *product_id);
+
+            if count != 0 {
+                client.execute(
+                    INSERT_REQUIREMENT,
+                    &[&client_address_id, &product_id, &count],
+                )?;
+            }
+        }
+    }
+
+    let warehouse_ids = ids(client, "SELECT warehouse_id from warehouses")?;
+
+    for warehouse_id in warehouse_ids {
+        for product_id in &product_ids {
+            let count = stock_count(warehouse_id, *product_id);
+
+            if count != 0 {
+                client.execute(INSERT_LOCATION, &[&warehouse_id, &product_id, &count])?;
+            }
+        }
+    }
+
+    for user_role in USER_ROLES {
+        client.execute(INSERT_USER_ROLE, &[&user_role])?;
+    }
+
+    let admin_role_id: i32 = client.query_one(USER_ROLE_ID, &[&"admin"])?.get(0);
+
+    client.execute(INSERT_USER, &[&ADMIN.0, &ADMIN.1, &admin_role_id])?;
+
+    let manager_role_id: i32 = client.query_one(USER_ROLE_ID, &[&"manager"])?.get(0);
+
+    for manager in MANAGERS {
+        client.execute(INSERT_USER, &[&manager.0, &manager.1, &manager_role_id])?;
+    }
+
+    let supplier_role_id: i32 = client.query_one(USER_ROLE_ID, &[&"supplier"])?.get(0);
+
+    for supplier_id in ids(client, "SELECT supplier_id FROM suppliers")? {
+        client.execute(INSERT_SUPPLIER_USER, &[&supplier_id, &supplier_role_id])?;
+    }
+
+    let client_role_id: i32 = client.query_one(USER_ROLE_ID, &[&"client"])?.get(0);
+
+    for client_id in client_ids {
+        client.execute(INSERT_CLIENT_USER, &[&client_id, &client_role_id])?;
+    }
+
+    client.batch_execute("COMMIT TRANSACTION")?;
+
+    Ok(())
+}
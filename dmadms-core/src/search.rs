@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+
+use postgres::{Client, Row};
+
+use crate::categories;
+use crate::error::Error;
+
+pub const DEFAULT_LIMIT: usize = 20;
+
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub text: String,
+    // id or path of a category, products anywhere below it match
+    pub category: Option<String>,
+    // country of an active supplier
+    pub country: Option<String>,
+    pub in_stock: bool,
+    // stock is looked for in this warehouse only
+    pub warehouse_id: Option<i32>,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub product_id: i32,
+    pub name: String,
+    pub category: String,
+    pub suppliers: String,
+    pub in_stock: bool,
+    pub rank: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResults {
+    pub total: usize,
+    pub hits: Vec<Hit>,
+    // counts of all matching products per root category, supplier country and availability
+    pub categories: BTreeMap<String, usize>,
+    pub countries: BTreeMap<String, usize>,
+    pub in_stock: usize,
+}
+
+// The search index is a materialized view, it has to be refreshed after
+// products, categories or suppliers change
+pub fn refresh(client: &mut Client) -> Result<(), Error> {
+    client.batch_execute("SELECT refresh_product_search()")?;
+
+    Ok(())
+}
+
+pub(crate) const SEARCH: &str = "
+    SELECT
+        product_search.product_id,
+        product_search.name,
+        product_search.category,
+        product_search.root_category,
+        product_search.suppliers,
+        product_search.countries,
+        EXISTS (
+            SELECT 1
+            FROM product_locations
+            WHERE product_locations.product_id = product_search.product_id
+            AND product_locations.count > 0
+            AND ($4::INTEGER IS NULL OR product_locations.warehouse_id = $4)
+        ) as in_stock,
+        (
+            ts_rank(product_search.document, websearch_to_tsquery('english', $1))
+            + word_similarity($1, product_search.search_text)
+        )::REAL as rank
+    FROM product_search
+    WHERE (
+        product_search.document @@ websearch_to_tsquery('english', $1)
+        OR $1 <% product_search.search_text
+    )
+    AND (
+        $2::INTEGER IS NULL
+        OR product_search.category_id IN (
+            SELECT descendant_id FROM category_closure WHERE ancestor_id = $2
+        )
+    )
+    AND ($3::TEXT IS NULL OR $3 = ANY(product_search.countries))
+    ORDER BY rank DESC, product_search.name
+    ";
+
+// Counts all matching rows and keeps the first `limit` of them as hits
+pub(crate) fn collect(rows: Vec<Row>, query: &Query) -> SearchResults {
+    let mut results = SearchResults::default();
+
+    for row in rows {
+        let in_stock: bool = row.get("in_stock");
+
+        if query.in_stock && !in_stock {
+            continue;
+        }
+
+        results.total += 1;
+        *results
+            .categories
+            .entry(row.get("root_category"))
+            .or_default() += 1;
+        for country in row.get::<_, Vec<String>>("countries") {
+            *results.countries.entry(country).or_default() += 1;
+        }
+        if in_stock {
+            results.in_stock += 1;
+        }
+
+        if results.hits.len() < query.limit {
+            results.hits.push(Hit {
+                product_id: row.get("product_id"),
+                name: row.get("name"),
+                category: row.get("category"),
+                suppliers: row.get("suppliers"),
+                in_stock,
+                rank: row.get("rank"),
+            });
+        }
+    }
+
+    results
+}
+
+// Ranks products matching all words of the query, or resembling it when words
+// are misspelt, by full text rank plus trigram similarity
+pub fn search(client: &mut Client, query: &Query) -> Result<SearchResults, Error> {
+    let category_id = query
+        .category
+        .as_deref()
+        .map(|category| categories::resolve(client, category))
+        .transpose()?;
+
+    let rows = client.query(
+        SEARCH,
+        &[
+            &query.text,
+            &category_id,
+            &query.country,
+            &query.warehouse_id,
+        ],
+    )?;
+
+    Ok(collect(rows, query))
+}
+
+pub fn print_results(results: &SearchResults) {
+    println!(
+        "
+{:8} {:6} {:35} {:45} {:35} Stock
+",
+        "Id", "Rank", "Product", "Category", "Suppliers"
+    );
+
+    for hit in &results.hits {
+        println!(
+            "{:<8} {:<6.3} {:35} {:45} {:35} {}",
+            hit.product_id,
+            hit.rank,
+            hit.name,
+            hit.category,
+            hit.suppliers,
+            if hit.in_stock { "yes" } else { "no" }
+        );
+    }
+
+    println!(
+        "\nshowing {} of {} products, {} in stock",
+        results.hits.len(),
+        results.total,
+        results.in_stock
+    );
+
+    println!("\nCategories");
+    for (category, count) in &results.categories {
+        println!("    {category:35} {count}");
+    }
+
+    println!("\nSupplier countries");
+    for (country, count) in &results.countries {
+        println!("    {country:35} {count}");
+    }
+}
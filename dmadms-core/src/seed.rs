@@ -0,0 +1,58 @@
+use postgres::Client;
+
+use crate::error::Error;
+use crate::geocoding::{self, GeocodeSummary};
+use crate::{attributes, barcodes, documents, lots, offerings, pricing, schema, search, units};
+
+// Bundled data the tables are filled with
+pub const GAZETTEER: &str = include_str!("init_data/gazetteer.csv");
+pub const EXCHANGE_RATES: &str = include_str!("init_data/exchange_rates.csv");
+pub const BARCODES: &str = include_str!("init_data/gtins.csv");
+
+#[derive(Debug, Clone)]
+pub struct SeedSummary {
+    pub geocoded: GeocodeSummary,
+    pub gazetteer_places: usize,
+    pub exchange_rates: usize,
+    pub barcodes: usize,
+}
+
+// Drops every table and creates them again, empty
+pub fn reset(client: &mut Client) -> Result<(), Error> {
+    schema::drop_tables(client);
+    schema::create_tables(client)?;
+    schema::create_indexes(client)?;
+
+    Ok(())
+}
+
+// Fills freshly created tables with the bundled data and builds the search index
+pub fn seed(client: &mut Client) -> Result<SeedSummary, Error> {
+    schema::init_tables(client)?;
+
+    let places = geocoding::read_gazetteer(GAZETTEER.as_bytes())?;
+    let geocoded = geocoding::import_gazetteer(client, &places)?;
+
+    let rates = pricing::read_exchange_rates(EXCHANGE_RATES.as_bytes())?;
+    pricing::import_exchange_rates(client, &rates)?;
+
+    offerings::seed(client)?;
+    pricing::seed_prices(client)?;
+    units::seed(client)?;
+
+    let gtins = barcodes::read_barcodes(BARCODES.as_bytes())?;
+    barcodes::import(client, &gtins)?;
+
+    lots::seed(client)?;
+    documents::seed(client)?;
+    attributes::seed(client)?;
+
+    search::refresh(client)?;
+
+    Ok(SeedSummary {
+        geocoded,
+        gazetteer_places: places.len(),
+        exchange_rates: rates.len(),
+        barcodes: gtins.len(),
+    })
+}
@@ -0,0 +1,271 @@
+use postgres::error::SqlState;
+use postgres::{Client, Transaction};
+
+use crate::error::Error;
+use crate::roles::Role;
+
+// Database roles of client and supplier users. Row level security lets them see
+// only the rows of their client or supplier, even for SQL run directly. Every
+// tenant has a role of its own, a member of one of these, and `tenant_roles`
+// maps it to the tenant. Policies are keyed on the role of the session, which
+// it can switch only to roles it is a member of. Admins and managers take a
+// staff role of `roles`. The roles are created with the staff ones by
+// `roles::setup_statements`.
+pub const CLIENT_ROLE: &str = "dmadms_client";
+pub const SUPPLIER_ROLE: &str = "dmadms_supplier";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tenant {
+    Client(i32),
+    Supplier(i32),
+}
+
+impl Tenant {
+    // The role of the tenant, shared by every database of the server like the
+    // other roles
+    pub fn role(self) -> String {
+        match self {
+            Tenant::Client(client_id) => format!("{CLIENT_ROLE}_{client_id}"),
+            Tenant::Supplier(supplier_id) => format!("{SUPPLIER_ROLE}_{supplier_id}"),
+        }
+    }
+
+    fn group(self) -> &'static str {
+        match self {
+            Tenant::Client(_) => CLIENT_ROLE,
+            Tenant::Supplier(_) => SUPPLIER_ROLE,
+        }
+    }
+}
+
+const STATEMENTS: [&str; 7] = [
+    // only the owner maps roles, tenants read their own mapping
+    "
+    CREATE TABLE tenant_roles (
+        role_name   TEXT PRIMARY KEY,
+        client_id   INTEGER REFERENCES clients ON DELETE CASCADE, -- NULLABLE
+        supplier_id INTEGER REFERENCES suppliers ON DELETE CASCADE, -- NULLABLE
+        CHECK ((client_id IS NULL) <> (supplier_id IS NULL))
+    );
+
+    REVOKE INSERT, UPDATE, DELETE ON tenant_roles FROM dmadms_app;
+
+    ALTER TABLE tenant_roles ENABLE ROW LEVEL SECURITY;
+
+    CREATE POLICY own_role ON tenant_roles TO dmadms_client, dmadms_supplier
+    USING (role_name = current_user);
+    ",
+    "
+    CREATE OR REPLACE FUNCTION current_client_id() RETURNS INTEGER
+    LANGUAGE sql STABLE AS $$
+        SELECT client_id FROM tenant_roles WHERE role_name = current_user
+    $$;
+
+    CREATE OR REPLACE FUNCTION current_supplier_id() RETURNS INTEGER
+    LANGUAGE sql STABLE AS $$
+        SELECT supplier_id FROM tenant_roles WHERE role_name = current_user
+    $$;
+    ",
+    // clients see their own addresses and requirements and the whole catalog
+    "
+    ALTER TABLE clients ENABLE ROW LEVEL SECURITY;
+    ALTER TABLE client_addresses ENABLE ROW LEVEL SECURITY;
+    ALTER TABLE product_requirements ENABLE ROW LEVEL SECURITY;
+    ALTER TABLE products ENABLE ROW LEVEL SECURITY;
+
+    CREATE POLICY own_client ON clients TO dmadms_client
+    USING (client_id = current_client_id());
+
+    CREATE POLICY own_client ON client_addresses TO dmadms_client
+    USING (client_id = current_client_id());
+
+    CREATE POLICY own_client ON product_requirements TO dmadms_client
+    USING (
+        client_address_id IN (
+            SELECT client_address_id
+            FROM client_addresses
+            WHERE client_id = current_client_id()
+        )
+    );
+
+    CREATE POLICY catalog ON products FOR SELECT TO dmadms_client
+    USING (deleted_at IS NULL);
+    ",
+    // suppliers see themselves, their offerings and the products they offer
+    "
+    ALTER TABLE suppliers ENABLE ROW LEVEL SECURITY;
+    ALTER TABLE supplier_products ENABLE ROW LEVEL SECURITY;
+
+    CREATE POLICY own_supplier ON suppliers TO dmadms_supplier
+    USING (supplier_id = current_supplier_id());
+
+    CREATE POLICY own_supplier ON supplier_products TO dmadms_supplier
+    USING (supplier_id = current_supplier_id());
+
+    CREATE POLICY offered ON products TO dmadms_supplier
+    USING (
+        product_id IN (
+            SELECT product_id
+            FROM supplier_products
+            WHERE supplier_id = current_supplier_id()
+        )
+    );
+    ",
+    // staff roles see every row
+    "
+    CREATE POLICY staff ON clients TO dmadms_app, dmadms_reporting, dmadms_auditor USING (true);
+    CREATE POLICY staff ON client_addresses TO dmadms_app, dmadms_reporting, dmadms_auditor USING (true);
+    CREATE POLICY staff ON product_requirements TO dmadms_app, dmadms_reporting, dmadms_auditor USING (true);
+    CREATE POLICY staff ON products TO dmadms_app, dmadms_reporting, dmadms_auditor USING (true);
+    CREATE POLICY staff ON suppliers TO dmadms_app, dmadms_reporting, dmadms_auditor USING (true);
+    CREATE POLICY staff ON supplier_products TO dmadms_app, dmadms_reporting, dmadms_auditor USING (true);
+    CREATE POLICY staff ON tenant_roles TO dmadms_app, dmadms_reporting, dmadms_auditor USING (true);
+    ",
+    "
+    GRANT SELECT
+    ON countries, units, product_units, products, suppliers, supplier_products, clients,
+        client_addresses, product_requirements, tenant_roles
+    TO dmadms_client, dmadms_supplier;
+
+    GRANT INSERT, UPDATE, DELETE ON product_requirements TO dmadms_client;
+    GRANT USAGE ON SEQUENCE product_requirements_product_requirement_id_seq TO dmadms_client;
+    ",
+    CATEGORY_GRANT,
+];
+
+// Tenants read the whole category tree, also granted by `categories::migrate`
+pub(crate) const CATEGORY_GRANT: &str = "
+    GRANT SELECT ON categories, category_closure, category_paths
+    TO dmadms_client, dmadms_supplier
+    ";
+
+pub fn table_statements() -> Vec<String> {
+    STATEMENTS.into_iter().map(str::to_owned).collect()
+}
+
+// Tenant of a user, `None` for admins and managers
+pub fn tenant(client: &mut Client, user_name: &str) -> Result<Option<Tenant>, Error> {
+    let row = client
+        .query_opt(
+            "
+            SELECT client_id, supplier_id
+            FROM users
+            WHERE name = $1 AND deleted_at IS NULL
+            ",
+            &[&user_name],
+        )?
+        .ok_or_else(|| Error::NotFound(format!("user '{user_name}'")))?;
+
+    Ok(match (row.get("client_id"), row.get("supplier_id")) {
+        (Some(client_id), _) => Some(Tenant::Client(client_id)),
+        (None, Some(supplier_id)) => Some(Tenant::Supplier(supplier_id)),
+        (None, None) => None,
+    })
+}
+
+// Roles are shared by the databases of the server, another session may create
+// or grant the same one meanwhile
+fn once(transaction: &mut Transaction, statement: &str) -> Result<(), Error> {
+    let mut savepoint = transaction.transaction()?;
+
+    match savepoint.batch_execute(statement) {
+        Ok(()) => Ok(savepoint.commit()?),
+        Err(error)
+            if error.code() == Some(&SqlState::DUPLICATE_OBJECT)
+                || error.code() == Some(&SqlState::UNIQUE_VIOLATION) =>
+        {
+            Ok(())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+// Lets a role, a login of its own for direct SQL access for example, act as
+// the tenant. It becomes a member of the client or supplier role and is mapped
+// to the tenant; the session needs to be allowed to create roles.
+pub fn grant(client: &mut Client, role: &str, tenant: Tenant) -> Result<(), Error> {
+    let (client_id, supplier_id) = match tenant {
+        Tenant::Client(client_id) => (Some(client_id), None),
+        Tenant::Supplier(supplier_id) => (None, Some(supplier_id)),
+    };
+
+    let mut transaction = client.transaction()?;
+
+    let mapped: Option<(Option<i32>, Option<i32>)> = transaction
+        .query_opt(
+            "SELECT client_id, supplier_id FROM tenant_roles WHERE role_name = $1",
+            &[&role],
+        )?
+        .map(|row| (row.get("client_id"), row.get("supplier_id")));
+
+    if mapped.is_some_and(|ids| ids != (client_id, supplier_id)) {
+        return Err(Error::Conflict(format!(
+            "role {role} already acts as another tenant"
+        )));
+    }
+
+    let row = transaction.query_one(
+        "
+        SELECT quote_ident($1) as role, EXISTS (SELECT FROM pg_roles WHERE rolname = $1) as exists
+        ",
+        &[&role],
+    )?;
+    let quoted: String = row.get("role");
+
+    // only what is missing is created and granted, so that entering an
+    // existing role takes no privileges
+    if !row.get::<_, bool>("exists") {
+        once(&mut transaction, &format!("CREATE ROLE {quoted} NOLOGIN"))?;
+    }
+
+    let row = transaction.query_one(
+        "
+        SELECT
+            pg_has_role($1, $2, 'MEMBER') as tenant,
+            pg_has_role(current_user, $1, 'MEMBER') as session
+        ",
+        &[&role, &tenant.group()],
+    )?;
+
+    if !row.get::<_, bool>("tenant") {
+        once(
+            &mut transaction,
+            &format!("GRANT {} TO {quoted}", tenant.group()),
+        )?;
+    }
+    // the session can switch to the role, as it does to the staff ones
+    if !row.get::<_, bool>("session") {
+        once(&mut transaction, &format!("GRANT {quoted} TO CURRENT_USER"))?;
+    }
+
+    transaction.batch_execute(&format!("SET LOCAL ROLE {}", Role::Owner.name()))?;
+    transaction.execute(
+        "
+        INSERT INTO tenant_roles (role_name, client_id, supplier_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (role_name) DO NOTHING
+        ",
+        &[&role, &client_id, &supplier_id],
+    )?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+// Switches the session to the role of the tenant until `leave`, the role is
+// created on the first switch
+pub fn enter(client: &mut Client, tenant: Tenant) -> Result<(), Error> {
+    let role = tenant.role();
+
+    grant(client, &role, tenant)?;
+    client.batch_execute(&format!("SET ROLE {role}"))?;
+
+    Ok(())
+}
+
+pub fn leave(client: &mut Client) -> Result<(), Error> {
+    client.batch_execute("RESET ROLE")?;
+
+    Ok(())
+}
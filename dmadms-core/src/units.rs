@@ -0,0 +1,252 @@
+use postgres::{Client, GenericClient};
+
+use crate::error::Error;
+
+pub const UNITS: [(&str, &str); 4] = [
+    ("each", "single item"),
+    ("case", "case of items"),
+    ("pallet", "pallet of cases"),
+    ("kg", "kilogram"),
+];
+
+#[derive(Debug, Clone)]
+pub struct Packaging {
+    pub unit: String,
+    pub factor: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct StockLine {
+    pub product_id: i32,
+    pub product: String,
+    pub count: i32,
+    pub unit: Option<String>,
+    pub factor: Option<i32>,
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Exact count of base units in a unit of `factor` base units: a whole number,
+// a terminating decimal or a fraction otherwise, also when the decimal has too
+// many places to compute
+pub fn render(count: i64, factor: i32) -> String {
+    let (count, factor) = (count as i128, factor as i128);
+    let divisor = gcd(count, factor).max(1);
+    let (numerator, denominator) = (count / divisor, factor / divisor);
+
+    if denominator == 1 {
+        return numerator.to_string();
+    }
+
+    let fraction = || {
+        let whole = numerator / denominator;
+        let remainder = numerator % denominator;
+        match whole {
+            0 => format!("{numerator}/{denominator}"),
+            _ => format!("{whole} {}/{denominator}", remainder.abs()),
+        }
+    };
+
+    let mut rest = denominator;
+    let (mut twos, mut fives) = (0, 0);
+    while rest % 2 == 0 {
+        rest /= 2;
+        twos += 1;
+    }
+    while rest % 5 == 0 {
+        rest /= 5;
+        fives += 1;
+    }
+
+    if rest != 1 {
+        return fraction();
+    }
+
+    let places: u32 = twos.max(fives);
+    let Some((scale, scaled)) = 10_i128
+        .checked_pow(places)
+        .and_then(|scale| Some((scale, numerator.checked_mul(scale)? / denominator)))
+    else {
+        return fraction();
+    };
+
+    let sign = if scaled < 0 { "-" } else { "" };
+    format!(
+        "{sign}{}.{:0width$}",
+        scaled.abs() / scale,
+        scaled.abs() % scale,
+        width = places as usize
+    )
+}
+
+// Count of base units a unit of the product holds
+pub fn factor<C: GenericClient>(client: &mut C, product_id: i32, unit: &str) -> Result<i32, Error> {
+    Ok(client
+        .query_opt(
+            "
+            SELECT product_units.factor
+            FROM product_units
+            INNER JOIN units
+            ON product_units.unit_id = units.unit_id
+            WHERE product_units.product_id = $1 AND units.code = $2
+            ",
+            &[&product_id, &unit],
+        )?
+        .ok_or_else(|| Error::NotFound(format!("unit '{unit}' of product {product_id}")))?
+        .get("factor"))
+}
+
+pub fn to_base<C: GenericClient>(
+    client: &mut C,
+    product_id: i32,
+    count: i32,
+    unit: &str,
+) -> Result<i32, Error> {
+    let factor = factor(client, product_id, unit)?;
+
+    count
+        .checked_mul(factor)
+        .ok_or_else(|| Error::Invalid(format!("{count} {unit} is too much")))
+}
+
+pub fn convert(
+    client: &mut Client,
+    product_id: i32,
+    count: i32,
+    from: &str,
+    to: &str,
+) -> Result<String, Error> {
+    let base = to_base(client, product_id, count, from)?;
+    let factor = factor(client, product_id, to)?;
+
+    Ok(render(base as i64, factor))
+}
+
+pub(crate) const INSERT_UNIT: &str = "INSERT INTO units (code, name) VALUES ($1, $2)";
+
+pub(crate) const SEED: &str = "
+    INSERT INTO product_units (product_id, unit_id, factor)
+    SELECT products.product_id, units.unit_id,
+        CASE units.code
+            WHEN 'each' THEN 1
+            WHEN 'case' THEN 6 + products.product_id % 3 * 6
+            WHEN 'pallet' THEN (6 + products.product_id % 3 * 6) * 40
+            ELSE 4
+        END
+    FROM products
+    CROSS JOIN units
+    WHERE units.code <> 'kg' OR products.product_id <= 10;
+
+    UPDATE product_locations
+    SET unit_id = (SELECT unit_id FROM units WHERE code = 'case');
+    ";
+
+// Every product is counted in items, packed in cases and pallets of 40 cases,
+// the first products are also sold by kilogram of 4 items
+pub fn seed(client: &mut Client) -> Result<(), Error> {
+    let mut transaction = client.transaction()?;
+
+    for (code, name) in UNITS {
+        transaction.execute(INSERT_UNIT, &[&code, &name])?;
+    }
+
+    transaction.batch_execute(SEED)?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+pub fn packaging(client: &mut Client, product_id: i32) -> Result<Vec<Packaging>, Error> {
+    Ok(client
+        .query(
+            "
+            SELECT units.code as unit, product_units.factor
+            FROM product_units
+            INNER JOIN units
+            ON product_units.unit_id = units.unit_id
+            WHERE product_units.product_id = $1
+            ORDER BY product_units.factor, units.code
+            ",
+            &[&product_id],
+        )?
+        .iter()
+        .map(|row| Packaging {
+            unit: row.get("unit"),
+            factor: row.get("factor"),
+        })
+        .collect())
+}
+
+// Adds to what the address requires, stated in a unit of the product
+pub fn require(
+    client: &mut Client,
+    client_address_id: i32,
+    product_id: i32,
+    count: i32,
+    unit: &str,
+) -> Result<i32, Error> {
+    let mut transaction = client.transaction()?;
+
+    let base = to_base(&mut transaction, product_id, count, unit)?;
+
+    let product_requirement_id: i32 = transaction
+        .query_one(
+            "
+            INSERT INTO product_requirements (client_address_id, product_id, count, unit_id)
+            VALUES ($1, $2, $3, (SELECT unit_id FROM units WHERE code = $4))
+            RETURNING product_requirement_id
+            ",
+            &[&client_address_id, &product_id, &base, &unit],
+        )?
+        .get("product_requirement_id");
+
+    transaction.commit()?;
+
+    Ok(product_requirement_id)
+}
+
+// Stock of a warehouse in `unit`, or in the unit each product is stored in
+pub fn stock(
+    client: &mut Client,
+    warehouse_id: i32,
+    unit: Option<&str>,
+) -> Result<Vec<StockLine>, Error> {
+    Ok(client
+        .query(
+            "
+            SELECT
+                products.product_id,
+                products.name as product,
+                product_locations.count,
+                units.code as unit,
+                product_units.factor
+            FROM product_locations
+            INNER JOIN products
+            ON product_locations.product_id = products.product_id
+            LEFT JOIN units
+            ON units.code = COALESCE($2, (SELECT code FROM units WHERE unit_id = product_locations.unit_id))
+            LEFT JOIN product_units
+            ON product_units.product_id = products.product_id
+            AND product_units.unit_id = units.unit_id
+            WHERE product_locations.warehouse_id = $1
+            ORDER BY products.name
+            ",
+            &[&warehouse_id, &unit],
+        )?
+        .iter()
+        .map(|row| StockLine {
+            product_id: row.get("product_id"),
+            product: row.get("product"),
+            count: row.get("count"),
+            unit: row.get("unit"),
+            factor: row.get("factor"),
+        })
+        .collect())
+}
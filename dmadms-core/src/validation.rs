@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use postgres::{Client, GenericClient};
+
+use crate::error::Error;
+use crate::roles;
+
+// Limits of the data model, `varchar(320)` for names and emails
+pub const NAME_MAX_LENGTH: usize = 320;
+pub const EMAIL_MAX_LENGTH: usize = 320;
+pub const EMAIL_LOCAL_MAX_LENGTH: usize = 64;
+const DOMAIN_LABEL_MAX_LENGTH: usize = 63;
+
+// Characters of an RFC 5322 atom besides letters and digits
+const ATOM_SPECIALS: &str = "!#$%&'*+/=?^_`{|}~-";
+
+// Tables with names and emails checked, the names of their rows and id columns
+const TABLES: [(&str, &str, &str); 2] = [
+    ("suppliers", "supplier", "supplier_id"),
+    ("clients", "client", "client_id"),
+];
+
+// The checks of `name` and `email` as constraints, and unique normalized emails
+// among rows not deleted. `migrate` adds them to existing databases. A blank
+// name has only the characters `char::is_whitespace` accepts, Unicode's
+// White_Space, whatever the locale of the database considers spaces.
+const CONSTRAINTS: &str = r#"
+    ALTER TABLE {table} ADD CONSTRAINT {entity}_name_length
+    CHECK (
+        name ~ '[^\u0009-\u000d\u0020\u0085\u00a0\u1680\u2000-\u200a\u2028\u2029\u202f\u205f\u3000]'
+        AND char_length(name) <= 320
+    );
+
+    ALTER TABLE {table} ADD CONSTRAINT {entity}_email_valid
+    CHECK (
+        char_length(email) <= 320
+        AND char_length(split_part(email, '@', 1)) <= 64
+        AND email ~ '^[a-z0-9!#$%&''*+/=?^_`{|}~-]+(\.[a-z0-9!#$%&''*+/=?^_`{|}~-]+)*@[a-z0-9]([a-z0-9-]{0,61}[a-z0-9])?(\.[a-z0-9]([a-z0-9-]{0,61}[a-z0-9])?)+$'
+    );
+
+    CREATE UNIQUE INDEX {entity}_email_index
+    ON {table}(email)
+    WHERE deleted_at IS NULL;
+    "#;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub table: &'static str,
+    pub id: i32,
+    pub column: &'static str,
+    pub value: String,
+    pub problem: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} '{}': {}",
+            self.table, self.id, self.column, self.value, self.problem
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Migration {
+    // emails lowercased and trimmed
+    pub normalized: usize,
+    // rows the constraints can't be added with, nothing is changed then
+    pub violations: Vec<Violation>,
+    // false when the constraints were there already or rows violate them
+    pub migrated: bool,
+}
+
+pub fn table_statements() -> Vec<String> {
+    TABLES
+        .iter()
+        .map(|(table, entity, _)| {
+            CONSTRAINTS
+                .replace("{table}", table)
+                .replace("{entity}", entity)
+        })
+        .collect()
+}
+
+// Emails are compared and stored lowercase, without surrounding spaces
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn atom(part: &str) -> bool {
+    !part.is_empty()
+        && part
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || ATOM_SPECIALS.contains(c))
+}
+
+fn label(part: &str) -> bool {
+    (1..=DOMAIN_LABEL_MAX_LENGTH).contains(&part.len())
+        && !part.starts_with('-')
+        && !part.ends_with('-')
+        && part
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+// What is wrong with a normalized email, in the RFC 5322 dot-atom form with a
+// domain name of at least two labels. Quoted local parts and address literals
+// aren't accepted.
+fn email_problem(email: &str) -> Option<String> {
+    if email.chars().count() > EMAIL_MAX_LENGTH {
+        return Some(format!("longer than {EMAIL_MAX_LENGTH} characters"));
+    }
+
+    let Some((local, domain)) = email.split_once('@') else {
+        return Some("has no @".to_owned());
+    };
+
+    if local.chars().count() > EMAIL_LOCAL_MAX_LENGTH {
+        Some(format!(
+            "part before @ is longer than {EMAIL_LOCAL_MAX_LENGTH} characters"
+        ))
+    } else if !local.split('.').all(atom) {
+        Some(format!("'{local}' is not a valid part before @"))
+    } else if !domain.contains('.') || !domain.split('.').all(label) {
+        Some(format!("'{domain}' is not a valid domain"))
+    } else {
+        None
+    }
+}
+
+// A valid email, normalized
+pub fn email(email: &str) -> Result<String, Error> {
+    let normalized = normalize_email(email);
+
+    match email_problem(&normalized) {
+        Some(problem) => Err(Error::Invalid(format!(
+            "email '{email}' is not valid: {problem}"
+        ))),
+        None => Ok(normalized),
+    }
+}
+
+fn name_problem(name: &str) -> Option<String> {
+    if name.chars().all(char::is_whitespace) {
+        Some("is blank".to_owned())
+    } else if name.chars().count() > NAME_MAX_LENGTH {
+        Some(format!("longer than {NAME_MAX_LENGTH} characters"))
+    } else {
+        None
+    }
+}
+
+pub fn name<'a>(what: &str, name: &'a str) -> Result<&'a str, Error> {
+    match name_problem(name) {
+        Some(problem) => Err(Error::Invalid(format!("{what} name '{name}' {problem}"))),
+        None => Ok(name),
+    }
+}
+
+// Rows of suppliers and clients breaking the checks. Emails that only need
+// normalizing are reported too, `migrate` fixes them.
+pub fn violations<C: GenericClient>(client: &mut C) -> Result<Vec<Violation>, Error> {
+    let mut violations = Vec::new();
+
+    for (table, _, id_column) in TABLES {
+        let rows = client.query(
+            &format!(
+                "
+                SELECT {id_column} as id, name, email, deleted_at IS NULL as current
+                FROM {table}
+                ORDER BY {id_column}
+                "
+            ),
+            &[],
+        )?;
+
+        // ids of rows not deleted by their normalized email
+        let mut emails: BTreeMap<String, Vec<i32>> = BTreeMap::new();
+
+        for row in &rows {
+            let (id, name, email): (i32, String, String) =
+                (row.get("id"), row.get("name"), row.get("email"));
+            let normalized = normalize_email(&email);
+
+            let mut violation = |column, value: &str, problem| {
+                violations.push(Violation {
+                    table,
+                    id,
+                    column,
+                    value: value.to_owned(),
+                    problem,
+                })
+            };
+
+            if let Some(problem) = name_problem(&name) {
+                violation("name", &name, problem);
+            }
+            if let Some(problem) = email_problem(&normalized) {
+                violation("email", &email, format!("not a valid email, {problem}"));
+            } else if normalized != email {
+                violation("email", &email, format!("not normalized, '{normalized}'"));
+            }
+
+            if row.get("current") {
+                emails.entry(normalized).or_default().push(id);
+            }
+        }
+
+        for (email, ids) in emails {
+            for &id in ids.iter().filter(|_| ids.len() > 1) {
+                let others: Vec<String> = ids
+                    .iter()
+                    .filter(|other| **other != id)
+                    .map(i32::to_string)
+                    .collect();
+
+                violations.push(Violation {
+                    table,
+                    id,
+                    column: "email",
+                    value: email.clone(),
+                    problem: format!("also the email of {}", others.join(", ")),
+                });
+            }
+        }
+    }
+
+    violations.sort_by(|a, b| (a.table, a.id, a.column).cmp(&(b.table, b.id, b.column)));
+
+    Ok(violations)
+}
+
+// Normalizes the emails of an existing database and adds the constraints, all
+// of it or nothing when some rows would still violate them
+pub fn migrate(client: &mut Client) -> Result<Migration, Error> {
+    let mut transaction = client.transaction()?;
+    let mut migration = Migration::default();
+
+    let migrated: bool = transaction
+        .query_one("SELECT to_regclass('client_email_index') IS NOT NULL", &[])?
+        .get(0);
+
+    if migrated {
+        return Ok(migration);
+    }
+
+    // the owner alters the tables, taking over those of databases older than
+    // the roles, which have no soft deletes either
+    roles::take_over(&mut transaction)?;
+
+    for (table, _, _) in TABLES {
+        transaction.batch_execute(&format!(
+            "ALTER TABLE {table} ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP"
+        ))?;
+    }
+
+    for (table, _, id_column) in TABLES {
+        let rows: Vec<(i32, String)> = transaction
+            .query(&format!("SELECT {id_column}, email FROM {table}"), &[])?
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .filter(|(_, email): &(i32, String)| normalize_email(email) != *email)
+            .collect();
+
+        for (id, email) in rows {
+            transaction.execute(
+                &format!("UPDATE {table} SET email = $2 WHERE {id_column} = $1"),
+                &[&id, &normalize_email(&email)],
+            )?;
+            migration.normalized += 1;
+        }
+    }
+
+    migration.violations = violations(&mut transaction)?;
+
+    if !migration.violations.is_empty() {
+        return Ok(migration);
+    }
+
+    for statement in table_statements() {
+        transaction.batch_execute(&statement)?;
+    }
+
+    transaction.commit()?;
+    migration.migrated = true;
+
+    Ok(migration)
+}
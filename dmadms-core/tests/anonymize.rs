@@ -0,0 +1,157 @@
+mod common;
+
+use dmadms_core::anonymize;
+use dmadms_core::archive::{self, Entity};
+use postgres::Client;
+
+// Columns with personal data and the tables keeping copies of them
+const COLUMNS: [(&str, &str); 13] = [
+    ("suppliers", "email"),
+    ("suppliers_history", "email"),
+    ("suppliers_archive", "email"),
+    ("clients", "email"),
+    ("clients_history", "email"),
+    ("clients_archive", "email"),
+    ("users", "name"),
+    ("users_archive", "name"),
+    ("client_addresses", "address"),
+    ("client_addresses_history", "address"),
+    ("client_addresses_archive", "address"),
+    ("warehouses", "address"),
+    ("warehouses_archive", "address"),
+];
+
+// (rows, distinct values) of every column
+fn shape(client: &mut Client) -> Vec<(i64, i64)> {
+    COLUMNS
+        .iter()
+        .map(|(table, column)| {
+            let row = client
+                .query_one(
+                    &format!("SELECT COUNT({column}), COUNT(DISTINCT {column}) FROM {table}"),
+                    &[],
+                )
+                .unwrap();
+            (row.get(0), row.get(1))
+        })
+        .collect()
+}
+
+#[test]
+fn replaces_personal_data_everywhere() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    // recognizable originals, more emails than the pool has, shared and former ones
+    client
+        .batch_execute(
+            "
+            UPDATE suppliers SET email = 'real.' || email;
+            UPDATE clients SET email = 'real.' || email;
+            UPDATE users SET name = 'real.' || name;
+            UPDATE client_addresses SET address = 'real ' || address;
+            UPDATE warehouses SET address = 'real ' || address;
+
+            INSERT INTO clients (name, email)
+            SELECT 'Extra ' || n, 'real.extra' || n || '@example.com'
+            FROM generate_series(1, 600) as n;
+
+            UPDATE suppliers
+            SET email = (SELECT email FROM clients WHERE client_id = 1)
+            WHERE supplier_id = 1;
+            ",
+        )
+        .unwrap();
+
+    let user_id: i32 = client
+        .query_one(
+            "SELECT MAX(user_id) FROM users WHERE client_id IS NULL AND supplier_id IS NULL",
+            &[],
+        )
+        .unwrap()
+        .get(0);
+    archive::delete(client, Entity::User, user_id).unwrap();
+    archive::purge(client, -1).unwrap();
+
+    let before = shape(client);
+    let history = common::count(client, "SELECT COUNT(1) FROM clients_history");
+    let geocoded = common::count(client, "SELECT COUNT(latitude) FROM client_addresses");
+
+    let summary = anonymize::anonymize(client).unwrap();
+    assert!(summary.values > 512);
+    assert!(summary
+        .columns
+        .iter()
+        .any(|(table, column, rows)| table == "users_archive" && *column == "name" && *rows == 1));
+
+    for (table, column) in COLUMNS {
+        assert_eq!(
+            common::count(
+                client,
+                &format!("SELECT COUNT(1) FROM {table} WHERE {column} LIKE '%real%'")
+            ),
+            0,
+            "{table}.{column} keeps original values"
+        );
+    }
+
+    // the same number of rows and distinct values, nothing else recorded in history
+    assert_eq!(shape(client), before);
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM clients_history"),
+        history
+    );
+    assert_eq!(
+        common::count(client, "SELECT COUNT(latitude) FROM client_addresses"),
+        geocoded
+    );
+
+    // a value gets the same fake one wherever it is
+    assert_eq!(
+        common::count(
+            client,
+            "
+            SELECT COUNT(1)
+            FROM suppliers
+            INNER JOIN clients
+            ON suppliers.email = clients.email
+            WHERE suppliers.supplier_id = 1 AND clients.client_id = 1
+            "
+        ),
+        1
+    );
+    assert_eq!(
+        common::count(
+            client,
+            "
+            SELECT COUNT(1)
+            FROM clients
+            INNER JOIN clients_history
+            ON clients.client_id = clients_history.client_id
+            AND clients_history.valid_to IS NULL
+            WHERE clients.email <> clients_history.email
+            "
+        ),
+        0
+    );
+    assert!(
+        common::count(
+            client,
+            "SELECT COUNT(1) FROM clients WHERE email LIKE '%+1@%'"
+        ) > 0
+    );
+
+    // history is recorded again afterwards
+    client
+        .execute(
+            "UPDATE clients SET email = 'new@example.com' WHERE client_id = 2",
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM clients_history"),
+        history + 1
+    );
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use dmadms_core::archive::{self, Entity};
+use dmadms_core::{lots, purchasing, sales, units, Error};
+use postgres::Client;
+
+#[test]
+fn purges_rows_with_their_details() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    units::seed(client).unwrap();
+
+    // a product out of stock and required by nobody, one still in stock
+    let (product, stocked): (i32, i32) = {
+        let row = client
+            .query_one(
+                "SELECT MIN(product_id), MAX(product_id) FROM product_locations WHERE count > 0",
+                &[],
+            )
+            .unwrap();
+        (row.get(0), row.get(1))
+    };
+    client
+        .execute(
+            "UPDATE product_locations SET count = 0 WHERE product_id = $1",
+            &[&product],
+        )
+        .unwrap();
+    client
+        .execute(
+            "DELETE FROM product_requirements WHERE product_id = ANY($1)",
+            &[&vec![product, stocked]],
+        )
+        .unwrap();
+
+    let offerings = common::count(
+        client,
+        &format!("SELECT COUNT(1) FROM supplier_products WHERE product_id = {product}"),
+    );
+    let units = common::count(
+        client,
+        &format!("SELECT COUNT(1) FROM product_units WHERE product_id = {product}"),
+    );
+    assert!(offerings > 0 && units > 0);
+
+    archive::delete(client, Entity::Product, product).unwrap();
+    archive::delete(client, Entity::Product, stocked).unwrap();
+
+    let summary = archive::purge(client, -1).unwrap();
+    assert!(summary.tables.contains(&(Entity::Product, 1, 1)));
+
+    assert_eq!(
+        common::count(
+            client,
+            &format!("SELECT COUNT(1) FROM products_archive WHERE product_id = {product}")
+        ),
+        1
+    );
+    assert_eq!(
+        common::count(
+            client,
+            &format!("SELECT COUNT(1) FROM supplier_products_archive WHERE product_id = {product}")
+        ),
+        offerings
+    );
+    assert_eq!(
+        common::count(
+            client,
+            &format!("SELECT COUNT(1) FROM product_units_archive WHERE product_id = {product}")
+        ),
+        units
+    );
+    assert_eq!(
+        common::count(
+            client,
+            &format!("SELECT COUNT(1) FROM product_units WHERE product_id = {product}")
+        ),
+        0
+    );
+    // stock keeps the other one, details included
+    assert_eq!(
+        common::count(
+            client,
+            &format!("SELECT COUNT(1) FROM product_units WHERE product_id = {stocked}")
+        ),
+        units
+    );
+}
+
+#[test]
+fn restores_users_deleted_with_their_supplier() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let users = |client: &mut Client| {
+        common::count(
+            client,
+            "SELECT COUNT(1) FROM users WHERE supplier_id = 1 AND deleted_at IS NULL",
+        )
+    };
+    assert_eq!(users(client), 1);
+
+    archive::delete(client, Entity::Supplier, 1).unwrap();
+    assert_eq!(users(client), 0);
+
+    archive::restore(client, Entity::Supplier, 1).unwrap();
+    assert_eq!(users(client), 1);
+
+    // a user deleted on its own stays deleted
+    let user_id: i32 = client
+        .query_one("SELECT user_id FROM users WHERE supplier_id = 1", &[])
+        .unwrap()
+        .get(0);
+    archive::delete(client, Entity::User, user_id).unwrap();
+    archive::delete(client, Entity::Supplier, 1).unwrap();
+    archive::restore(client, Entity::Supplier, 1).unwrap();
+    assert_eq!(users(client), 0);
+}
+
+#[test]
+fn deleted_rows_stay_out_of_orders_lots_and_receipts() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    lots::seed(client).unwrap();
+
+    // an address requiring several products, one of them deleted
+    let (client_address_id, product_id): (i32, i32) = {
+        let row = client
+            .query_one(
+                "
+                SELECT client_address_id, MIN(product_id)
+                FROM product_requirements
+                WHERE count > 0
+                GROUP BY client_address_id
+                HAVING COUNT(DISTINCT product_id) > 1
+                ORDER BY client_address_id
+                LIMIT 1
+                ",
+                &[],
+            )
+            .unwrap();
+        (row.get(0), row.get(1))
+    };
+    archive::delete(client, Entity::Product, product_id).unwrap();
+
+    let sales_order_id = sales::create_from_requirements(client, client_address_id).unwrap();
+    assert_eq!(
+        common::count(
+            client,
+            &format!(
+                "
+                SELECT COUNT(1)
+                FROM sales_order_lines
+                WHERE sales_order_id = {sales_order_id} AND product_id = {product_id}
+                "
+            )
+        ),
+        0
+    );
+
+    let warehouse_id = lots::expiring(client, 365).unwrap()[0].warehouse_id;
+    archive::delete(client, Entity::Warehouse, warehouse_id).unwrap();
+    assert!(lots::expiring(client, 365)
+        .unwrap()
+        .iter()
+        .all(|lot| lot.warehouse_id != warehouse_id));
+
+    let purchase_order_id: i32 = client
+        .query_one(
+            "
+            WITH purchase_order AS (
+                INSERT INTO purchase_orders (supplier_id, status)
+                VALUES (1, 'confirmed')
+                RETURNING purchase_order_id
+            )
+            INSERT INTO purchase_order_lines (purchase_order_id, product_id, count)
+            SELECT purchase_order_id, $1, 5
+            FROM purchase_order
+            RETURNING purchase_order_id
+            ",
+            &[&(product_id + 1)],
+        )
+        .unwrap()
+        .get(0);
+    assert!(matches!(
+        purchasing::receive(client, purchase_order_id, warehouse_id),
+        Err(Error::NotFound(_))
+    ));
+    purchasing::receive(client, purchase_order_id, warehouse_id + 1).unwrap();
+}
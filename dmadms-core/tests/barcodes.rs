@@ -0,0 +1,54 @@
+mod common;
+
+use dmadms_core::{barcodes, seed, units, Error};
+
+#[test]
+fn assigning_a_gtin_again_changes_nothing() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    units::seed(client).unwrap();
+
+    let gtins = barcodes::read_barcodes(seed::BARCODES.as_bytes()).unwrap();
+    barcodes::import(client, &gtins).unwrap();
+    barcodes::import(client, &gtins).unwrap();
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM product_barcodes"),
+        gtins.len() as i64
+    );
+
+    let scanned = barcodes::lookup(client, &gtins[0].gtin).unwrap();
+    let id = barcodes::add(client, scanned.product_id, &scanned.unit, &scanned.gtin).unwrap();
+    // leading zeros don't make another GTIN
+    assert_eq!(
+        barcodes::add(
+            client,
+            scanned.product_id,
+            &scanned.unit,
+            &format!("{:0>14}", scanned.gtin)
+        )
+        .unwrap(),
+        id
+    );
+
+    let other_unit: String = client
+        .query_one(
+            "
+            SELECT units.code
+            FROM product_units
+            INNER JOIN units
+            ON product_units.unit_id = units.unit_id
+            WHERE product_units.product_id = $1 AND units.code <> $2
+            LIMIT 1
+            ",
+            &[&scanned.product_id, &scanned.unit],
+        )
+        .unwrap()
+        .get(0);
+    assert!(matches!(
+        barcodes::add(client, scanned.product_id, &other_unit, &scanned.gtin),
+        Err(Error::Conflict(_))
+    ));
+}
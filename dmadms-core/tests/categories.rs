@@ -0,0 +1,102 @@
+mod common;
+
+use dmadms_core::categories;
+use dmadms_core::drift;
+use dmadms_core::roles::{self, Role};
+use postgres::error::SqlState;
+use postgres::Client;
+
+fn owner(client: &mut Client, relation: &str) -> String {
+    client
+        .query_one(
+            "SELECT pg_get_userbyid(relowner) FROM pg_class WHERE oid = to_regclass($1)",
+            &[&relation],
+        )
+        .unwrap()
+        .get(0)
+}
+
+#[test]
+fn migrates_two_level_databases() {
+    let Some(mut database) = common::baseline() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    assert_eq!(categories::migrate(client).unwrap(), 5);
+    assert_eq!(categories::migrate(client).unwrap(), 0);
+
+    // the tree is built as in a new schema, the rest of the baseline is older
+    let drifts: Vec<String> = drift::diff(client)
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .filter(|drift| drift.contains("categor"))
+        .collect();
+    assert_eq!(drifts, Vec::<String>::new());
+
+    let paths: Vec<(String, String)> = client
+        .query(
+            "
+            SELECT products.name, category_paths.path
+            FROM products
+            INNER JOIN category_paths
+            ON products.category_id = category_paths.category_id
+            ORDER BY products.product_id
+            ",
+            &[],
+        )
+        .unwrap()
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            ("Smoked Salmon".to_owned(), "Seafood / Salmon".to_owned()),
+            (
+                "Cold Water Shrimp".to_owned(),
+                "Seafood / Shrimp".to_owned()
+            ),
+            ("Blueberries".to_owned(), "Fruit / Berries".to_owned()),
+        ]
+    );
+
+    for relation in [
+        "categories",
+        "categories_history",
+        "category_paths",
+        "products",
+    ] {
+        assert_eq!(owner(client, relation), "dmadms_owner", "{relation}");
+    }
+
+    // the app changes the tree, its trigger records the history
+    roles::assume(client, Role::App).unwrap();
+    categories::add(client, Some("Fruit"), "Citrus").unwrap();
+    let error = client
+        .batch_execute("DELETE FROM categories_history")
+        .unwrap_err();
+    assert_eq!(error.code(), Some(&SqlState::INSUFFICIENT_PRIVILEGE));
+
+    roles::assume(client, Role::Auditor).unwrap();
+    let versions: i64 = client
+        .query_one("SELECT COUNT(1) FROM categories_history", &[])
+        .unwrap()
+        .get(0);
+    assert_eq!(versions, 6);
+    roles::reset(client).unwrap();
+
+    let readable: bool = client
+        .query_one(
+            "
+            SELECT has_table_privilege('dmadms_client', 'category_paths', 'SELECT')
+            AND has_table_privilege('dmadms_supplier', 'categories', 'SELECT')
+            AND has_table_privilege('dmadms_reporting', 'category_closure', 'SELECT')
+            ",
+            &[],
+        )
+        .unwrap()
+        .get(0);
+    assert!(readable);
+}
@@ -0,0 +1,291 @@
+// Every test crate includes this module and uses only a part of it
+#![allow(dead_code)]
+
+// A throwaway database for every test: a new database on the server from
+// POSTGRES_HOST, POSTGRES_USER, POSTGRES_PASSWORD and POSTGRES_DBNAME when they
+// are set, otherwise a server of the local PostgreSQL binaries (initdb and
+// postgres from PATH or PG_BIN) started in a temporary directory, otherwise a
+// Docker container of the image docker-compose runs. Without any of them the
+// tests fail, unless DMADMS_SKIP_DB_TESTS is set to skip them.
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use dmadms_core::{schema, Config};
+use postgres::{Client, NoTls};
+
+static DATABASES: AtomicUsize = AtomicUsize::new(0);
+
+const IMAGE: &str = "postgres:14-alpine";
+
+enum Server {
+    // database created on a running server, dropped with the connection parameters of the server
+    Shared { params: String, dbname: String },
+    // server started for the test, stopped and removed with its directory
+    Local { process: Child, dir: PathBuf },
+    // container started for the test, removed with the directory of its socket
+    Container { id: String, dir: PathBuf },
+}
+
+// The client is dropped before the server, closing its connection first
+pub struct TestDatabase {
+    pub client: Client,
+    // for further connections to the same database
+    pub config: Config,
+    _server: Server,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        match self {
+            Server::Shared { params, dbname } => {
+                if let Ok(mut admin) = Client::connect(params, NoTls) {
+                    _ = admin
+                        .batch_execute(&format!("DROP DATABASE IF EXISTS {dbname} WITH (FORCE)"));
+                }
+            }
+            Server::Local { process, dir } => {
+                _ = process.kill();
+                _ = process.wait();
+                _ = std::fs::remove_dir_all(dir);
+            }
+            Server::Container { id, dir } => {
+                _ = Command::new("docker")
+                    .args(["rm", "--force", id])
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+                _ = std::fs::remove_dir_all(dir);
+            }
+        }
+    }
+}
+
+fn unique_name() -> String {
+    format!(
+        "dmadms_test_{}_{}",
+        std::process::id(),
+        DATABASES.fetch_add(1, Ordering::SeqCst)
+    )
+}
+
+fn shared() -> Option<TestDatabase> {
+    let server = Config::from_env().ok()?;
+    let params = server.params();
+
+    let mut admin = Client::connect(&params, NoTls)
+        .unwrap_or_else(|error| panic!("can't connect to POSTGRES_HOST: {error}"));
+
+    let dbname = unique_name();
+    admin
+        // in UTF-8 like production databases, whatever the encoding of the server
+        .batch_execute(&format!(
+            "CREATE DATABASE {dbname} ENCODING 'UTF8' LOCALE 'C' TEMPLATE template0"
+        ))
+        .expect("can't create test database");
+
+    let config = Config {
+        dbname: dbname.clone(),
+        ..server
+    };
+
+    let client = Client::connect(&config.params(), NoTls).expect("can't connect to test database");
+
+    Some(TestDatabase {
+        client,
+        config,
+        _server: Server::Shared { params, dbname },
+    })
+}
+
+fn binary(name: &str) -> Option<PathBuf> {
+    let dirs = std::env::var_os("PG_BIN")
+        .into_iter()
+        .chain(std::env::var_os("PATH"))
+        .flat_map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>());
+
+    dirs.map(|dir| dir.join(name)).find(|path| path.is_file())
+}
+
+fn local() -> Option<TestDatabase> {
+    let (initdb, postgres) = (binary("initdb")?, binary("postgres")?);
+
+    let dir = std::env::temp_dir().join(unique_name());
+    let data = dir.join("data");
+
+    let initialized = Command::new(initdb)
+        .args([
+            "--auth=trust",
+            "--username=postgres",
+            "--encoding=UTF8",
+            "--locale=C",
+            "-D",
+        ])
+        .arg(&data)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+
+    if !initialized {
+        // initdb refuses to run as root, for example
+        _ = std::fs::remove_dir_all(&dir);
+        return None;
+    }
+
+    // listens on a socket in the directory only, so tests never fight over a port
+    let process = Command::new(postgres)
+        .arg("-D")
+        .arg(&data)
+        .args(["-c", "listen_addresses=", "-k"])
+        .arg(&dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // the password is ignored with trust authentication
+    let config = Config {
+        host: dir
+            .to_str()
+            .expect("temporary directory isn't UTF-8")
+            .to_owned(),
+        user: "postgres".to_owned(),
+        password: "postgres".to_owned(),
+        dbname: "postgres".to_owned(),
+    };
+    let mut server = Server::Local { process, dir };
+    let started = Instant::now();
+
+    while started.elapsed() < Duration::from_secs(30) {
+        if let Ok(client) = Client::connect(&config.params(), NoTls) {
+            return Some(TestDatabase {
+                client,
+                config,
+                _server: server,
+            });
+        }
+        if let Server::Local { process, .. } = &mut server {
+            if let Ok(Some(_)) = process.try_wait() {
+                return None;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    None
+}
+
+fn container() -> Option<TestDatabase> {
+    binary("docker")?;
+
+    // the server of the container listens on a socket in the directory, like a
+    // local one, and its user needs to write there
+    let dir = std::env::temp_dir().join(unique_name());
+    std::fs::create_dir_all(&dir).ok()?;
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).ok()?;
+
+    let output = Command::new("docker")
+        .args(["run", "--detach", "--rm"])
+        .args(["--env", "POSTGRES_HOST_AUTH_METHOD=trust", "--volume"])
+        .arg(format!("{}:/var/run/postgresql", dir.display()))
+        .arg(IMAGE)
+        .stderr(Stdio::null())
+        .output()
+        .ok()
+        .filter(|output| output.status.success());
+
+    let Some(output) = output else {
+        // no Docker daemon running, for example
+        _ = std::fs::remove_dir_all(&dir);
+        return None;
+    };
+
+    let config = Config {
+        host: dir
+            .to_str()
+            .expect("temporary directory isn't UTF-8")
+            .to_owned(),
+        user: "postgres".to_owned(),
+        password: "postgres".to_owned(),
+        dbname: "postgres".to_owned(),
+    };
+    let server = Server::Container {
+        id: String::from_utf8_lossy(&output.stdout).trim().to_owned(),
+        dir,
+    };
+    let started = Instant::now();
+
+    while started.elapsed() < Duration::from_secs(60) {
+        if let Ok(mut client) = Client::connect(&config.params(), NoTls) {
+            // the image initializes the database with a server listening on
+            // the socket only, and restarts it afterwards
+            let listening = client
+                .query_one("SELECT current_setting('listen_addresses')", &[])
+                .map(|row| !row.get::<_, &str>(0).is_empty())
+                .unwrap_or(false);
+
+            if listening {
+                return Some(TestDatabase {
+                    client,
+                    config,
+                    _server: server,
+                });
+            }
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    None
+}
+
+// An empty database, or `None` when no PostgreSQL is available and
+// DMADMS_SKIP_DB_TESTS is set
+pub fn database() -> Option<TestDatabase> {
+    let database = shared().or_else(local).or_else(container);
+
+    if database.is_none() {
+        if std::env::var_os("DMADMS_SKIP_DB_TESTS").is_none() {
+            panic!(
+                "no PostgreSQL for database tests: set POSTGRES_*, install initdb and postgres or \
+                 start Docker, or set DMADMS_SKIP_DB_TESTS to skip them"
+            );
+        }
+        eprintln!("skipped: DMADMS_SKIP_DB_TESTS is set and no PostgreSQL is available");
+    }
+
+    database
+}
+
+// A database with the tables created and filled like `init` does before its demo
+pub fn initialized() -> Option<TestDatabase> {
+    let mut database = database()?;
+
+    schema::create_tables(&mut database.client).expect("can't create tables");
+    schema::create_indexes(&mut database.client).expect("can't create indexes");
+    schema::init_tables(&mut database.client).expect("can't init tables");
+
+    Some(database)
+}
+
+// A database shaped like the ones the first db_initializer created, with two
+// fixed category levels and no roles, for the migrations
+pub fn baseline() -> Option<TestDatabase> {
+    let mut database = database()?;
+
+    database
+        .client
+        .batch_execute(include_str!("../fixtures/baseline.sql"))
+        .expect("can't create the baseline tables");
+
+    Some(database)
+}
+
+// The single number a query selects, mostly a COUNT
+pub fn count(client: &mut Client, query: &str) -> i64 {
+    client.query_one(query, &[]).unwrap().get(0)
+}
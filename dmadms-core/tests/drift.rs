@@ -0,0 +1,72 @@
+mod common;
+
+use dmadms_core::drift::{self, Drift};
+use dmadms_core::schema;
+
+#[test]
+fn created_schema_has_no_drift() {
+    let Some(mut database) = common::database() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    schema::create_tables(client).unwrap();
+    schema::create_indexes(client).unwrap();
+
+    assert_eq!(drift::diff(client).unwrap(), vec![]);
+}
+
+#[test]
+fn converge_sql_repairs_drift() {
+    let Some(mut database) = common::database() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    schema::create_tables(client).unwrap();
+    schema::create_indexes(client).unwrap();
+
+    client
+        .batch_execute(
+            "
+            ALTER TABLE warehouses ALTER COLUMN address DROP NOT NULL;
+            ALTER TABLE product_locations DROP CONSTRAINT product_locations_count_check;
+            DROP INDEX user_role_index;
+            DROP TABLE product_ingredients;
+            ALTER TABLE products ADD COLUMN legacy_code TEXT;
+            ",
+        )
+        .unwrap();
+
+    let drifts = drift::diff(client).unwrap();
+
+    assert!(drifts.contains(&Drift::ExtraColumn {
+        table: "products".to_owned(),
+        column: "legacy_code".to_owned(),
+    }));
+    assert!(drifts
+        .iter()
+        .any(|drift| matches!(drift, Drift::MissingRelation { name, .. } if name == "product_ingredients")));
+    assert!(drifts
+        .iter()
+        .any(|drift| matches!(drift, Drift::ChangedColumn { table, column, .. } if table == "warehouses" && column == "address")));
+    assert!(drifts
+        .iter()
+        .any(|drift| matches!(drift, Drift::MissingConstraint { name, .. } if name == "product_locations_count_check")));
+    assert!(drifts.iter().any(
+        |drift| matches!(drift, Drift::MissingIndex { name, .. } if name == "user_role_index")
+    ));
+
+    client
+        .batch_execute(&drift::converge_sql(&drifts).join("\n"))
+        .unwrap();
+
+    // dropping columns is left to a person
+    assert_eq!(
+        drift::diff(client).unwrap(),
+        vec![Drift::ExtraColumn {
+            table: "products".to_owned(),
+            column: "legacy_code".to_owned(),
+        }]
+    );
+}
@@ -0,0 +1,310 @@
+mod common;
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use dmadms_core::export::{self, Anonymization, Filter, Format, Manifest, Options};
+use sha2::{Digest, Sha256};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dmadms_export_{}_{name}", std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+
+    dir
+}
+
+fn options(format: Format) -> Options {
+    Options {
+        format,
+        filter: Filter::default(),
+        anonymize: BTreeSet::new(),
+    }
+}
+
+fn rows(manifest: &Manifest, table: &str) -> usize {
+    manifest
+        .tables
+        .iter()
+        .find(|exported| exported.table == table)
+        .unwrap_or_else(|| panic!("{table} isn't exported"))
+        .rows
+}
+
+fn read(dir: &Path, file: &str) -> String {
+    std::fs::read_to_string(dir.join(file)).unwrap()
+}
+
+#[test]
+fn exports_every_table_in_load_order() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+    let dir = scratch_dir("all");
+
+    let manifest = export::export(client, &dir, &options(Format::Csv)).unwrap();
+
+    for table in &manifest.tables {
+        assert_eq!(
+            table.rows as i64,
+            common::count(client, &format!("SELECT COUNT(1) FROM {}", table.table)),
+            "{} rows",
+            table.table
+        );
+
+        let checksum: String = Sha256::digest(std::fs::read(dir.join(&table.file)).unwrap())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        assert_eq!(checksum, table.checksum, "{} checksum", table.table);
+    }
+
+    assert!(manifest
+        .tables
+        .iter()
+        .all(|table| !table.table.ends_with("_history") && !table.table.ends_with("_archive")));
+
+    let position = |table: &str| {
+        manifest
+            .tables
+            .iter()
+            .position(|exported| exported.table == table)
+    };
+
+    for row in client
+        .query(
+            "
+            SELECT conrelid::regclass::TEXT, confrelid::regclass::TEXT
+            FROM pg_constraint
+            WHERE contype = 'f' AND conrelid <> confrelid
+            ",
+            &[],
+        )
+        .unwrap()
+    {
+        let (table, referenced): (&str, &str) = (row.get(0), row.get(1));
+
+        if let (Some(table), Some(referenced)) = (position(table), position(referenced)) {
+            assert!(
+                referenced < table,
+                "{referenced} should come before {table}"
+            );
+        }
+    }
+
+    let written: serde_json::Value = serde_json::from_str(&read(&dir, export::MANIFEST)).unwrap();
+    assert_eq!(written, manifest.to_json());
+    assert_eq!(written["schema_version"], dmadms_core::schema::version());
+
+    // password hashes are never exported
+    let mut users = csv::Reader::from_path(dir.join("users.csv")).unwrap();
+    let password = users
+        .headers()
+        .unwrap()
+        .iter()
+        .position(|column| column == "password")
+        .unwrap();
+    for user in users.records() {
+        assert_eq!(user.unwrap().get(password), Some(""));
+    }
+
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn filters_keep_referenced_rows_consistent() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+    let dir = scratch_dir("filtered");
+
+    let country: String = client
+        .query_one(
+            "
+            SELECT countries.name
+            FROM suppliers
+            INNER JOIN countries
+            ON suppliers.country_id = countries.country_id
+            GROUP BY countries.name
+            ORDER BY COUNT(1) DESC, countries.name
+            LIMIT 1
+            ",
+            &[],
+        )
+        .unwrap()
+        .get(0);
+
+    let options = Options {
+        filter: Filter {
+            country: Some(country.clone()),
+            client_id: Some(3),
+        },
+        ..options(Format::JsonLines)
+    };
+    let manifest = export::export(client, &dir, &options).unwrap();
+
+    let suppliers = common::count(
+        client,
+        &format!(
+            "
+            SELECT COUNT(1)
+            FROM suppliers
+            INNER JOIN countries
+            ON suppliers.country_id = countries.country_id
+            WHERE countries.name = '{country}'
+            "
+        ),
+    );
+    assert_eq!(rows(&manifest, "suppliers") as i64, suppliers);
+    assert_eq!(rows(&manifest, "clients"), 1);
+    assert_eq!(
+        rows(&manifest, "client_addresses") as i64,
+        common::count(
+            client,
+            "SELECT COUNT(1) FROM client_addresses WHERE client_id = 3"
+        )
+    );
+    assert_eq!(
+        rows(&manifest, "products") as i64,
+        common::count(client, "SELECT COUNT(1) FROM products")
+    );
+
+    let supplier_ids: BTreeSet<i64> = read(&dir, "suppliers.jsonl")
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<serde_json::Value>(line).unwrap()["supplier_id"]
+                .as_i64()
+                .unwrap()
+        })
+        .collect();
+
+    let offerings = read(&dir, "supplier_products.jsonl");
+    assert_eq!(
+        offerings.lines().count(),
+        rows(&manifest, "supplier_products")
+    );
+    for line in offerings.lines() {
+        let offering: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(supplier_ids.contains(&offering["supplier_id"].as_i64().unwrap()));
+    }
+
+    // users belong to the exported suppliers and clients or to neither
+    for line in read(&dir, "users.jsonl").lines() {
+        let user: serde_json::Value = serde_json::from_str(line).unwrap();
+
+        if let Some(supplier_id) = user["supplier_id"].as_i64() {
+            assert!(supplier_ids.contains(&supplier_id));
+        }
+        if let Some(client_id) = user["client_id"].as_i64() {
+            assert_eq!(client_id, 3);
+        }
+    }
+
+    assert!(matches!(
+        export::export(
+            client,
+            &dir,
+            &Options {
+                filter: Filter {
+                    country: Some("Atlantis".to_owned()),
+                    client_id: None,
+                },
+                ..options
+            }
+        ),
+        Err(dmadms_core::Error::NotFound(_))
+    ));
+
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn anonymizes_personal_data() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+    let dir = scratch_dir("anonymized");
+
+    let options = Options {
+        anonymize: Anonymization::ALL.into_iter().collect(),
+        ..options(Format::Csv)
+    };
+    let manifest = export::export(client, &dir, &options).unwrap();
+    assert_eq!(manifest.anonymized, Anonymization::ALL);
+
+    let exported = [
+        "suppliers.csv",
+        "clients.csv",
+        "client_addresses.csv",
+        "users.csv",
+    ]
+    .map(|file| read(&dir, file))
+    .concat();
+
+    for row in client
+        .query(
+            "
+            SELECT email FROM suppliers
+            UNION ALL SELECT email FROM clients
+            UNION ALL SELECT name FROM clients
+            UNION ALL SELECT address FROM client_addresses
+            UNION ALL SELECT name FROM users
+            ",
+            &[],
+        )
+        .unwrap()
+    {
+        let personal: &str = row.get(0);
+        assert!(!exported.contains(personal), "'{personal}' is exported");
+    }
+
+    // ids and references are kept
+    assert_eq!(
+        rows(&manifest, "client_addresses") as i64,
+        common::count(client, "SELECT COUNT(1) FROM client_addresses")
+    );
+
+    _ = std::fs::remove_dir_all(&dir);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn writes_typed_parquet_files() {
+    use parquet::basic::Type;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+    let dir = scratch_dir("parquet");
+
+    let manifest = export::export(client, &dir, &options(Format::Parquet)).unwrap();
+
+    for table in &manifest.tables {
+        let reader =
+            SerializedFileReader::new(std::fs::File::open(dir.join(&table.file)).unwrap()).unwrap();
+        assert_eq!(
+            reader.metadata().file_metadata().num_rows() as usize,
+            table.rows,
+            "{} rows",
+            table.table
+        );
+    }
+
+    let reader = SerializedFileReader::new(
+        std::fs::File::open(dir.join("product_locations.parquet")).unwrap(),
+    )
+    .unwrap();
+    let schema = reader.metadata().file_metadata().schema_descr();
+    let types: Vec<(String, Type)> = schema
+        .columns()
+        .iter()
+        .map(|column| (column.name().to_owned(), column.physical_type()))
+        .collect();
+    assert!(types.contains(&("count".to_owned(), Type::INT32)));
+
+    _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,116 @@
+-- The tables and indexes of a database created before the library existed, by
+-- the first db_initializer, with a few rows
+
+CREATE TABLE countries (
+    country_id  SERIAL PRIMARY KEY,
+    name        TEXT UNIQUE NOT NULL
+);
+
+CREATE TABLE suppliers (
+    supplier_id SERIAL PRIMARY KEY,
+    country_id  INTEGER REFERENCES countries NOT NULL,
+    name        TEXT NOT NULL,
+    email       TEXT NOT NULL
+);
+
+CREATE TABLE product_categories (
+    category_id SERIAL PRIMARY KEY,
+    name        TEXT UNIQUE NOT NULL
+);
+
+CREATE TABLE product_subcategories (
+    subcategory_id  SERIAL PRIMARY KEY,
+    category_id     INTEGER REFERENCES product_categories NOT NULL,
+    name            TEXT UNIQUE NOT NULL
+);
+
+CREATE TABLE products (
+    product_id      SERIAL PRIMARY KEY,
+    supplier_id     INTEGER REFERENCES suppliers NOT NULL,
+    subcategory_id  INTEGER REFERENCES product_subcategories NOT NULL,
+    name            TEXT UNIQUE NOT NULL
+);
+
+CREATE TABLE clients (
+    client_id   SERIAL PRIMARY KEY,
+    name        TEXT UNIQUE NOT NULL,
+    email       TEXT NOT NULL
+);
+
+CREATE TABLE client_addresses (
+    client_address_id   SERIAL PRIMARY KEY,
+    client_id           INTEGER REFERENCES clients NOT NULL,
+    address             TEXT NOT NULL
+);
+
+CREATE TABLE product_requirements (
+    product_requirement_id  SERIAL PRIMARY KEY,
+    product_id              INTEGER REFERENCES products NOT NULL,
+    client_address_id       INTEGER REFERENCES client_addresses NOT NULL,
+    count                   INTEGER NOT NULL,
+    CHECK (count >= 0)
+);
+
+CREATE TABLE warehouses (
+    warehouse_id    SERIAL PRIMARY KEY,
+    address         TEXT UNIQUE NOT NULL
+);
+
+CREATE TABLE product_locations (
+    product_location_id SERIAL PRIMARY KEY,
+    warehouse_id        INTEGER REFERENCES warehouses NOT NULL,
+    product_id          INTEGER REFERENCES products NOT NULL,
+    count               INTEGER NOT NULL,
+    CHECK (count >= 0)
+);
+
+CREATE TABLE user_roles (
+    user_role_id    SERIAL PRIMARY KEY,
+    name            TEXT UNIQUE NOT NULL
+);
+
+CREATE EXTENSION IF NOT EXISTS pgcrypto;
+
+CREATE TABLE users (
+    user_id             SERIAL PRIMARY KEY,
+    supplier_id         INTEGER REFERENCES suppliers UNIQUE, -- NULLABLE
+    client_id           INTEGER REFERENCES clients UNIQUE, -- NULLABLE
+    user_role_id        INTEGER REFERENCES user_roles NOT NULL,
+    name                TEXT UNIQUE NOT NULL,
+    password            TEXT NOT NULL, -- use encryption
+    CHECK ((supplier_id IS NULL) OR (client_id IS NULL))
+);
+
+CREATE INDEX user_index
+ON users(supplier_id, client_id);
+
+CREATE INDEX user_role_index
+ON user_roles(name);
+
+INSERT INTO countries (name) VALUES ('Norway'), ('Chile');
+
+INSERT INTO suppliers (country_id, name, email) VALUES
+    (1, 'Fjord Foods', 'Sales@Fjord.example.com '),
+    (2, 'Andes Export', 'andes@example.com');
+
+INSERT INTO product_categories (name) VALUES ('Seafood'), ('Fruit');
+
+INSERT INTO product_subcategories (category_id, name) VALUES
+    (1, 'Salmon'),
+    (1, 'Shrimp'),
+    (2, 'Berries');
+
+INSERT INTO products (supplier_id, subcategory_id, name) VALUES
+    (1, 1, 'Smoked Salmon'),
+    (1, 2, 'Cold Water Shrimp'),
+    (2, 3, 'Blueberries');
+
+INSERT INTO clients (name, email) VALUES ('Harbour Deli', 'orders@harbour.example.com');
+
+INSERT INTO client_addresses (client_id, address) VALUES (1, '1 Quay Street');
+
+INSERT INTO user_roles (name) VALUES ('admin'), ('supplier'), ('client');
+
+INSERT INTO users (supplier_id, client_id, user_role_id, name, password) VALUES
+    (NULL, NULL, 1, 'admin', crypt('password', gen_salt('md5'))),
+    (1, NULL, 2, 'fjord', crypt('password', gen_salt('md5')));
@@ -0,0 +1,60 @@
+mod common;
+
+use dmadms_core::fulfillment::{self, Inventory, Strategy};
+use dmadms_core::Error;
+
+#[test]
+fn plans_of_the_same_inventory_commit_once() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let inventory = Inventory::load(client).unwrap();
+    let first = fulfillment::plan(&inventory, Strategy::Greedy);
+    let second = fulfillment::plan(&inventory, Strategy::FairShare);
+    assert!(!first.allocations.is_empty());
+
+    fulfillment::commit_plan(client, &first).unwrap();
+    let allocations = common::count(client, "SELECT COUNT(1) FROM allocations");
+
+    assert!(matches!(
+        fulfillment::commit_plan(client, &second),
+        Err(Error::Conflict(_))
+    ));
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM allocations"),
+        allocations
+    );
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM fulfillment_plans"),
+        1
+    );
+
+    // nothing is over-allocated, and the session is usable after the failure
+    assert_eq!(
+        common::count(
+            client,
+            "
+            SELECT COUNT(1)
+            FROM product_locations
+            INNER JOIN (
+                SELECT allocations.warehouse_id, product_requirements.product_id, SUM(allocations.count) as count
+                FROM allocations
+                INNER JOIN product_requirements
+                ON allocations.product_requirement_id = product_requirements.product_requirement_id
+                GROUP BY allocations.warehouse_id, product_requirements.product_id
+            ) as allocated
+            ON product_locations.warehouse_id = allocated.warehouse_id
+            AND product_locations.product_id = allocated.product_id
+            WHERE allocated.count > product_locations.count
+            "
+        ),
+        0
+    );
+
+    // planned again, what is left can be committed
+    let inventory = Inventory::load(client).unwrap();
+    let rest = fulfillment::plan(&inventory, Strategy::Greedy);
+    fulfillment::commit_plan(client, &rest).unwrap();
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use dmadms_core::geocoding;
+use dmadms_core::Error;
+
+#[test]
+fn unreadable_gazetteer_entries_are_errors() {
+    let valid = "address,latitude,longitude\n1 Main St,51.5,-0.12\n";
+    assert_eq!(
+        geocoding::read_gazetteer(valid.as_bytes()).unwrap().len(),
+        1
+    );
+
+    for entry in [
+        "2 High St,north,-0.12",
+        "2 High St,51.5,",
+        "2 High St,91,-0.12",
+        "2 High St,51.5,180.5",
+        ",51.5,-0.12",
+    ] {
+        match geocoding::read_gazetteer(format!("{valid}{entry}\n").as_bytes()) {
+            Err(Error::Invalid(message)) => {
+                assert!(message.contains("line 3"), "{message}")
+            }
+            other => panic!("{entry} should be invalid, not {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn distances_between_antipodes_are_finite() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let warehouse_id: i32 = client
+        .query_one(
+            "
+            UPDATE warehouses SET latitude = 1, longitude = 2
+            WHERE warehouse_id = (SELECT MIN(warehouse_id) FROM warehouses)
+            RETURNING warehouse_id
+            ",
+            &[],
+        )
+        .unwrap()
+        .get(0);
+    let client_address_id: i32 = client
+        .query_one(
+            "
+            UPDATE client_addresses SET latitude = -1, longitude = -178
+            WHERE client_address_id = (SELECT MIN(client_address_id) FROM client_addresses)
+            RETURNING client_address_id
+            ",
+            &[],
+        )
+        .unwrap()
+        .get(0);
+
+    let nearest = geocoding::nearest_warehouses(client, client_address_id, 1).unwrap();
+    assert_eq!(nearest.len(), 1);
+    assert_eq!(nearest[0].warehouse_id, warehouse_id);
+    assert!((nearest[0].distance_km - std::f64::consts::PI * 6371.0).abs() < 1.0);
+
+    let distances = geocoding::distances(client).unwrap();
+    assert!(distances.values().all(|distance| distance.is_finite()));
+}
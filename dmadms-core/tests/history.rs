@@ -0,0 +1,57 @@
+mod common;
+
+use dmadms_core::history::{self, Table, Version};
+use postgres::{Client, NoTls};
+
+fn name(version: &Version) -> &str {
+    version
+        .fields
+        .iter()
+        .find(|(column, _)| column == "name")
+        .and_then(|(_, value)| value.as_deref())
+        .unwrap()
+}
+
+#[test]
+fn older_transactions_close_newer_versions() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let mut other = Client::connect(&database.config.params(), NoTls).unwrap();
+    let client = &mut database.client;
+
+    // begins before the other session changes the product
+    let mut older = client.transaction().unwrap();
+    older.batch_execute("SELECT 1").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    other
+        .execute(
+            "UPDATE products SET name = 'newer' WHERE product_id = 1",
+            &[],
+        )
+        .unwrap();
+
+    older
+        .execute(
+            "UPDATE products SET name = 'older' WHERE product_id = 1",
+            &[],
+        )
+        .unwrap();
+    older.commit().unwrap();
+
+    let versions = history::versions(client, Table::Product, 1).unwrap();
+    let names: Vec<&str> = versions.iter().map(name).collect();
+    assert_eq!(&names[names.len() - 2..], ["newer", "older"]);
+
+    for pair in versions.windows(2) {
+        assert_eq!(pair[0].valid_to, Some(pair[1].valid_from));
+    }
+
+    // each moment has one version, the last change is the current one
+    let newer = &versions[versions.len() - 2];
+    let current = history::as_of(client, Table::Product, 1, newer.valid_from).unwrap();
+    assert_eq!(name(&current), "newer");
+    let current = history::as_of(client, Table::Product, 1, newer.valid_to.unwrap()).unwrap();
+    assert_eq!(name(&current), "older");
+}
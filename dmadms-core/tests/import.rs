@@ -0,0 +1,351 @@
+mod common;
+
+use chrono::NaiveDate;
+use dmadms_core::fulfillment::{self, Inventory, Strategy};
+use dmadms_core::import::{self, Entity, Record};
+use dmadms_core::lots::{self, Lot};
+use dmadms_core::units;
+use postgres::Client;
+
+fn records(csv: &str) -> Vec<Record> {
+    import::read_csv(csv.as_bytes()).unwrap()
+}
+
+fn name(client: &mut Client, query: &str) -> String {
+    client.query_one(query, &[]).unwrap().get(0)
+}
+
+#[test]
+fn upserts_by_natural_keys() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    // new products are counted in the 'each' unit
+    units::seed(client).unwrap();
+
+    let country = name(
+        client,
+        "SELECT name FROM countries ORDER BY country_id LIMIT 1",
+    );
+    let category = name(
+        client,
+        "SELECT path FROM category_paths ORDER BY path LIMIT 1",
+    );
+
+    let suppliers = records(&format!(
+        "Name,Country,Email\nNew Supplier,{},first@example.com\n",
+        country.to_uppercase()
+    ));
+    let report = import::import(client, Entity::Suppliers, &suppliers, false).unwrap();
+    assert_eq!((report.inserted, report.updated), (1, 0));
+    assert!(report.committed);
+
+    let suppliers = records(&format!(
+        "name,country,email\nnew supplier,{country},second@example.com\n"
+    ));
+    let report = import::import(client, Entity::Suppliers, &suppliers, false).unwrap();
+    assert_eq!((report.inserted, report.updated), (0, 1));
+    assert_eq!(
+        name(
+            client,
+            "SELECT email FROM suppliers WHERE name = 'New Supplier'"
+        ),
+        "second@example.com"
+    );
+
+    // a subcategory is found by its name alone
+    let leaf = category.rsplit(" / ").next().unwrap().to_owned();
+    let products = records(&format!(
+        "name,category,supplier,sku,lead_time_days,min_order_quantity\n\
+         Imported Tea,\"{category}\",New Supplier,NS-1,4,12\n\
+         Imported Coffee,\"{leaf}\",New Supplier,NS-2,6,\n"
+    ));
+    let report = import::import(client, Entity::Products, &products, false).unwrap();
+    assert_eq!(report.errors, []);
+    assert_eq!((report.inserted, report.updated), (2, 0));
+
+    assert_eq!(
+        common::count(
+            client,
+            "
+            SELECT COUNT(1)
+            FROM supplier_products
+            INNER JOIN suppliers
+            ON supplier_products.supplier_id = suppliers.supplier_id
+            WHERE suppliers.name = 'New Supplier'
+            "
+        ),
+        2
+    );
+    assert_eq!(
+        common::count(
+            client,
+            "
+            SELECT COUNT(1)
+            FROM product_search
+            WHERE name IN ('Imported Tea', 'Imported Coffee')
+            "
+        ),
+        2
+    );
+
+    let warehouse = name(
+        client,
+        "SELECT address FROM warehouses ORDER BY warehouse_id LIMIT 1",
+    );
+    let stock = records(&format!(
+        "warehouse,product,count,unit\n\"{warehouse}\",Imported Tea,7,each\n"
+    ));
+    for expected in [(1, 0), (0, 1)] {
+        let report = import::import(client, Entity::Stock, &stock, false).unwrap();
+        assert_eq!(report.errors, []);
+        assert_eq!((report.inserted, report.updated), expected);
+    }
+    assert_eq!(
+        common::count(
+            client,
+            "
+            SELECT product_locations.count::BIGINT
+            FROM product_locations
+            INNER JOIN products
+            ON product_locations.product_id = products.product_id
+            WHERE products.name = 'Imported Tea'
+            "
+        ),
+        7
+    );
+
+    let (client_name, address): (String, String) = {
+        let row = client
+            .query_one(
+                "
+                SELECT clients.name, client_addresses.address
+                FROM client_addresses
+                INNER JOIN clients
+                ON client_addresses.client_id = clients.client_id
+                ORDER BY client_addresses.client_address_id
+                LIMIT 1
+                ",
+                &[],
+            )
+            .unwrap();
+        (row.get(0), row.get(1))
+    };
+
+    let addresses = records(&format!(
+        "client,address\n\"{client_name}\",\"{address}\"\n\"{client_name}\",1 New Street\n"
+    ));
+    let report = import::import(client, Entity::ClientAddresses, &addresses, false).unwrap();
+    assert_eq!((report.inserted, report.updated), (1, 1));
+
+    let requirements = records(&format!(
+        "client,address,product,count\n\"{client_name}\",1 New Street,Imported Coffee,5\n"
+    ));
+    let report = import::import(client, Entity::Requirements, &requirements, false).unwrap();
+    assert_eq!((report.inserted, report.updated), (1, 0));
+}
+
+#[test]
+fn dry_runs_and_failed_rows_keep_nothing() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let suppliers = common::count(client, "SELECT COUNT(1) FROM suppliers");
+    let country = name(
+        client,
+        "SELECT name FROM countries ORDER BY country_id LIMIT 1",
+    );
+
+    let valid = records(&format!(
+        "name,country,email\nDry Supplier,{country},dry@example.com\n"
+    ));
+    let report = import::import(client, Entity::Suppliers, &valid, true).unwrap();
+    assert_eq!(report.inserted, 1);
+    assert!(!report.committed);
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM suppliers"),
+        suppliers
+    );
+
+    let invalid = records(&format!(
+        "name,country,email\n\
+         Good Supplier,{country},good@example.com\n\
+         Lost Supplier,Atlantis,lost@example.com\n\
+         \n\
+         ,{country},nameless@example.com\n\
+         Careless Supplier,{country},careless.example.com\n"
+    ));
+    let report = import::import(client, Entity::Suppliers, &invalid, false).unwrap();
+    assert!(!report.committed);
+    assert_eq!(report.inserted, 1);
+    assert_eq!(
+        report
+            .errors
+            .iter()
+            .map(|error| error.line)
+            .collect::<Vec<_>>(),
+        [3, 5, 6]
+    );
+    assert_eq!(report.errors[0].message, "country 'Atlantis' not found");
+    assert_eq!(
+        report.errors[2].message,
+        "email 'careless.example.com' is not valid: has no @"
+    );
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM suppliers"),
+        suppliers
+    );
+
+    let report = import::import(
+        client,
+        Entity::Stock,
+        &records("warehouse,product,amount\nsomewhere,something,1\n"),
+        false,
+    )
+    .unwrap();
+    assert_eq!(
+        report
+            .errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+        [
+            "line 1: missing columns count",
+            "line 1: unknown columns amount"
+        ]
+    );
+}
+
+#[test]
+fn stock_stays_above_lots_and_allocations() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let inventory = Inventory::load(client).unwrap();
+    fulfillment::commit_plan(client, &fulfillment::plan(&inventory, Strategy::Greedy)).unwrap();
+
+    // the most allocated stock of a product and warehouse with unique names
+    let row = client
+        .query_one(
+            "
+            SELECT warehouses.address, products.name, SUM(allocations.count) as count
+            FROM allocations
+            INNER JOIN product_requirements
+            ON allocations.product_requirement_id = product_requirements.product_requirement_id
+            INNER JOIN products
+            ON product_requirements.product_id = products.product_id
+            INNER JOIN warehouses
+            ON allocations.warehouse_id = warehouses.warehouse_id
+            WHERE (SELECT COUNT(1) FROM products as same WHERE lower(same.name) = lower(products.name)) = 1
+            AND (SELECT COUNT(1) FROM warehouses as same WHERE same.address = warehouses.address) = 1
+            GROUP BY warehouses.warehouse_id, products.product_id
+            ORDER BY count DESC, warehouses.warehouse_id, products.product_id
+            LIMIT 1
+            ",
+            &[],
+        )
+        .unwrap();
+    let (warehouse, product, allocated): (String, String, i64) =
+        (row.get(0), row.get(1), row.get(2));
+
+    let stock = |count: i64| {
+        records(&format!(
+            "warehouse,product,count\n\"{warehouse}\",\"{product}\",{count}\n"
+        ))
+    };
+    let located = |client: &mut Client| {
+        common::count(
+            client,
+            &format!(
+                "
+                SELECT product_locations.count::BIGINT
+                FROM product_locations
+                INNER JOIN products ON product_locations.product_id = products.product_id
+                INNER JOIN warehouses ON product_locations.warehouse_id = warehouses.warehouse_id
+                WHERE products.name = '{}' AND warehouses.address = '{}'
+                ",
+                product.replace('\'', "''"),
+                warehouse.replace('\'', "''")
+            ),
+        )
+    };
+    let before = located(client);
+
+    let report = import::import(client, Entity::Stock, &stock(allocated - 1), false).unwrap();
+    assert_eq!(
+        report.errors[0].message,
+        format!(
+            "count {} is below the {allocated} allocated at the warehouse",
+            allocated - 1
+        )
+    );
+    assert_eq!(located(client), before);
+
+    let report = import::import(client, Entity::Stock, &stock(allocated), false).unwrap();
+    assert_eq!(report.errors, []);
+    assert_eq!(located(client), allocated);
+
+    // received lots count in the stock, which can't drop below them
+    let (warehouse_id, product_id): (i32, i32) = {
+        let row = client
+            .query_one(
+                "
+                SELECT warehouses.warehouse_id, products.product_id
+                FROM warehouses, products
+                WHERE warehouses.address = $1 AND products.name = $2
+                ",
+                &[&warehouse, &product],
+            )
+            .unwrap();
+        (row.get(0), row.get(1))
+    };
+    let lot = Lot {
+        supplier_lot_number: "IMPORTED-1".to_owned(),
+        production_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        expiry_date: NaiveDate::from_ymd_opt(2030, 1, 1).unwrap(),
+    };
+    lots::receive(client, warehouse_id, product_id, &lot, allocated as i32 + 3).unwrap();
+
+    let report = import::import(client, Entity::Stock, &stock(allocated + 2), false).unwrap();
+    assert_eq!(
+        report.errors[0].message,
+        format!(
+            "count {} is below the {} held in lots at the warehouse",
+            allocated + 2,
+            allocated + 3
+        )
+    );
+    assert_eq!(located(client), 2 * allocated + 3);
+}
+
+#[cfg(feature = "xlsx")]
+#[test]
+fn reads_workbooks() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let path =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/suppliers.xlsx");
+    let records = import::read(&path).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].line, 2);
+
+    let report = import::import(client, Entity::Suppliers, &records, false).unwrap();
+    assert_eq!(report.errors, []);
+    assert_eq!(report.inserted, 2);
+    assert_eq!(
+        common::count(
+            client,
+            "SELECT COUNT(1) FROM suppliers WHERE name IN ('Workbook Foods', 'Spreadsheet Supply')"
+        ),
+        2
+    );
+}
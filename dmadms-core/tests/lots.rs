@@ -0,0 +1,44 @@
+mod common;
+
+use dmadms_core::lots;
+
+#[test]
+fn consumes_from_lots_adding_up_beyond_an_integer() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    client
+        .batch_execute(
+            "
+            INSERT INTO product_locations (warehouse_id, product_id, count)
+            VALUES (1, 1, 2147483647)
+            ON CONFLICT (warehouse_id, product_id) DO UPDATE SET count = EXCLUDED.count;
+
+            INSERT INTO product_lots (product_id, supplier_lot_number, production_date, expiry_date)
+            VALUES
+                (1, 'BIG-1', CURRENT_DATE, CURRENT_DATE + 10),
+                (1, 'BIG-2', CURRENT_DATE, CURRENT_DATE + 20);
+
+            INSERT INTO lot_locations (product_lot_id, warehouse_id, count)
+            SELECT product_lot_id, 1, 1500000000
+            FROM product_lots
+            WHERE supplier_lot_number LIKE 'BIG-%';
+            ",
+        )
+        .unwrap();
+
+    let mut transaction = client.transaction().unwrap();
+    let picked = lots::consume(&mut transaction, 1, 1, 10).unwrap();
+    transaction.commit().unwrap();
+
+    let first: i32 = client
+        .query_one(
+            "SELECT product_lot_id FROM product_lots WHERE supplier_lot_number = 'BIG-1'",
+            &[],
+        )
+        .unwrap()
+        .get(0);
+    assert_eq!(picked, [(Some(first), 10)]);
+}
@@ -0,0 +1,161 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use dmadms_core::nonblocking::{self, Pool};
+use dmadms_core::search::Query;
+use dmadms_core::{documents, lots, offerings, pricing, search, seed};
+use postgres::Client;
+
+// Seeded rows that don't depend on when or how concurrently they were seeded,
+// passwords are salted and left out of users
+const SEEDED: [&str; 22] = [
+    "countries",
+    "suppliers",
+    "clients",
+    "categories",
+    "category_closure",
+    "products",
+    "supplier_products",
+    "warehouses",
+    "client_addresses",
+    "product_requirements",
+    "product_locations",
+    "(SELECT user_id, name, user_role_id, supplier_id, client_id FROM users)",
+    "exchange_rates",
+    "supplier_prices",
+    "client_prices",
+    "product_units",
+    "product_barcodes",
+    "product_lots",
+    "lot_locations",
+    "documents",
+    "product_allergens",
+    "product_nutrition",
+];
+
+fn fingerprint(client: &mut Client, rows: &str) -> String {
+    client
+        .query_one(
+            &format!(
+                "SELECT md5(COALESCE(string_agg(seeded::TEXT, ',' ORDER BY seeded::TEXT), '')) FROM {rows} as seeded"
+            ),
+            &[],
+        )
+        .unwrap()
+        .get(0)
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+fn pool(database: &common::TestDatabase) -> Pool {
+    nonblocking::pool(&database.config, 8).unwrap()
+}
+
+#[test]
+fn concurrent_seed_matches_blocking_seed() {
+    let (Some(mut blocking), Some(mut concurrent)) = (common::database(), common::database())
+    else {
+        return;
+    };
+
+    seed::reset(&mut blocking.client).unwrap();
+    let expected = seed::seed(&mut blocking.client).unwrap();
+
+    let summary = runtime().block_on(async {
+        let pool = pool(&concurrent);
+
+        nonblocking::seed::reset(&pool).await.unwrap();
+        nonblocking::seed::seed(&pool).await.unwrap()
+    });
+
+    assert_eq!(summary.geocoded.warehouses, expected.geocoded.warehouses);
+    assert_eq!(
+        summary.geocoded.client_addresses,
+        expected.geocoded.client_addresses
+    );
+    assert_eq!(summary.geocoded.unmatched, expected.geocoded.unmatched);
+    assert_eq!(summary.barcodes, expected.barcodes);
+
+    for rows in SEEDED {
+        assert_eq!(
+            fingerprint(&mut concurrent.client, rows),
+            fingerprint(&mut blocking.client, rows),
+            "{rows} differ"
+        );
+    }
+}
+
+#[test]
+fn reports_match_blocking_reports() {
+    let Some(mut database) = common::database() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    seed::reset(client).unwrap();
+    seed::seed(client).unwrap();
+
+    let query = Query {
+        text: "dolore".to_owned(),
+        category: Some("grocery".to_owned()),
+        limit: search::DEFAULT_LIMIT,
+        ..Query::default()
+    };
+
+    let expected = format!(
+        "{:?}",
+        (
+            pricing::stock_value(client, "EUR").unwrap(),
+            pricing::requirements_value(client, "USD").unwrap(),
+            search::search(client, &query).unwrap(),
+            offerings::suppliers_of(client, 3).unwrap(),
+            offerings::products_of(client, 1).unwrap(),
+            lots::expiring(client, 30).unwrap(),
+            documents::missing(client, None).unwrap(),
+        )
+    );
+
+    let reports = runtime().block_on(async {
+        let pool = pool(&database);
+        let client = nonblocking::get(&pool).await.unwrap();
+
+        format!(
+            "{:?}",
+            (
+                nonblocking::pricing::stock_value(&client, "EUR")
+                    .await
+                    .unwrap(),
+                nonblocking::pricing::requirements_value(&client, "USD")
+                    .await
+                    .unwrap(),
+                nonblocking::search::search(&client, &query).await.unwrap(),
+                nonblocking::offerings::suppliers_of(&client, 3)
+                    .await
+                    .unwrap(),
+                nonblocking::offerings::products_of(&client, 1)
+                    .await
+                    .unwrap(),
+                nonblocking::lots::expiring(&client, 30).await.unwrap(),
+                nonblocking::documents::missing(&client, None)
+                    .await
+                    .unwrap(),
+            )
+        )
+    });
+
+    assert_eq!(reports, expected);
+
+    let unknown = runtime().block_on(async {
+        let client = nonblocking::connect(&database.config).await.unwrap();
+
+        nonblocking::pricing::stock_value(&client, "XXX").await
+    });
+
+    assert!(matches!(unknown, Err(dmadms_core::Error::NotFound(_))));
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use dmadms_core::archive::{self, Entity};
+use dmadms_core::roles::{self, Role};
+use dmadms_core::search;
+use postgres::error::SqlState;
+use postgres::Client;
+
+fn denied(client: &mut Client, statement: &str) {
+    let error = client
+        .batch_execute(statement)
+        .expect_err(&format!("'{statement}' should be denied"));
+    assert_eq!(error.code(), Some(&SqlState::INSUFFICIENT_PRIVILEGE));
+}
+
+#[test]
+fn owner_owns_the_schema() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let foreign = common::count(
+        client,
+        "
+        SELECT COUNT(1)
+        FROM pg_class
+        INNER JOIN pg_namespace
+        ON pg_class.relnamespace = pg_namespace.oid
+        WHERE pg_namespace.nspname = current_schema()
+        AND pg_class.relkind IN ('r', 'v', 'm', 'S')
+        AND pg_get_userbyid(pg_class.relowner) <> 'dmadms_owner'
+        ",
+    );
+    assert_eq!(foreign, 0);
+
+    roles::assume(client, Role::App).unwrap();
+    denied(client, "CREATE TABLE scratch (id INTEGER)");
+    denied(client, "DROP TABLE products");
+}
+
+#[test]
+fn reporting_only_reads() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let products = common::count(client, "SELECT COUNT(1) FROM products");
+
+    roles::assume(client, Role::Reporting).unwrap();
+
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM products"),
+        products
+    );
+    assert!(common::count(client, "SELECT COUNT(1) FROM client_addresses") > 0);
+    assert!(common::count(client, "SELECT COUNT(name) FROM users") > 0);
+
+    denied(client, "UPDATE products SET name = name");
+    denied(client, "DELETE FROM product_requirements");
+    denied(client, "SELECT password FROM users");
+    denied(client, "SELECT refresh_product_search()");
+
+    roles::reset(client).unwrap();
+}
+
+#[test]
+fn app_writes_rows_but_not_history() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    roles::assume(client, Role::App).unwrap();
+
+    client
+        .execute(
+            "UPDATE products SET name = 'renamed' WHERE product_id = 1",
+            &[],
+        )
+        .unwrap();
+    client
+        .execute(
+            "INSERT INTO currencies (code, name) VALUES ('XTS', 'Test')",
+            &[],
+        )
+        .unwrap();
+    search::refresh(client).unwrap();
+
+    assert_eq!(
+        common::count(
+            client,
+            "SELECT COUNT(1) FROM product_search WHERE name = 'renamed'"
+        ),
+        1
+    );
+
+    denied(client, "DELETE FROM products_history");
+    denied(
+        client,
+        "UPDATE sales_order_status_history SET status = status",
+    );
+
+    roles::assume(client, Role::Auditor).unwrap();
+
+    assert_eq!(
+        common::count(
+            client,
+            "SELECT COUNT(1) FROM products_history WHERE product_id = 1"
+        ),
+        2
+    );
+    assert_eq!(
+        common::count(client, "SELECT COUNT(name) FROM users_archive"),
+        0
+    );
+
+    denied(client, "SELECT COUNT(1) FROM supplier_prices");
+    denied(client, "SELECT password FROM users_archive");
+    denied(client, "UPDATE products SET name = name");
+
+    roles::reset(client).unwrap();
+}
+
+#[test]
+fn app_reads_users_without_passwords() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let user_id: i32 = client
+        .query_one(
+            "SELECT user_id FROM users WHERE supplier_id IS NOT NULL ORDER BY user_id LIMIT 1",
+            &[],
+        )
+        .unwrap()
+        .get(0);
+
+    roles::assume(client, Role::App).unwrap();
+
+    assert!(common::count(client, "SELECT COUNT(name) FROM users") > 0);
+    denied(client, "SELECT name, password FROM users");
+    denied(client, "SELECT password FROM users_archive");
+
+    // purging archives users along with their hashes all the same
+    archive::delete(client, Entity::User, user_id).unwrap();
+    archive::purge(client, -1).unwrap();
+
+    roles::reset(client).unwrap();
+
+    assert_eq!(
+        common::count(
+            client,
+            &format!(
+                "SELECT COUNT(1) FROM users_archive WHERE user_id = {user_id} AND password <> ''"
+            )
+        ),
+        1
+    );
+}
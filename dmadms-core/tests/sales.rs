@@ -0,0 +1,135 @@
+mod common;
+
+use dmadms_core::fulfillment::{self, Allocation, Plan, Strategy};
+use dmadms_core::{sales, Error};
+use postgres::Client;
+
+fn allocation(
+    product_requirement_id: i32,
+    client_address_id: i32,
+    product_id: i32,
+    warehouse_id: i32,
+    count: i32,
+) -> Allocation {
+    Allocation {
+        product_requirement_id,
+        client_address_id,
+        product_id,
+        warehouse_id,
+        count,
+        distance_km: None,
+    }
+}
+
+fn require(client: &mut Client, client_address_id: i32, product_id: i32, count: i32) -> i32 {
+    client
+        .query_one(
+            "
+            INSERT INTO product_requirements (client_address_id, product_id, count)
+            VALUES ($1, $2, $3)
+            RETURNING product_requirement_id
+            ",
+            &[&client_address_id, &product_id, &count],
+        )
+        .unwrap()
+        .get(0)
+}
+
+#[test]
+fn shipping_releases_allocations_everywhere() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let ids = |client: &mut Client, query: &str| -> Vec<i32> {
+        client
+            .query(query, &[])
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect()
+    };
+    let product = ids(client, "SELECT MIN(product_id) FROM products")[0];
+    let warehouses = ids(
+        client,
+        "SELECT warehouse_id FROM warehouses ORDER BY warehouse_id LIMIT 2",
+    );
+    let addresses = ids(
+        client,
+        "SELECT client_address_id FROM client_addresses ORDER BY client_address_id LIMIT 2",
+    );
+    let (first, second) = (warehouses[0], warehouses[1]);
+    let (shipped, other) = (addresses[0], addresses[1]);
+
+    // twelve of the product in the first warehouse, ten in the second, nothing
+    // else required by the addresses
+    client
+        .execute(
+            "
+            DELETE FROM product_requirements
+            WHERE product_id = $1 OR client_address_id = ANY($2)
+            ",
+            &[&product, &addresses],
+        )
+        .unwrap();
+    for (warehouse_id, stock) in [(first, 12), (second, 10)] {
+        client
+            .execute(
+                "
+                INSERT INTO product_locations (warehouse_id, product_id, count)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (warehouse_id, product_id) DO UPDATE SET count = EXCLUDED.count
+                ",
+                &[&warehouse_id, &product, &stock],
+            )
+            .unwrap();
+    }
+
+    let requirement = require(client, shipped, product, 8);
+    let other_requirement = require(client, other, product, 6);
+
+    fulfillment::commit_plan(
+        client,
+        &Plan {
+            strategy: Strategy::Greedy,
+            allocations: vec![
+                allocation(requirement, shipped, product, first, 5),
+                allocation(requirement, shipped, product, second, 3),
+                allocation(other_requirement, other, product, first, 6),
+            ],
+            shortfalls: Vec::new(),
+        },
+    )
+    .unwrap();
+
+    let order = sales::create_from_requirements(client, shipped).unwrap();
+    sales::confirm(client, order).unwrap();
+
+    // six of the twelve in the first warehouse are reserved for the other address
+    assert!(matches!(
+        sales::ship(client, order, first),
+        Err(Error::Conflict(_))
+    ));
+
+    sales::ship(client, order, second).unwrap();
+
+    assert_eq!(
+        common::count(
+            client,
+            &format!(
+                "SELECT COUNT(1) FROM allocations WHERE product_requirement_id = {requirement}"
+            )
+        ),
+        0
+    );
+    assert_eq!(
+        common::count(
+            client,
+            &format!(
+                "SELECT SUM(count) FROM allocations WHERE product_requirement_id = {other_requirement}"
+            )
+        ),
+        6
+    );
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use dmadms_core::demo;
+use postgres::error::SqlState;
+use postgres::Client;
+
+fn rows(client: &mut Client, table: &str) -> usize {
+    common::count(client, &format!("SELECT COUNT(1) FROM {table}")) as usize
+}
+
+fn violation(result: Result<u64, postgres::Error>) -> SqlState {
+    result
+        .expect_err("statement should violate a constraint")
+        .code()
+        .expect("not a database error")
+        .clone()
+}
+
+#[test]
+fn init_fills_tables() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let countries = include!("../src/init_data/countries");
+    let suppliers = include!("../src/init_data/suppliers");
+    let clients = include!("../src/init_data/clients");
+    let emails = include!("../src/init_data/emails");
+    let products = include!("../src/init_data/products");
+    let addresses = include!("../src/init_data/addresses");
+    let product_categories = include!("../src/init_data/product_categories");
+
+    let supplier_count = suppliers.len().min(emails.len());
+    let client_count = clients.len().min(emails.len());
+    let category_count: usize = product_categories
+        .iter()
+        .map(|(_, subcategories)| 1 + subcategories.len())
+        .sum();
+
+    assert_eq!(rows(client, "countries"), countries.len());
+    assert_eq!(rows(client, "suppliers"), supplier_count);
+    assert_eq!(rows(client, "clients"), client_count);
+    assert_eq!(rows(client, "categories"), category_count);
+    assert_eq!(rows(client, "products"), products.len());
+    assert_eq!(rows(client, "supplier_products"), products.len());
+    assert_eq!(rows(client, "warehouses"), addresses.len());
+    assert_eq!(rows(client, "client_addresses"), addresses.len());
+    assert_eq!(rows(client, "user_roles"), 4);
+    // an admin, 8 managers and a user of every supplier and client
+    assert_eq!(rows(client, "users"), 1 + 8 + supplier_count + client_count);
+
+    // every category is its own ancestor, subcategories have their root as well
+    assert_eq!(
+        rows(client, "category_closure"),
+        category_count + category_count - product_categories.len()
+    );
+
+    // requirements and stock cover the first 10 products, leaving out zero counts
+    assert!(rows(client, "product_requirements") <= 10 * addresses.len());
+    assert!(rows(client, "product_requirements") > 9 * addresses.len());
+    assert!(rows(client, "product_locations") <= 10 * addresses.len());
+    assert!(rows(client, "product_locations") > 9 * addresses.len());
+
+    // inserted rows are the first versions in their history
+    assert_eq!(rows(client, "products_history"), products.len());
+    assert_eq!(rows(client, "categories_history"), category_count);
+}
+
+#[test]
+fn constraints_reject_invalid_rows() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    assert_eq!(
+        violation(client.execute(
+            "UPDATE product_locations SET count = -1 WHERE product_location_id = 1",
+            &[],
+        )),
+        SqlState::CHECK_VIOLATION
+    );
+
+    assert_eq!(
+        violation(client.execute(
+            "
+            INSERT INTO users (name, password, user_role_id, supplier_id, client_id)
+            SELECT 'both', 'password', user_role_id, 1, 1
+            FROM user_roles
+            WHERE name = 'supplier'
+            ",
+            &[],
+        )),
+        SqlState::CHECK_VIOLATION
+    );
+
+    assert_eq!(
+        violation(client.execute(
+            "INSERT INTO product_requirements (client_address_id, product_id, count) VALUES (1, 1, -1)",
+            &[],
+        )),
+        SqlState::CHECK_VIOLATION
+    );
+
+    assert_eq!(
+        violation(client.execute(
+            "INSERT INTO product_locations (warehouse_id, product_id, count) VALUES (1, 0, 1)",
+            &[],
+        )),
+        SqlState::FOREIGN_KEY_VIOLATION
+    );
+
+    assert_eq!(
+        violation(client.execute(
+            "INSERT INTO users (name, password, user_role_id) SELECT name, 'password', user_role_id FROM users LIMIT 1",
+            &[],
+        )),
+        SqlState::UNIQUE_VIOLATION
+    );
+
+    // a deleted user's name can be taken again
+    client
+        .batch_execute(
+            "
+            UPDATE users SET deleted_at = CURRENT_TIMESTAMP WHERE name = 'Gigachad';
+            INSERT INTO users (name, password, user_role_id) SELECT 'Gigachad', 'password', user_role_id FROM user_roles LIMIT 1;
+            ",
+        )
+        .unwrap();
+}
+
+#[test]
+fn demo_queries_return_init_data() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let countries = include!("../src/init_data/countries");
+    let product_categories = include!("../src/init_data/product_categories");
+    let subcategory_count: usize = product_categories
+        .iter()
+        .map(|(_, subcategories)| subcategories.len())
+        .sum();
+
+    let mut found = demo::countries(client).unwrap();
+    found.sort();
+    let mut expected: Vec<String> = countries
+        .iter()
+        .map(|country| country.to_string())
+        .collect();
+    expected.sort();
+    assert_eq!(found, expected);
+
+    assert_eq!(
+        demo::subcategories(client, &[]).unwrap().len(),
+        subcategory_count
+    );
+
+    let suppliers = demo::suppliers(client, 10).unwrap();
+    assert_eq!(suppliers.len(), 10);
+    for (supplier, email, country) in &suppliers {
+        assert!(!supplier.is_empty());
+        assert!(email.contains('@'));
+        assert!(countries.contains(&country.as_str()));
+    }
+
+    let groceries = demo::groceries(client, "M").unwrap();
+    assert!(!groceries.is_empty());
+    for (subcategory, _) in &groceries {
+        assert!(subcategory.starts_with('M'));
+        assert!(product_categories
+            .iter()
+            .any(|(category, subcategories)| *category == "Grocery"
+                && subcategories.contains(&subcategory.as_str())));
+    }
+
+    let users = demo::count_users(client).unwrap();
+    let supplier_users = rows(client, "users WHERE supplier_id IS NOT NULL") as u64;
+    assert_eq!(
+        demo::delete_supplier_users(client, "password").unwrap(),
+        supplier_users
+    );
+    assert_eq!(
+        demo::count_users(client).unwrap(),
+        users - supplier_users as i64
+    );
+    // they are deleted already
+    assert_eq!(demo::delete_supplier_users(client, "password").unwrap(), 0);
+
+    demo::lowercase_categories(client).unwrap();
+
+    let except = ["grocery", "healthy eating"];
+    let subcategories = demo::subcategories(client, &except).unwrap();
+    let expected: usize = product_categories
+        .iter()
+        .filter(|(category, _)| !except.contains(&category.to_lowercase().as_str()))
+        .map(|(_, subcategories)| subcategories.len())
+        .sum();
+    assert_eq!(subcategories.len(), expected);
+    for (category, subcategory) in subcategories {
+        assert!(!except.contains(&category.as_str()));
+        assert_eq!(category, category.to_lowercase());
+        assert_eq!(subcategory, subcategory.to_lowercase());
+    }
+}
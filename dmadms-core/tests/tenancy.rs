@@ -0,0 +1,221 @@
+mod common;
+
+use dmadms_core::tenancy::{self, Tenant};
+use dmadms_core::{Config, Error};
+use postgres::error::SqlState;
+use postgres::{Client, NoTls};
+
+fn user_of(client: &mut Client, column: &str, id: i32) -> String {
+    client
+        .query_one(
+            &format!("SELECT name FROM users WHERE {column} = $1"),
+            &[&id],
+        )
+        .unwrap()
+        .get(0)
+}
+
+#[test]
+fn clients_see_only_their_rows() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let requirements = common::count(client, "SELECT COUNT(1) FROM product_requirements");
+    let own_requirements = common::count(
+        client,
+        "
+        SELECT COUNT(1)
+        FROM product_requirements
+        INNER JOIN client_addresses
+        ON product_requirements.client_address_id = client_addresses.client_address_id
+        WHERE client_addresses.client_id = 3
+        ",
+    );
+    let other_address: i32 = client
+        .query_one(
+            "SELECT client_address_id FROM client_addresses WHERE client_id <> 3 LIMIT 1",
+            &[],
+        )
+        .unwrap()
+        .get(0);
+
+    let user = user_of(client, "client_id", 3);
+    let tenant = tenancy::tenant(client, &user).unwrap();
+    assert_eq!(tenant, Some(Tenant::Client(3)));
+
+    tenancy::enter(client, tenant.unwrap()).unwrap();
+
+    assert_eq!(common::count(client, "SELECT COUNT(1) FROM clients"), 1);
+    assert_eq!(
+        common::count(
+            client,
+            "SELECT COUNT(1) FROM client_addresses WHERE client_id <> 3"
+        ),
+        0
+    );
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM product_requirements"),
+        own_requirements
+    );
+    assert_eq!(common::count(client, "SELECT COUNT(1) FROM suppliers"), 0);
+    assert!(common::count(client, "SELECT COUNT(1) FROM products") > 0);
+
+    let error = client
+        .execute(
+            "INSERT INTO product_requirements (client_address_id, product_id, count) VALUES ($1, 1, 5)",
+            &[&other_address],
+        )
+        .expect_err("requirement of another client should be rejected");
+    assert_eq!(error.code(), Some(&SqlState::INSUFFICIENT_PRIVILEGE));
+
+    let error = client
+        .execute("SELECT COUNT(1) FROM users", &[])
+        .expect_err("users should be hidden from clients");
+    assert_eq!(error.code(), Some(&SqlState::INSUFFICIENT_PRIVILEGE));
+
+    tenancy::leave(client).unwrap();
+
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM product_requirements"),
+        requirements
+    );
+}
+
+#[test]
+fn suppliers_see_only_their_offerings() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let offered = common::count(
+        client,
+        "SELECT COUNT(DISTINCT product_id) FROM supplier_products WHERE supplier_id = 2",
+    );
+
+    let user = user_of(client, "supplier_id", 2);
+    let tenant = tenancy::tenant(client, &user).unwrap();
+    assert_eq!(tenant, Some(Tenant::Supplier(2)));
+
+    tenancy::enter(client, tenant.unwrap()).unwrap();
+
+    assert_eq!(common::count(client, "SELECT COUNT(1) FROM suppliers"), 1);
+    assert_eq!(
+        common::count(
+            client,
+            "SELECT COUNT(1) FROM supplier_products WHERE supplier_id <> 2"
+        ),
+        0
+    );
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM products"),
+        offered
+    );
+    assert_eq!(common::count(client, "SELECT COUNT(1) FROM clients"), 0);
+    assert_eq!(
+        common::count(client, "SELECT COUNT(1) FROM product_requirements"),
+        0
+    );
+
+    tenancy::leave(client).unwrap();
+}
+
+#[test]
+fn staff_have_no_tenant() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    assert_eq!(tenancy::tenant(client, "Gigachad").unwrap(), None);
+    assert!(matches!(
+        tenancy::tenant(client, "nobody"),
+        Err(Error::NotFound(_))
+    ));
+}
+
+#[test]
+fn sessions_cant_pose_as_other_tenants() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    // a login of its own for the client, as for direct SQL access
+    let login = format!("{}_login", database.config.dbname);
+    client
+        .batch_execute(&format!("CREATE ROLE {login} LOGIN PASSWORD 'login'"))
+        .unwrap();
+    tenancy::grant(client, &login, Tenant::Client(3)).unwrap();
+    tenancy::grant(client, &login, Tenant::Client(3)).unwrap();
+    assert!(matches!(
+        tenancy::grant(client, &login, Tenant::Client(4)),
+        Err(Error::Conflict(_))
+    ));
+    // the role of the other client, as `enter` creates it
+    tenancy::enter(client, Tenant::Client(4)).unwrap();
+    tenancy::leave(client).unwrap();
+
+    let config = Config {
+        user: login.clone(),
+        password: "login".to_owned(),
+        ..database.config.clone()
+    };
+    let mut session = Client::connect(&config.params(), NoTls).unwrap();
+
+    let clients = |session: &mut Client| -> Vec<i32> {
+        session
+            .query("SELECT client_id FROM clients", &[])
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect()
+    };
+    assert_eq!(clients(&mut session), [3]);
+
+    // session variables don't name the tenant
+    session
+        .batch_execute(
+            "
+            SET dmadms.client_id = '4';
+            SET dmadms.supplier_id = '2';
+            ",
+        )
+        .unwrap();
+    assert_eq!(clients(&mut session), [3]);
+    assert_eq!(
+        common::count(&mut session, "SELECT COUNT(1) FROM suppliers"),
+        0
+    );
+
+    let error = session
+        .batch_execute(&format!("SET ROLE {}", Tenant::Client(4).role()))
+        .expect_err("the role of another client should be out of reach");
+    assert_eq!(error.code(), Some(&SqlState::INSUFFICIENT_PRIVILEGE));
+
+    let error = session
+        .execute(
+            "INSERT INTO tenant_roles (role_name, client_id) VALUES (current_user || '_2', 4)",
+            &[],
+        )
+        .expect_err("tenants should not map roles");
+    assert_eq!(error.code(), Some(&SqlState::INSUFFICIENT_PRIVILEGE));
+
+    // the role of all clients belongs to none of them
+    session
+        .batch_execute(&format!("SET ROLE {}", tenancy::CLIENT_ROLE))
+        .unwrap();
+    assert!(clients(&mut session).is_empty());
+
+    drop(session);
+    client
+        .batch_execute(&format!(
+            "
+            DELETE FROM tenant_roles WHERE role_name = '{login}';
+            DROP ROLE {login};
+            "
+        ))
+        .unwrap();
+}
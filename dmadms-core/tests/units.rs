@@ -0,0 +1,22 @@
+use dmadms_core::units;
+
+#[test]
+fn renders_counts_exactly() {
+    assert_eq!(units::render(24, 12), "2");
+    assert_eq!(units::render(7, 12), "7/12");
+    assert_eq!(units::render(19, 12), "1 7/12");
+    assert_eq!(units::render(6, 4), "1.5");
+    assert_eq!(units::render(-3, 8), "-0.375");
+
+    // many places, and large counts in large units
+    assert_eq!(
+        units::render(1, 1 << 30),
+        "0.000000000931322574615478515625"
+    );
+    assert_eq!(units::render(i64::MAX, 1024), "9007199254740991.9990234375");
+    // too many places to compute, a fraction instead
+    assert_eq!(
+        units::render(i64::MAX, 1 << 30),
+        "8589934591 1073741823/1073741824"
+    );
+}
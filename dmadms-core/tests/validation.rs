@@ -0,0 +1,259 @@
+mod common;
+
+use dmadms_core::{drift, validation};
+use postgres::Client;
+
+const VALID: [&str; 5] = [
+    "orders@example.com",
+    " Sales.Team@Example.COM ",
+    "o'brien+invoices@mail.example.co.uk",
+    "x@a-b.io",
+    "!#$%&*/=?^_`{|}~-@example.com",
+];
+
+const INVALID: [&str; 10] = [
+    "example.com",
+    "two@at@example.com",
+    ".orders@example.com",
+    "orders..team@example.com",
+    "orders team@example.com",
+    "\"orders\"@example.com",
+    "orders@localhost",
+    "orders@-example.com",
+    "orders@exa_mple.com",
+    "orders@[192.168.0.1]",
+];
+
+// Constraints, index and data of a database from before the validation
+fn unvalidated(client: &mut Client) {
+    client
+        .batch_execute(
+            "
+            ALTER TABLE suppliers DROP CONSTRAINT supplier_name_length;
+            ALTER TABLE suppliers DROP CONSTRAINT supplier_email_valid;
+            DROP INDEX supplier_email_index;
+            ALTER TABLE clients DROP CONSTRAINT client_name_length;
+            ALTER TABLE clients DROP CONSTRAINT client_email_valid;
+            DROP INDEX client_email_index;
+
+            UPDATE clients SET email = 'Mixed.Case@Example.com' WHERE client_id = 1;
+            UPDATE clients SET email = ' mixed.case@example.com' WHERE client_id = 2;
+            UPDATE clients SET email = 'not an email' WHERE client_id = 3;
+            UPDATE suppliers SET name = repeat('x', 321) WHERE supplier_id = 1;
+            UPDATE suppliers SET email = upper(email) WHERE supplier_id = 2;
+            ",
+        )
+        .unwrap();
+}
+
+#[test]
+fn validates_emails_and_names() {
+    assert_eq!(
+        validation::email(" Sales.Team@Example.COM ").unwrap(),
+        "sales.team@example.com"
+    );
+
+    for email in VALID {
+        assert!(validation::email(email).is_ok(), "{email} should be valid");
+    }
+    for email in INVALID {
+        assert!(
+            validation::email(email).is_err(),
+            "{email} should be invalid"
+        );
+    }
+
+    let local = "x".repeat(validation::EMAIL_LOCAL_MAX_LENGTH);
+    assert!(validation::email(&format!("{local}@example.com")).is_ok());
+    assert!(validation::email(&format!("{local}x@example.com")).is_err());
+
+    let domain = vec!["x".repeat(60); 5].join(".");
+    assert!(validation::email(&format!("{local}@{domain}.com")).is_err());
+
+    assert!(validation::name("client", "Acme").is_ok());
+    assert!(validation::name("client", "  ").is_err());
+    assert!(validation::name("client", &"é".repeat(validation::NAME_MAX_LENGTH)).is_ok());
+    assert!(validation::name("client", &"x".repeat(validation::NAME_MAX_LENGTH + 1)).is_err());
+}
+
+#[test]
+fn constraints_agree_with_the_checks() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    for email in VALID.into_iter().chain(INVALID) {
+        let normalized = validation::normalize_email(email);
+        let mut transaction = client.transaction().unwrap();
+
+        let inserted = transaction.execute(
+            "INSERT INTO clients (name, email) VALUES ('Checked', $1)",
+            &[&normalized],
+        );
+
+        assert_eq!(
+            inserted.is_ok(),
+            validation::email(email).is_ok(),
+            "'{normalized}'"
+        );
+    }
+
+    for name in [
+        "Acme",
+        " Acme\t",
+        "\u{00a0}Acme",
+        "\t\n",
+        "\u{00a0}",
+        "\u{2003}\u{3000}",
+        "\u{0085}",
+        "\u{200b}",
+    ] {
+        let mut transaction = client.transaction().unwrap();
+
+        let inserted = transaction.execute(
+            "INSERT INTO clients (name, email) VALUES ($1, 'named@example.com')",
+            &[&name],
+        );
+
+        assert_eq!(
+            inserted.is_ok(),
+            validation::name("client", name).is_ok(),
+            "{name:?}"
+        );
+    }
+
+    // stored emails are normalized, unique among clients not deleted
+    assert!(validation::violations(client).unwrap().is_empty());
+
+    let email: String = client
+        .query_one("SELECT email FROM clients WHERE client_id = 1", &[])
+        .unwrap()
+        .get(0);
+    assert!(client
+        .execute(
+            "INSERT INTO clients (name, email) VALUES ('Copy', $1)",
+            &[&email]
+        )
+        .is_err());
+    assert!(client
+        .execute(
+            "INSERT INTO clients (name, email) VALUES ('Upper', 'Upper@example.com')",
+            &[]
+        )
+        .is_err());
+}
+
+#[test]
+fn migrates_existing_databases() {
+    let Some(mut database) = common::initialized() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    unvalidated(client);
+
+    let violations = validation::violations(client).unwrap();
+    let found: Vec<(&str, i32, &str)> = violations
+        .iter()
+        .map(|violation| (violation.table, violation.id, violation.column))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("clients", 1, "email"),
+            ("clients", 1, "email"),
+            ("clients", 2, "email"),
+            ("clients", 2, "email"),
+            ("clients", 3, "email"),
+            ("suppliers", 1, "name"),
+            ("suppliers", 2, "email"),
+        ]
+    );
+    assert!(violations
+        .iter()
+        .any(|violation| violation.problem == "also the email of 2"));
+
+    // nothing changes while rows would violate the constraints
+    let migration = validation::migrate(client).unwrap();
+    assert!(!migration.migrated);
+    assert_eq!(
+        migration
+            .violations
+            .iter()
+            .map(|violation| (violation.table, violation.id, violation.column))
+            .collect::<Vec<_>>(),
+        [
+            ("clients", 1, "email"),
+            ("clients", 2, "email"),
+            ("clients", 3, "email"),
+            ("suppliers", 1, "name"),
+        ]
+    );
+    assert_eq!(
+        common::count(
+            client,
+            "SELECT COUNT(1) FROM clients WHERE email = 'Mixed.Case@Example.com'"
+        ),
+        1
+    );
+
+    client
+        .batch_execute(
+            "
+            UPDATE clients SET email = 'other@example.com' WHERE client_id = 2;
+            UPDATE clients SET email = 'client3@example.com' WHERE client_id = 3;
+            UPDATE suppliers SET name = 'Shortened' WHERE supplier_id = 1;
+            ",
+        )
+        .unwrap();
+
+    let migration = validation::migrate(client).unwrap();
+    assert!(migration.migrated);
+    assert_eq!(migration.normalized, 2);
+    assert!(validation::violations(client).unwrap().is_empty());
+    assert_eq!(drift::diff(client).unwrap(), []);
+
+    let migration = validation::migrate(client).unwrap();
+    assert!(!migration.migrated);
+    assert!(migration.violations.is_empty());
+}
+
+#[test]
+fn migrates_baseline_databases() {
+    let Some(mut database) = common::baseline() else {
+        return;
+    };
+    let client = &mut database.client;
+
+    let migration = validation::migrate(client).unwrap();
+    assert!(migration.migrated);
+    assert_eq!(migration.normalized, 1);
+    assert_eq!(
+        common::count(
+            client,
+            "SELECT COUNT(1) FROM suppliers WHERE email = 'sales@fjord.example.com'"
+        ),
+        1
+    );
+
+    // the owner took the tables over and holds the constraints
+    assert_eq!(
+        common::count(
+            client,
+            "
+            SELECT COUNT(1)
+            FROM pg_class
+            WHERE relname IN ('suppliers', 'clients', 'supplier_email_index', 'client_email_index')
+            AND pg_get_userbyid(relowner) = 'dmadms_owner'
+            "
+        ),
+        4
+    );
+    assert!(client
+        .execute(
+            "UPDATE clients SET email = 'Not An Email' WHERE client_id = 1",
+            &[]
+        )
+        .is_err());
+}